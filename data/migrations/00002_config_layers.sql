@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS config_layers
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    cmd_id     TEXT,
+    layer      TEXT NOT NULL, -- merged remote config layer (JSON)
+    state      TEXT NOT NULL, -- pending, applied, rolled_back
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
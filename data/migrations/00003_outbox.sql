@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS outbox
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    body       BLOB NOT NULL, -- encoded AgentMessage not delivered before shutdown
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
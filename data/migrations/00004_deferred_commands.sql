@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS deferred_commands
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    cmd_id     TEXT NOT NULL, -- ControlCmd id, the final result is reported under it
+    cmd        TEXT NOT NULL,
+    payload    BLOB NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,332 @@
+//! Local admin socket and runtime log filter changes.
+//!
+//! The admin subsystem listens on `basic.admin_socket` (a unix socket only
+//! accessible to the agent's user) for one JSON request per line,
+//! `{"cmd": "...", "payload": {...}}`, and answers each with the resulting
+//! `{"status": "...", "message": "..."}`. Requests go through a local
+//! [`Dispatcher`], so the same handlers serve `warden log` and the master's
+//! `ControlCmd`s.
+//!
+//! The socket is only available on unix; elsewhere `log.level` is served to
+//! the master alone.
+//!
+//! `log.level` replaces the log filter with EnvFilter directives, optionally
+//! reverting to the configured `telemetry.log_level` after `ttl_secs`.
+
+use crate::agent::service::{Context, Subsystem};
+use crate::grpc::handler::{CommandHandler, reply};
+use crate::grpc::proto::{CmdResult, ControlCmd};
+use crate::telemetry::logging::{self, FilterState};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+#[cfg(unix)]
+use {
+    crate::grpc::handler::Dispatcher,
+    anyhow::Context as _,
+    std::os::unix::fs::{DirBuilderExt, PermissionsExt},
+    tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    tokio::net::{UnixListener, UnixStream},
+};
+
+pub const LOG_LEVEL_CMD: &str = "log.level";
+
+/// 单个连接最多读取的字节数
+#[cfg(unix)]
+const MAX_CONNECTION_BYTES: u64 = 64 * 1024;
+
+/// admin socket 上的请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminRequest {
+    pub cmd: String,
+    #[serde(default)]
+    pub payload: serde_json::Value,
+}
+
+/// admin socket 上的响应
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminResponse {
+    pub status: String,
+    pub message: String,
+}
+
+/// `log.level` 的 payload；filter 为空且未要求 reset 时只返回当前状态
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LogLevelRequest {
+    #[serde(default)]
+    pub filter: String, // EnvFilter 指令，如 "info,warden::grpc=trace"
+    #[serde(default)]
+    pub ttl_secs: u64, // 到期后恢复配置中的级别，0 表示不自动恢复
+    #[serde(default)]
+    pub reset: bool, // 立即恢复配置中的级别
+}
+
+fn describe(state: &FilterState) -> String {
+    json!({
+        "filter": state.directives,
+        "default": state.default,
+        "expires_at": state.expires_at.map(|t| t.to_rfc3339()),
+    })
+    .to_string()
+}
+
+/// `log.level` 命令：运行时修改日志过滤指令
+pub struct LogLevelHandler;
+
+#[async_trait]
+impl CommandHandler for LogLevelHandler {
+    async fn handle(&self, cmd: &ControlCmd) -> Result<CmdResult> {
+        let req: LogLevelRequest = if cmd.payload.is_empty() {
+            LogLevelRequest::default()
+        } else {
+            match serde_json::from_slice(&cmd.payload) {
+                Ok(req) => req,
+                Err(e) => return Ok(reply("rejected", format!("invalid payload: {e}"))),
+            }
+        };
+        let handle = logging::global().ok_or_else(|| anyhow!("logging is not initialized"))?;
+        let filter = req.filter.trim();
+        let result = if req.reset {
+            handle.reset_filter()
+        } else if filter.is_empty() {
+            return Ok(reply("ok", describe(&handle.filter())));
+        } else {
+            let ttl = (req.ttl_secs > 0).then(|| Duration::from_secs(req.ttl_secs));
+            handle.set_filter(filter, ttl)
+        };
+        match result {
+            Ok(state) => {
+                tracing::info!(
+                    cmd_id = %cmd.id,
+                    filter = %state.directives,
+                    ttl_secs = req.ttl_secs,
+                    "log filter changed"
+                );
+                Ok(reply("applied", describe(&state)))
+            }
+            Err(e) => Ok(reply("rejected", format!("{e:#}"))),
+        }
+    }
+}
+
+/// 本地管理接口子系统，同时向 master 注册 `log.level`
+#[derive(Default)]
+pub struct AdminSubsystem {
+    shutdown: Option<watch::Sender<bool>>,
+    task: Option<JoinHandle<()>>,
+    socket: Option<PathBuf>,
+}
+
+#[async_trait]
+impl Subsystem for AdminSubsystem {
+    fn name(&self) -> &'static str {
+        "admin"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        let handler = Arc::new(LogLevelHandler);
+        ctx.client_mut()?.register(LOG_LEVEL_CMD, handler.clone());
+
+        let path = crate::config::global().basic.admin_socket.clone();
+        if path.is_empty() {
+            return Ok(());
+        }
+        self.listen(PathBuf::from(path), handler)
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send_replace(true);
+        }
+        if let Some(task) = self.task.take() {
+            task.await?;
+        }
+        if let Some(path) = self.socket.take() {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(())
+    }
+}
+
+impl AdminSubsystem {
+    #[cfg(unix)]
+    fn listen(&mut self, path: PathBuf, handler: Arc<LogLevelHandler>) -> Result<()> {
+        let listener = bind(&path)?;
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register(LOG_LEVEL_CMD, handler);
+        let (tx, rx) = watch::channel(false);
+        self.task = Some(tokio::spawn(serve(listener, Arc::new(dispatcher), rx)));
+        self.shutdown = Some(tx);
+        self.socket = Some(path);
+        Ok(())
+    }
+
+    /// 没有 unix socket 时只通过 master 提供 `log.level`
+    #[cfg(not(unix))]
+    fn listen(&mut self, path: PathBuf, _handler: Arc<LogLevelHandler>) -> Result<()> {
+        tracing::warn!(path = %path.display(), "admin socket is only supported on unix, skipping");
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn bind(path: &Path) -> Result<UnixListener> {
+    let dir = match path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+            dir
+        }
+        None => Path::new("."),
+    };
+    // 上次异常退出遗留的 socket 文件
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(anyhow!(
+            "admin socket {} is in use by another agent",
+            path.display()
+        ));
+    }
+    // 先在仅属主可访问（0700）的临时目录中绑定并收紧权限，再移动到配置的位置，
+    // 其他用户在任何时刻都无法连接
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid admin socket path {}", path.display()))?;
+    let private = dir.join(format!(
+        ".{}.{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .with_context(|| format!("failed to create {}", private.display()))?;
+    let staged = private.join(name);
+    let bound = UnixListener::bind(&staged)
+        .with_context(|| format!("failed to bind admin socket {}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("failed to restrict {}", staged.display()))?;
+            std::fs::rename(&staged, path)
+                .with_context(|| format!("failed to move admin socket to {}", path.display()))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&private);
+    let listener = bound?;
+    tracing::info!(path = %path.display(), "admin socket listening");
+    Ok(listener)
+}
+
+#[cfg(unix)]
+async fn serve(
+    listener: UnixListener,
+    dispatcher: Arc<Dispatcher>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, dispatcher.clone()));
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "admin socket accept failed");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            _ = shutdown.changed() => return,
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn handle_connection(stream: UnixStream, dispatcher: Arc<Dispatcher>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader.take(MAX_CONNECTION_BYTES)).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let response = match serde_json::from_str::<AdminRequest>(&line) {
+            Ok(req) => {
+                let cmd = ControlCmd {
+                    id: format!("admin-{}", crate::utils::time::now_millis()),
+                    cmd: req.cmd,
+                    payload: if req.payload.is_null() {
+                        Vec::new()
+                    } else {
+                        serde_json::to_vec(&req.payload).unwrap_or_default()
+                    },
+                };
+                let result = dispatcher.dispatch(cmd).await;
+                AdminResponse {
+                    status: result.status,
+                    message: result.message,
+                }
+            }
+            Err(e) => AdminResponse {
+                status: "rejected".to_string(),
+                message: format!("invalid request: {e}"),
+            },
+        };
+        let mut out = serde_json::to_vec(&response).unwrap_or_default();
+        out.push(b'\n');
+        if writer.write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// 向运行中的 agent 发送一个请求（供 CLI 使用）
+#[cfg(unix)]
+pub fn request(socket: &Path, req: &AdminRequest) -> Result<AdminResponse> {
+    use std::io::{BufRead, Write};
+    let mut stream = std::os::unix::net::UnixStream::connect(socket).with_context(|| {
+        format!(
+            "failed to connect to {}; is the agent running?",
+            socket.display()
+        )
+    })?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut line = serde_json::to_vec(req)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    let mut response = String::new();
+    std::io::BufReader::new(stream)
+        .read_line(&mut response)
+        .context("failed to read admin response")?;
+    serde_json::from_str(&response).context("invalid admin response")
+}
+
+#[cfg(not(unix))]
+pub fn request(_socket: &Path, _req: &AdminRequest) -> Result<AdminResponse> {
+    Err(anyhow!("the admin socket is only supported on unix"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn socket_is_bound_private_and_not_shared() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run").join("admin.sock");
+        let _listener = bind(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // 临时目录已移除，只剩 socket
+        let entries: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["admin.sock"]);
+        std::os::unix::net::UnixStream::connect(&path).unwrap();
+
+        let err = bind(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("in use"), "{err:#}");
+    }
+}
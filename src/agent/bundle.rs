@@ -0,0 +1,496 @@
+//! Signed offline update bundles for sites without master connectivity.
+//!
+//! A bundle is a tar archive with a `manifest.json`, a `manifest.sig` (base64
+//! ed25519 signature over the SHA-256 digest of the manifest, checked against
+//! `update.trusted_keys` like online updates) and the files it lists: an
+//! optional agent binary and any number of plugins under `plugins/`. The
+//! manifest carries the SHA-256 digest of every file.
+//!
+//! Applying a bundle is recorded in the `updates` table. A new agent binary is
+//! installed with the same `<exe>.prev` rollback; plugin-only bundles are
+//! recorded under the `plugins` component. Either way the update stays pending
+//! until the restarted agent proves healthy with the plugins loaded, and the
+//! plugins replaced by the bundle are restored when the install fails or the
+//! update is rolled back. Every archive entry is capped at the size limit of
+//! an agent binary.
+
+use crate::agent::updater::{
+    self, COMPONENT, MAX_BINARY_BYTES, PLUGINS_COMPONENT, STATE_FAILED, STATE_PENDING, VERSION,
+    check_channel, record_step_in,
+};
+use crate::config::schema::UpdateConfig;
+use crate::plugin::installer::{self, PluginFile};
+use crate::storage::Storage;
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+
+#[derive(Debug, Deserialize)]
+pub struct BundleManifest {
+    #[serde(default)]
+    pub agent: Option<BundleAgent>,
+    #[serde(default)]
+    pub plugins: Vec<BundlePlugin>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BundleAgent {
+    pub version: String,
+    pub file: String, // bundle 内的路径
+    pub sha256: String,
+    #[serde(default = "default_channel")]
+    pub channel: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BundlePlugin {
+    pub name: String,
+    pub version: String,
+    pub entry: String, // 相对 plugin_dir 的入口文件，bundle 内位于 plugins/<entry>
+    pub sha256: String,
+}
+
+fn default_channel() -> String {
+    "stable".to_string()
+}
+
+/// 已校验的 bundle
+pub struct Bundle {
+    pub manifest: BundleManifest,
+    pub manifest_sha256: String,
+    files: HashMap<String, Vec<u8>>,
+}
+
+/// 应用结果
+pub enum Applied {
+    /// 新版本已安装，重启后等待健康确认
+    Agent { id: i64, version: String },
+    /// 插件已安装，重启后加载并等待健康确认
+    Plugins { id: i64, count: usize },
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn plugin_path(plugin: &BundlePlugin) -> String {
+    format!("plugins/{}", plugin.entry)
+}
+
+/// 读取 bundle 并校验签名与每个文件的摘要
+pub fn open(path: &Path, trusted_keys: &[String]) -> Result<Bundle> {
+    let file =
+        fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = tar::Archive::new(file);
+    let mut files = HashMap::new();
+    for entry in archive.entries().context("invalid bundle archive")? {
+        let mut entry = entry.context("invalid bundle archive")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        // 头部声明的大小不可信，读取时同样限制
+        if entry.header().size()? > MAX_BINARY_BYTES {
+            return Err(anyhow!("{name} in bundle exceeds {MAX_BINARY_BYTES} bytes"));
+        }
+        let mut data = Vec::new();
+        (&mut entry)
+            .take(MAX_BINARY_BYTES + 1)
+            .read_to_end(&mut data)
+            .with_context(|| format!("failed to read {name} from bundle"))?;
+        if data.len() as u64 > MAX_BINARY_BYTES {
+            return Err(anyhow!("{name} in bundle exceeds {MAX_BINARY_BYTES} bytes"));
+        }
+        files.insert(name, data);
+    }
+
+    let manifest_bytes = files
+        .remove(MANIFEST_FILE)
+        .ok_or_else(|| anyhow!("bundle has no {MANIFEST_FILE}"))?;
+    let signature = files
+        .remove(SIGNATURE_FILE)
+        .ok_or_else(|| anyhow!("bundle has no {SIGNATURE_FILE}"))?;
+    let signature = String::from_utf8(signature).context("bundle signature is not text")?;
+    let manifest_sha256 = sha256_hex(&manifest_bytes);
+    updater::verify_signature(&manifest_bytes, signature.trim(), trusted_keys)
+        .context("bundle signature rejected")?;
+
+    let manifest: BundleManifest =
+        serde_json::from_slice(&manifest_bytes).context("invalid bundle manifest")?;
+    if manifest.agent.is_none() && manifest.plugins.is_empty() {
+        return Err(anyhow!("bundle contains nothing to install"));
+    }
+    let expected = manifest
+        .agent
+        .iter()
+        .map(|a| (a.file.clone(), &a.sha256))
+        .chain(manifest.plugins.iter().map(|p| (plugin_path(p), &p.sha256)));
+    for (name, sha256) in expected {
+        let data = files
+            .get(&name)
+            .ok_or_else(|| anyhow!("bundle is missing {name}"))?;
+        let actual = sha256_hex(data);
+        if !actual.eq_ignore_ascii_case(sha256.trim()) {
+            return Err(anyhow!(
+                "sha256 mismatch for {name}: expected {sha256}, got {actual}"
+            ));
+        }
+    }
+    Ok(Bundle {
+        manifest,
+        manifest_sha256,
+        files,
+    })
+}
+
+impl Bundle {
+    fn file(&self, name: &str) -> &[u8] {
+        // open 已确认清单中的文件都存在
+        self.files.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// 安装 bundle 并记录到 updates 表；需已初始化全局配置与存储
+pub fn apply(bundle: &Bundle, source: &Path) -> Result<Applied> {
+    let cfg = crate::config::global();
+    apply_to(
+        bundle,
+        source,
+        &crate::storage::global(),
+        &cfg.update,
+        Path::new(&cfg.basic.plugin_dir),
+        updater::exe_path()?,
+    )
+}
+
+/// 安装到指定的插件目录与可执行文件
+fn apply_to(
+    bundle: &Bundle,
+    source: &Path,
+    storage: &Storage,
+    update_cfg: &UpdateConfig,
+    plugin_dir: &Path,
+    exe: &Path,
+) -> Result<Applied> {
+    let manifest = &bundle.manifest;
+    if let Some(agent) = &manifest.agent {
+        if agent.version == VERSION {
+            return Err(anyhow!("already running {VERSION}"));
+        }
+        check_channel(update_cfg, &agent.channel, &agent.version)?;
+    }
+    for component in [COMPONENT, PLUGINS_COMPONENT] {
+        if let Some(pending) = storage.pending_update(component)? {
+            return Err(anyhow!(
+                "previous {component} update {} is still waiting for confirmation",
+                pending.id
+            ));
+        }
+    }
+
+    let plugins: Vec<String> = manifest
+        .plugins
+        .iter()
+        .map(|p| format!("{}@{}", p.name, p.version))
+        .collect();
+    let (component, version) = match &manifest.agent {
+        Some(agent) => (COMPONENT, agent.version.clone()),
+        None => (PLUGINS_COMPONENT, plugins.join(",")),
+    };
+    let mut meta = json!({
+        "from": VERSION,
+        "source": "bundle",
+        "bundle": source,
+        "manifest_sha256": bundle.manifest_sha256,
+        "plugins": plugins,
+    });
+    let id = storage.insert_update(component, &version, STATE_PENDING, &meta)?;
+    tracing::info!(update_id = id, component, version = %version, "applying update bundle");
+
+    if let Err(e) = install(bundle, storage, plugin_dir, exe, id, &mut meta) {
+        record_step_in(
+            storage,
+            id,
+            STATE_FAILED,
+            &mut meta,
+            "failed",
+            json!(format!("{e:#}")),
+        );
+        return Err(e);
+    }
+    Ok(match &manifest.agent {
+        Some(agent) => Applied::Agent {
+            id,
+            version: agent.version.clone(),
+        },
+        None => Applied::Plugins {
+            id,
+            count: manifest.plugins.len(),
+        },
+    })
+}
+
+fn install(
+    bundle: &Bundle,
+    storage: &Storage,
+    plugin_dir: &Path,
+    exe: &Path,
+    id: i64,
+    meta: &mut Value,
+) -> Result<()> {
+    let manifest = &bundle.manifest;
+    let mut backup = None;
+    if !manifest.plugins.is_empty() {
+        let files: Vec<PluginFile<'_>> = manifest
+            .plugins
+            .iter()
+            .map(|p| PluginFile {
+                name: p.name.clone(),
+                version: p.version.clone(),
+                entry: p.entry.clone(),
+                data: bundle.file(&plugin_path(p)),
+            })
+            .collect();
+        let installed = installer::install(plugin_dir, &files)?;
+        meta["plugin_backup"] = serde_json::to_value(&installed)?;
+        if manifest.agent.is_none() {
+            // 插件在重启后加载，与新版本 agent 一样等待健康确认
+            meta["boot_attempts"] = json!(0);
+        }
+        record_step_in(
+            storage,
+            id,
+            STATE_PENDING,
+            meta,
+            "plugins_installed",
+            json!({ "dir": plugin_dir }),
+        );
+        backup = Some(installed);
+    }
+
+    let Some(agent) = &manifest.agent else {
+        return Ok(());
+    };
+    let result = updater::stage(bundle.file(&agent.file), exe)
+        .and_then(|staged| updater::install(&staged, exe));
+    let prev = match result {
+        Ok(prev) => prev,
+        Err(e) => {
+            if let Some(backup) = &backup {
+                installer::restore(backup).context("failed to restore plugins")?;
+            }
+            return Err(e);
+        }
+    };
+    meta["boot_attempts"] = json!(0);
+    record_step_in(
+        storage,
+        id,
+        STATE_PENDING,
+        meta,
+        "installed",
+        json!({ "path": exe, "previous": prev }),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    struct Site {
+        _dir: tempfile::TempDir,
+        db: PathBuf,
+        storage: Storage,
+        plugin_dir: PathBuf,
+        exe: PathBuf,
+    }
+
+    fn site() -> Site {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("agent.db");
+        let plugin_dir = dir.path().join("plugins");
+        fs::create_dir(&plugin_dir).unwrap();
+        fs::write(plugin_dir.join("p.wasm"), b"old plugin").unwrap();
+        let manifest = json!({ "plugins": [{ "name": "p", "version": "1", "entry": "p.wasm" }] });
+        fs::write(plugin_dir.join("manifest.json"), manifest.to_string()).unwrap();
+        let exe = dir.path().join("warden");
+        fs::write(&exe, b"v1").unwrap();
+        Site {
+            storage: Storage::open(&db).unwrap(),
+            db,
+            plugin_dir,
+            exe,
+            _dir: dir,
+        }
+    }
+
+    /// 未签名的 bundle，签名与摘要校验由 open 负责
+    fn bundle(agent: bool) -> Bundle {
+        let mut files = HashMap::from([("plugins/p.wasm".to_string(), b"new plugin".to_vec())]);
+        let agent = agent.then(|| {
+            files.insert("warden".to_string(), b"v2".to_vec());
+            BundleAgent {
+                version: "9.9.9".to_string(),
+                file: "warden".to_string(),
+                sha256: sha256_hex(b"v2"),
+                channel: "stable".to_string(),
+            }
+        });
+        Bundle {
+            manifest: BundleManifest {
+                agent,
+                plugins: vec![BundlePlugin {
+                    name: "p".to_string(),
+                    version: "2".to_string(),
+                    entry: "p.wasm".to_string(),
+                    sha256: sha256_hex(b"new plugin"),
+                }],
+            },
+            manifest_sha256: "00".to_string(),
+            files,
+        }
+    }
+
+    fn apply_at(site: &Site, bundle: &Bundle, exe: &Path) -> Result<Applied> {
+        apply_to(
+            bundle,
+            Path::new("/tmp/bundle.tar"),
+            &site.storage,
+            &UpdateConfig::default(),
+            &site.plugin_dir,
+            exe,
+        )
+    }
+
+    fn rows(site: &Site) -> Vec<(String, String, String)> {
+        let conn = rusqlite::Connection::open(&site.db).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT name, version, state FROM updates ORDER BY id")
+            .unwrap();
+        stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn steps(meta: &Value) -> Vec<&str> {
+        meta["steps"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["step"].as_str().unwrap())
+            .collect()
+    }
+
+    fn plugin_version(site: &Site) -> Value {
+        let manifest = fs::read_to_string(site.plugin_dir.join("manifest.json")).unwrap();
+        serde_json::from_str::<Value>(&manifest).unwrap()["plugins"][0]["version"].clone()
+    }
+
+    #[test]
+    fn agent_bundle_installs_binary_and_plugins_pending_confirmation() {
+        let site = site();
+        let applied = apply_at(&site, &bundle(true), &site.exe).unwrap();
+        assert!(matches!(applied, Applied::Agent { ref version, .. } if version == "9.9.9"));
+        assert_eq!(fs::read(&site.exe).unwrap(), b"v2");
+        assert_eq!(
+            fs::read(site.plugin_dir.join("p.wasm")).unwrap(),
+            b"new plugin"
+        );
+        assert_eq!(plugin_version(&site), "2");
+
+        assert_eq!(
+            rows(&site),
+            [(
+                COMPONENT.to_string(),
+                "9.9.9".to_string(),
+                STATE_PENDING.to_string()
+            )]
+        );
+        let record = site.storage.pending_update(COMPONENT).unwrap().unwrap();
+        assert_eq!(record.meta["source"], "bundle");
+        assert_eq!(record.meta["plugins"], json!(["p@2"]));
+        assert_eq!(record.meta["boot_attempts"], 0);
+        assert!(record.meta.get("plugin_backup").is_some());
+        assert_eq!(steps(&record.meta), ["plugins_installed", "installed"]);
+    }
+
+    #[test]
+    fn plugins_are_restored_when_the_agent_install_fails() {
+        let site = site();
+        // 可执行文件所在目录不存在，暂存新二进制失败
+        let exe = site.plugin_dir.join("missing").join("warden");
+        assert!(apply_at(&site, &bundle(true), &exe).is_err());
+        assert_eq!(
+            fs::read(site.plugin_dir.join("p.wasm")).unwrap(),
+            b"old plugin"
+        );
+        assert_eq!(plugin_version(&site), "1");
+        assert_eq!(
+            rows(&site),
+            [(
+                COMPONENT.to_string(),
+                "9.9.9".to_string(),
+                STATE_FAILED.to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn plugin_only_bundle_stays_pending_until_confirmed() {
+        let site = site();
+        let applied = apply_at(&site, &bundle(false), &site.exe).unwrap();
+        assert!(matches!(applied, Applied::Plugins { count: 1, .. }));
+        assert_eq!(fs::read(&site.exe).unwrap(), b"v1");
+        assert_eq!(plugin_version(&site), "2");
+
+        let record = site
+            .storage
+            .pending_update(PLUGINS_COMPONENT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.version, "p@2");
+        assert_eq!(record.meta["boot_attempts"], 0);
+        assert_eq!(steps(&record.meta), ["plugins_installed"]);
+
+        // 未确认前拒绝再次应用
+        let err = apply_at(&site, &bundle(false), &site.exe).err().unwrap();
+        assert!(format!("{err:#}").contains("waiting for confirmation"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unconfirmed_plugin_bundle_is_rolled_back() {
+        let site = site();
+        apply_at(&site, &bundle(false), &site.exe).unwrap();
+        assert!(updater::roll_back_unconfirmed(&site.storage, "crash loop after update").unwrap());
+        assert_eq!(
+            fs::read(site.plugin_dir.join("p.wasm")).unwrap(),
+            b"old plugin"
+        );
+        assert_eq!(plugin_version(&site), "1");
+        assert_eq!(fs::read(&site.exe).unwrap(), b"v1");
+        assert_eq!(
+            rows(&site),
+            [(
+                PLUGINS_COMPONENT.to_string(),
+                "p@2".to_string(),
+                updater::STATE_ROLLED_BACK.to_string()
+            )]
+        );
+    }
+}
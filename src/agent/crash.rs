@@ -0,0 +1,258 @@
+//! Panic hook and crash reporting.
+//!
+//! [`install_panic_hook`] logs a panic as an ERROR event with its message,
+//! location, thread and backtrace, stores it in the `events` table as
+//! `agent.panic` and flushes the log file writers synchronously before the
+//! previous (default) hook runs, so the lines buffered before a crash reach
+//! disk. Panics caught by the tokio runtime are recorded the same way. A panic
+//! on the log writer thread itself is only stored, since logging or flushing
+//! would wait on that thread.
+//!
+//! An orderly shutdown records an `agent.clean_stop` event. On the next start
+//! [`CrashReporter`] sends the last panic recorded after it, if any and not yet
+//! reported, to the master as a `CrashReport` and marks it with an
+//! `agent.crash_reported` event; panics the agent recovered from before a clean
+//! shutdown are not reported. Reporting is best effort and never fails startup.
+
+use crate::agent::service::{Context, Subsystem};
+use crate::agent::updater::VERSION;
+use crate::grpc::client::Outbound;
+use crate::grpc::proto::{CrashReport, agent_message};
+use crate::storage::{EventRecord, Storage};
+use crate::utils::time::now_millis;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+pub const PANIC_EVENT: &str = "agent.panic";
+pub const REPORTED_EVENT: &str = "agent.crash_reported";
+pub const CLEAN_STOP_EVENT: &str = "agent.clean_stop";
+
+thread_local! {
+    /// 正在处理 panic，hook 内部再次 panic 时不再记录
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 安装 panic hook；sqlite_path 用于存储尚未打开或正被占用时单独写入事件
+pub fn install_panic_hook(sqlite_path: &str) {
+    let sqlite_path = PathBuf::from(sqlite_path);
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !IN_HOOK.with(|flag| flag.replace(true)) {
+            record_panic(info, &sqlite_path);
+            IN_HOOK.with(|flag| flag.set(false));
+        }
+        previous(info);
+    }));
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+fn record_panic(info: &PanicHookInfo<'_>, sqlite_path: &Path) {
+    let message = panic_message(info);
+    let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+    let thread = thread::current().name().unwrap_or("<unnamed>").to_string();
+    let backtrace = Backtrace::force_capture().to_string();
+    // 日志写入线程自身 panic 时，经由队列输出或刷盘都会等待这个线程
+    let log_writer = thread == crate::telemetry::logging::WRITER_THREAD;
+    if !log_writer {
+        tracing::error!(
+            panic = %message,
+            location = %location,
+            thread = %thread,
+            backtrace = %backtrace,
+            "agent panicked"
+        );
+    }
+    // 事件随崩溃报告发往 master，与日志同样脱敏
+    let payload = json!({
+        "message": crate::telemetry::redact::global().redact(&message),
+        "location": location,
+        "thread": thread,
+        "backtrace": backtrace,
+        "version": VERSION,
+        "ts": now_millis(),
+    });
+    if let Err(e) = store(&payload, sqlite_path) {
+        // 不能再经由 tracing 报告，日志可能正是出错的地方
+        eprintln!("[crash] failed to record panic: {e:#}");
+    }
+    // 后台线程中排队的日志在进程退出前落盘
+    if !log_writer {
+        crate::telemetry::logging::flush_global();
+    }
+}
+
+fn store(payload: &Value, sqlite_path: &Path) -> Result<()> {
+    if let Some(storage) = crate::storage::try_global()
+        && storage.try_record_event(PANIC_EVENT, payload).is_ok()
+    {
+        return Ok(());
+    }
+    // 存储尚未打开，或 panic 时本线程正持有连接
+    Storage::open(sqlite_path)?.record_event(PANIC_EVENT, payload)
+}
+
+/// 记录本次运行正常退出，此前已恢复的 panic 不再视为崩溃
+pub fn record_clean_stop() {
+    let Some(storage) = crate::storage::try_global() else {
+        return;
+    };
+    if let Err(e) = storage.record_event(CLEAN_STOP_EVENT, &json!({ "ts": now_millis() })) {
+        tracing::warn!(error = %format!("{e:#}"), "failed to record clean stop");
+    }
+}
+
+/// 最近一次尚未上报、且之后没有正常退出的 panic
+pub fn unreported_crash(storage: &Storage) -> Result<Option<EventRecord>> {
+    let Some(crash) = storage.last_event(PANIC_EVENT)? else {
+        return Ok(None);
+    };
+    let reported = storage
+        .last_event(REPORTED_EVENT)?
+        .and_then(|e| e.payload["event_id"].as_i64())
+        .unwrap_or(0);
+    let clean_stop = storage.last_event(CLEAN_STOP_EVENT)?.map_or(0, |e| e.id);
+    Ok((crash.id > reported && crash.id > clean_stop).then_some(crash))
+}
+
+/// 把上次运行的 panic 放入出站队列并标记为已上报；队列满时留到下次启动
+fn report_crash(storage: &Storage, outbound: &Outbound) -> Result<()> {
+    let Some(crash) = unreported_crash(storage)? else {
+        return Ok(());
+    };
+    let text = |key: &str| crash.payload[key].as_str().unwrap_or_default().to_string();
+    let report = CrashReport {
+        id: crate::agent::identity::agent_id(),
+        ts: now_millis(),
+        crashed_at: crash.payload["ts"].as_i64().unwrap_or_default(),
+        message: text("message"),
+        location: text("location"),
+        thread: text("thread"),
+        backtrace: text("backtrace"),
+        version: text("version"),
+    };
+    tracing::warn!(
+        crashed_at = %crash.created_at,
+        panic = %report.message,
+        location = %report.location,
+        "previous run panicked, reporting to master"
+    );
+    // 连接建立后发出；退出前仍未发出时随出站队列持久化
+    outbound.try_send(agent_message::Body::Crash(report))?;
+    storage.record_event(REPORTED_EVENT, &json!({ "event_id": crash.id }))
+}
+
+/// 启动时把上次运行的 panic 上报给 master
+pub struct CrashReporter;
+
+#[async_trait]
+impl Subsystem for CrashReporter {
+    fn name(&self) -> &'static str {
+        "crash_report"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        // 上报失败不影响启动
+        let reported = crate::storage::try_global()
+            .ok_or_else(|| anyhow!("storage is not open"))
+            .and_then(|storage| report_crash(&storage, &ctx.outbound));
+        if let Err(e) = reported {
+            tracing::warn!(error = %format!("{e:#}"), "failed to report previous crash");
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open() -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+        (dir, storage)
+    }
+
+    fn record_panic_event(storage: &Storage, message: &str) -> i64 {
+        storage
+            .record_event(PANIC_EVENT, &json!({ "message": message, "ts": 1 }))
+            .unwrap();
+        storage.last_event(PANIC_EVENT).unwrap().unwrap().id
+    }
+
+    #[test]
+    fn unreported_crash_is_the_last_panic_not_yet_reported() {
+        let (_dir, storage) = open();
+        assert!(unreported_crash(&storage).unwrap().is_none());
+
+        record_panic_event(&storage, "first");
+        let second = record_panic_event(&storage, "second");
+        let crash = unreported_crash(&storage).unwrap().unwrap();
+        assert_eq!(
+            (crash.id, crash.payload["message"].as_str()),
+            (second, Some("second"))
+        );
+
+        storage
+            .record_event(REPORTED_EVENT, &json!({ "event_id": second }))
+            .unwrap();
+        assert!(unreported_crash(&storage).unwrap().is_none());
+
+        let third = record_panic_event(&storage, "third");
+        assert_eq!(unreported_crash(&storage).unwrap().unwrap().id, third);
+    }
+
+    #[test]
+    fn panics_before_a_clean_stop_are_not_crashes() {
+        let (_dir, storage) = open();
+        record_panic_event(&storage, "recovered");
+        storage
+            .record_event(CLEAN_STOP_EVENT, &json!({ "ts": 2 }))
+            .unwrap();
+        assert!(unreported_crash(&storage).unwrap().is_none());
+
+        let crash = record_panic_event(&storage, "fatal");
+        assert_eq!(unreported_crash(&storage).unwrap().unwrap().id, crash);
+    }
+
+    #[test]
+    fn report_is_marked_only_once_queued() {
+        let (_dir, storage) = open();
+        let crash = record_panic_event(&storage, "boom");
+
+        let (outbound, rx) = crate::grpc::client::outbound_channel();
+        drop(rx);
+        assert!(report_crash(&storage, &outbound).is_err());
+        assert_eq!(unreported_crash(&storage).unwrap().unwrap().id, crash);
+
+        let (outbound, mut rx) = crate::grpc::client::outbound_channel();
+        report_crash(&storage, &outbound).unwrap();
+        match rx.try_recv().unwrap().body {
+            Some(agent_message::Body::Crash(report)) => assert_eq!(report.message, "boom"),
+            other => panic!("unexpected message {other:?}"),
+        }
+        assert!(unreported_crash(&storage).unwrap().is_none());
+        // 已上报的崩溃不再重复发送
+        report_crash(&storage, &outbound).unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! Stable agent identity reported to the master.
+//!
+//! The id is derived from the host machine-id and the DMI system UUID
+//! (UUIDv5, identical after a reinstall on the same machine) or generated
+//! randomly, and persisted in `identity.id_file` together with digests of
+//! both. Golden-image clones share the machine-id but not the system UUID, so
+//! they derive distinct ids on first start. When the system UUID no longer
+//! matches a persisted identity, the disk was cloned onto another machine
+//! after the agent ran: the id is regenerated, or the agent is flagged with
+//! the `agent.clone_suspected` label when regeneration is disabled.
+
+use crate::agent::service::{Context, Subsystem};
+use crate::agent::state::{self, AgentState};
+use crate::config::schema::Config;
+use crate::storage::Storage;
+use crate::utils::host;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// machine-id 派生 id 时使用的 UUIDv5 命名空间
+const NAMESPACE: Uuid = Uuid::from_u128(0x6f1c_2a4e_93b7_4d52_8e0a_5c3f_d7b1_a946);
+
+/// 未配置 id_file 时的文件名（位于 sqlite 所在目录）
+const ID_FILE_NAME: &str = "agent_id.json";
+
+pub const SOURCE_MACHINE_ID: &str = "machine_id";
+pub const SOURCE_GENERATED: &str = "generated";
+
+/// 持久化的 agent 身份
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    pub id: String,
+    pub source: String, // machine_id / generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine_id: Option<String>, // machine-id 摘要，用于发现主机重新置备
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>, // DMI 系统 UUID 摘要，用于发现克隆
+    #[serde(skip)]
+    pub clone_suspected: bool,
+}
+
+/// 持久化身份与当前主机的比对结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum Check {
+    Ok,
+    /// machine-id 变化，主机被重新置备
+    Reprovisioned,
+    /// 系统 UUID 变化而 machine-id 未变，磁盘被克隆到了另一台机器
+    Cloned,
+}
+
+static IDENTITY: OnceCell<Identity> = OnceCell::new();
+
+/// 心跳与上报中使用的 agent 标识，身份建立前为空
+pub fn agent_id() -> String {
+    IDENTITY.get().map(|i| i.id.clone()).unwrap_or_default()
+}
+
+/// 当前身份是否疑似来自克隆镜像（未重新生成 id 时）
+pub fn clone_suspected() -> bool {
+    IDENTITY.get().is_some_and(|i| i.clone_suspected)
+}
+
+fn digest(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// 派生与比对身份所用的主机标识
+#[derive(Debug, Clone, Default)]
+struct HostIds {
+    machine_id: Option<String>,
+    product_uuid: Option<String>, // DMI 系统 UUID，无权限读取时为空
+}
+
+impl HostIds {
+    fn current() -> Self {
+        Self {
+            machine_id: host::facts().machine_id.clone(),
+            product_uuid: host::product_uuid(),
+        }
+    }
+
+    fn machine_id_digest(&self) -> Option<String> {
+        self.machine_id.as_deref().map(digest)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.product_uuid.as_deref().map(digest)
+    }
+
+    /// 由 machine-id 与系统 UUID 派生 id：同一台机器重装后不变，
+    /// 而由同一镜像克隆出的机器 machine-id 相同、系统 UUID 不同
+    fn derived_id(&self) -> Option<String> {
+        let machine_id = self.machine_id.as_deref()?;
+        let name = match &self.product_uuid {
+            Some(product_uuid) => format!("{machine_id}/{product_uuid}"),
+            None => machine_id.to_string(),
+        };
+        Some(Uuid::new_v5(&NAMESPACE, name.as_bytes()).to_string())
+    }
+}
+
+/// 身份文件路径
+pub fn id_path(cfg: &Config) -> PathBuf {
+    if !cfg.identity.id_file.is_empty() {
+        return PathBuf::from(&cfg.identity.id_file);
+    }
+    Path::new(&cfg.basic.sqlite_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(ID_FILE_NAME)
+}
+
+/// 按配置的来源创建新身份
+pub fn create(source: &str) -> Result<Identity> {
+    create_on(source, &HostIds::current())
+}
+
+fn create_on(source: &str, host: &HostIds) -> Result<Identity> {
+    let (id, source) = match (source, host.derived_id()) {
+        ("auto" | SOURCE_MACHINE_ID, Some(id)) => (id, SOURCE_MACHINE_ID),
+        (SOURCE_MACHINE_ID, None) => return Err(anyhow!("machine-id not available on this host")),
+        _ => (Uuid::new_v4().to_string(), SOURCE_GENERATED),
+    };
+    Ok(Identity {
+        id,
+        source: source.to_string(),
+        machine_id: host.machine_id_digest(),
+        fingerprint: host.fingerprint(),
+        clone_suspected: false,
+    })
+}
+
+/// 与当前主机比对持久化身份
+pub fn check(stored: &Identity) -> Check {
+    check_on(stored, &HostIds::current())
+}
+
+fn check_on(stored: &Identity, host: &HostIds) -> Check {
+    let machine_id = host.machine_id_digest();
+    if stored.machine_id.is_some() && machine_id.is_some() && stored.machine_id != machine_id {
+        return Check::Reprovisioned;
+    }
+    match (&stored.fingerprint, host.fingerprint()) {
+        (Some(stored), Some(current)) if *stored != current => Check::Cloned,
+        _ => Check::Ok,
+    }
+}
+
+pub fn read(path: &Path) -> Result<Option<Identity>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(Some(serde_json::from_str(&s).with_context(|| {
+            format!("corrupt identity file {}", path.display())
+        })?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// 先写临时文件再 rename，避免写到一半留下损坏的身份
+pub fn write(path: &Path, identity: &Identity) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(identity)?)
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// 读取或建立身份：处理重新置备与克隆，并把变化记录到 events 表
+pub fn establish(cfg: &Config, storage: Option<&Storage>) -> Result<Identity> {
+    establish_on(cfg, storage, &HostIds::current())
+}
+
+fn establish_on(cfg: &Config, storage: Option<&Storage>, host: &HostIds) -> Result<Identity> {
+    let record = |kind: &str, payload: serde_json::Value| {
+        if let Some(storage) = storage
+            && let Err(e) = storage.record_event(kind, &payload)
+        {
+            tracing::warn!(error = %format!("{e:#}"), "failed to record identity event");
+        }
+    };
+    let path = id_path(cfg);
+    let Some(mut identity) = read(&path)? else {
+        let identity = create_on(&cfg.identity.source, host)?;
+        write(&path, &identity)?;
+        tracing::info!(agent_id = %identity.id, source = %identity.source, "agent identity created");
+        record(
+            "identity.created",
+            json!({ "id": identity.id, "source": identity.source }),
+        );
+        return Ok(identity);
+    };
+
+    match check_on(&identity, host) {
+        Check::Ok => {
+            // 创建时无权限读取系统 UUID 的，之后补全指纹
+            let fingerprint = host.fingerprint();
+            if identity.fingerprint.is_none() && fingerprint.is_some() {
+                identity.fingerprint = fingerprint;
+                write(&path, &identity)?;
+            }
+        }
+        Check::Reprovisioned => {
+            let previous = identity.id.clone();
+            identity = create_on(&cfg.identity.source, host)?;
+            write(&path, &identity)?;
+            tracing::warn!(previous = %previous, agent_id = %identity.id, "machine-id changed, agent identity re-derived");
+            record(
+                "identity.reprovisioned",
+                json!({ "previous": previous, "id": identity.id }),
+            );
+        }
+        Check::Cloned if cfg.identity.regenerate_on_clone => {
+            // 派生 id 含系统 UUID，克隆机重新派生即与原机不同
+            let previous = identity.id.clone();
+            identity = create_on(&cfg.identity.source, host)?;
+            write(&path, &identity)?;
+            tracing::warn!(previous = %previous, agent_id = %identity.id, "cloned image detected, agent identity regenerated");
+            record(
+                "identity.clone_detected",
+                json!({ "previous": previous, "id": identity.id, "regenerated": true }),
+            );
+        }
+        Check::Cloned => {
+            identity.clone_suspected = true;
+            tracing::warn!(agent_id = %identity.id, "cloned image detected, agent id is likely duplicated; run `warden id reset`");
+            record(
+                "identity.clone_detected",
+                json!({ "id": identity.id, "regenerated": false }),
+            );
+        }
+    }
+    Ok(identity)
+}
+
+/// 身份子系统：在连接 master 之前建立 agent 身份
+pub struct IdentitySubsystem;
+
+#[async_trait]
+impl Subsystem for IdentitySubsystem {
+    fn name(&self) -> &'static str {
+        "identity"
+    }
+
+    async fn start(&mut self, _ctx: &mut Context) -> Result<()> {
+        state::transition(AgentState::Enrolling, "establishing agent identity")?;
+        let cfg = crate::config::global();
+        let identity = establish(&cfg, crate::storage::try_global().as_deref())?;
+        tracing::info!(agent_id = %identity.id, source = %identity.source, "agent identity established");
+        IDENTITY
+            .set(identity)
+            .map_err(|_| anyhow!("agent identity already established"))
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(machine_id: &str, product_uuid: Option<&str>) -> HostIds {
+        HostIds {
+            machine_id: Some(machine_id.to_string()),
+            product_uuid: product_uuid.map(str::to_string),
+        }
+    }
+
+    fn config(dir: &Path, regenerate_on_clone: bool) -> Config {
+        let mut cfg = Config::default();
+        cfg.identity.source = "auto".to_string();
+        cfg.identity.id_file = dir.join(ID_FILE_NAME).to_string_lossy().into_owned();
+        cfg.identity.regenerate_on_clone = regenerate_on_clone;
+        cfg
+    }
+
+    #[test]
+    fn derived_id_is_stable_and_differs_between_clones() {
+        let original = create_on("auto", &host("golden", Some("uuid-a"))).unwrap();
+        let again = create_on("auto", &host("golden", Some("uuid-a"))).unwrap();
+        let clone = create_on("auto", &host("golden", Some("uuid-b"))).unwrap();
+        assert_eq!(original.source, SOURCE_MACHINE_ID);
+        assert_eq!(original.id, again.id);
+        assert_ne!(original.id, clone.id);
+
+        let generated = create_on("auto", &HostIds::default()).unwrap();
+        assert_eq!(generated.source, SOURCE_GENERATED);
+        assert!(create_on(SOURCE_MACHINE_ID, &HostIds::default()).is_err());
+    }
+
+    #[test]
+    fn check_compares_machine_id_then_fingerprint() {
+        let stored = create_on("auto", &host("m1", Some("uuid-a"))).unwrap();
+        assert_eq!(check_on(&stored, &host("m1", Some("uuid-a"))), Check::Ok);
+        assert_eq!(
+            check_on(&stored, &host("m2", Some("uuid-a"))),
+            Check::Reprovisioned
+        );
+        assert_eq!(
+            check_on(&stored, &host("m1", Some("uuid-b"))),
+            Check::Cloned
+        );
+        // 读取不到系统 UUID 时无法判断
+        assert_eq!(check_on(&stored, &host("m1", None)), Check::Ok);
+    }
+
+    #[test]
+    fn establish_persists_and_backfills_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = config(dir.path(), true);
+        let created = establish_on(&cfg, None, &host("m1", None)).unwrap();
+        assert!(created.fingerprint.is_none());
+
+        let reread = establish_on(&cfg, None, &host("m1", Some("uuid-a"))).unwrap();
+        assert_eq!(reread.id, created.id);
+        let stored = read(&id_path(&cfg)).unwrap().unwrap();
+        assert_eq!(stored.fingerprint, Some(digest("uuid-a")));
+    }
+
+    #[test]
+    fn establish_rederives_after_reprovisioning() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = config(dir.path(), true);
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+        let original = establish_on(&cfg, Some(&storage), &host("m1", Some("uuid-a"))).unwrap();
+        let fresh = establish_on(&cfg, Some(&storage), &host("m2", Some("uuid-a"))).unwrap();
+        assert_ne!(fresh.id, original.id);
+        let event = storage
+            .last_event("identity.reprovisioned")
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.payload["previous"], original.id.as_str());
+    }
+
+    #[test]
+    fn establish_regenerates_or_flags_clones() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = config(dir.path(), true);
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+        let original = establish_on(&cfg, Some(&storage), &host("golden", Some("uuid-a"))).unwrap();
+        let clone = establish_on(&cfg, Some(&storage), &host("golden", Some("uuid-b"))).unwrap();
+        assert_ne!(clone.id, original.id);
+        assert!(!clone.clone_suspected);
+        let event = storage
+            .last_event("identity.clone_detected")
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.payload["regenerated"], true);
+        // 重新生成后身份与克隆机一致，下次启动不再报告
+        let again = establish_on(&cfg, Some(&storage), &host("golden", Some("uuid-b"))).unwrap();
+        assert_eq!(again.id, clone.id);
+
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = config(dir.path(), false);
+        let original = establish_on(&cfg, None, &host("golden", Some("uuid-a"))).unwrap();
+        let flagged = establish_on(&cfg, None, &host("golden", Some("uuid-b"))).unwrap();
+        assert_eq!(flagged.id, original.id);
+        assert!(flagged.clone_suspected);
+    }
+}
@@ -0,0 +1,175 @@
+//! Agent labels: `basic.labels` from config merged over labels derived from host facts.
+//!
+//! Labels are recomputed from the current global config, so runtime updates go
+//! through the persisted remote config layer (`labels.update` command).
+//!
+//! Labels are attached to heartbeats and collect batches, and handed to
+//! plugins as `<plugin_dir>/labels.json`, which the plugin host rewrites
+//! whenever they change.
+
+use crate::config::remote::RemoteConfig;
+use crate::grpc::handler::{CommandHandler, reply};
+use crate::grpc::proto::{CmdResult, ControlCmd};
+use crate::utils::host::{self, HostFacts};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// 当前生效的标签：主机派生标签（host.*）在前，配置中的同名标签覆盖之
+pub fn current() -> BTreeMap<String, String> {
+    merge(
+        host::facts(),
+        &crate::config::global().basic.labels,
+        crate::agent::identity::clone_suspected(),
+    )
+}
+
+fn merge(
+    facts: &HostFacts,
+    configured: &BTreeMap<String, String>,
+    clone_suspected: bool,
+) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    labels.insert("host.os".to_string(), facts.os.clone());
+    labels.insert("host.arch".to_string(), facts.arch.clone());
+    if let Some(hostname) = &facts.hostname {
+        labels.insert("host.hostname".to_string(), hostname.clone());
+    }
+    if let Some(ip) = &facts.ip {
+        labels.insert("host.ip".to_string(), ip.clone());
+    }
+    for (key, value) in configured {
+        // 空值表示删除（包括覆盖文件中或派生的同名标签）
+        if value.is_empty() {
+            labels.remove(key);
+        } else {
+            labels.insert(key.clone(), value.clone());
+        }
+    }
+    // 克隆镜像且未重新生成 id 时提示 master
+    if clone_suspected {
+        labels.insert("agent.clone_suspected".to_string(), "true".to_string());
+    }
+    labels
+}
+
+/// 以 proto map 形式返回标签，用于 Heartbeat / CollectData
+pub fn current_proto() -> HashMap<String, String> {
+    current().into_iter().collect()
+}
+
+/// `labels.update` 命令：payload 为标签的 JSON 对象，值为 null 或空字符串时删除该标签
+pub struct LabelsUpdateHandler {
+    pub remote: Arc<RemoteConfig>,
+}
+
+/// 将 `labels.update` 的 payload 转为远程配置补丁，拒绝时返回原因
+fn labels_patch(payload: &[u8]) -> std::result::Result<Value, &'static str> {
+    let mut labels = match serde_json::from_slice(payload) {
+        Ok(Value::Object(map)) => map,
+        _ => return Err("labels must be a JSON object"),
+    };
+    // 与 config.patch 一致：远程下发的值不允许包含引用
+    if labels
+        .values()
+        .any(|v| v.as_str().is_some_and(|s| s.contains("${")))
+    {
+        return Err("label values must not contain ${...} references");
+    }
+    // null 会从远程配置层中移除键，但无法删除文件中定义的标签，统一转为空值
+    for value in labels.values_mut() {
+        if value.is_null() {
+            *value = Value::String(String::new());
+        }
+    }
+    Ok(json!({ "basic": { "labels": labels } }))
+}
+
+#[async_trait]
+impl CommandHandler for LabelsUpdateHandler {
+    async fn handle(&self, cmd: &ControlCmd) -> Result<CmdResult> {
+        let patch = match labels_patch(&cmd.payload) {
+            Ok(patch) => patch,
+            Err(reason) => return Ok(reply("rejected", reason)),
+        };
+        match self.remote.apply(&cmd.id, &patch).await {
+            Ok(_) => Ok(reply("applied", "")),
+            Err(e) => Ok(reply("rejected", format!("{e:#}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> HostFacts {
+        HostFacts {
+            hostname: Some("web-1".to_string()),
+            ip: None,
+            machine_id: None,
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+        }
+    }
+
+    fn configured(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn configured_labels_override_derived_ones() {
+        let labels = merge(
+            &facts(),
+            &configured(&[("env", "prod"), ("host.hostname", "web-1.example")]),
+            false,
+        );
+        assert_eq!(
+            labels,
+            configured(&[
+                ("env", "prod"),
+                ("host.arch", "x86_64"),
+                ("host.hostname", "web-1.example"),
+                ("host.os", "linux"),
+            ])
+        );
+    }
+
+    #[test]
+    fn empty_value_deletes_a_label() {
+        let labels = merge(
+            &facts(),
+            &configured(&[("host.arch", ""), ("role", "")]),
+            true,
+        );
+        assert_eq!(
+            labels,
+            configured(&[
+                ("agent.clone_suspected", "true"),
+                ("host.hostname", "web-1"),
+                ("host.os", "linux"),
+            ])
+        );
+    }
+
+    #[test]
+    fn update_rejects_references_and_non_objects() {
+        assert!(labels_patch(br#"{"env": "${env:HOME}"}"#).is_err());
+        assert!(labels_patch(br#"["env"]"#).is_err());
+        assert!(labels_patch(b"not json").is_err());
+    }
+
+    #[test]
+    fn update_turns_null_into_an_empty_value() {
+        let patch = labels_patch(br#"{"env": "prod", "owner": null}"#).unwrap();
+        assert_eq!(
+            patch,
+            json!({ "basic": { "labels": { "env": "prod", "owner": "" } } })
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Maintenance windows for disruptive commands.
+//!
+//! Windows are cron schedules (start times) with a duration, evaluated in the
+//! configured timezone. Schedules use crontab syntax, optionally with a
+//! leading seconds field; day-of-week numbers are crontab's (0 and 7 are
+//! Sunday) and are translated to the `cron` crate's numbering (Sunday = 1). Handlers of disruptive commands (agent updates) call
+//! [`defer_outside_window`] after validating a command: outside every window
+//! the command is persisted in `deferred_commands` and answered with
+//! "deferred". [`spawn_runner`] re-dispatches queued commands once the next
+//! window opens and reports their final result under the original command id.
+//! Without any configured window nothing is deferred.
+
+use crate::config::schema::{MaintenanceConfig, MaintenanceWindow};
+use crate::grpc::client::Outbound;
+use crate::grpc::handler::{Dispatcher, reply};
+use crate::grpc::proto::{CmdResult, ControlCmd, agent_message};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// 两次检查之间的最长等待，用于应对时钟跳变
+const MAX_WAIT: Duration = Duration::from_secs(3600);
+
+/// 解析 cron 表达式，五段式（分 时 日 月 周）补齐秒字段；星期按 crontab 编号
+fn parse_schedule(expr: &str) -> Result<Schedule> {
+    let expr = expr.trim();
+    let invalid = |e: &dyn std::fmt::Display| anyhow!("invalid maintenance schedule {expr:?}: {e}");
+    let mut fields: Vec<String> = expr.split_whitespace().map(str::to_string).collect();
+    if fields.len() == 5 {
+        fields.insert(0, "0".to_string());
+    }
+    if let Some(weekdays) = fields.get_mut(5) {
+        *weekdays = crontab_weekdays(weekdays).map_err(|e| invalid(&e))?;
+    }
+    Schedule::from_str(&fields.join(" ")).map_err(|e| invalid(&e))
+}
+
+/// 把 crontab 的星期编号（0-7，0 与 7 为周日）换成 cron crate 的编号（1-7，周日为 1）。
+/// 数字范围与步长展开为列表，名称（如 Mon-Fri）原样保留
+fn crontab_weekdays(field: &str) -> Result<String> {
+    let mut days = std::collections::BTreeSet::new();
+    let mut named = Vec::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        let bounds = match range {
+            "*" | "?" if step.is_none() => {
+                named.push(item.to_string());
+                continue;
+            }
+            "*" => Some((0, 6)),
+            _ => match range.split_once('-') {
+                Some((from, to)) => from.parse::<u32>().ok().zip(to.parse::<u32>().ok()),
+                None => range.parse::<u32>().ok().map(|day| match step {
+                    Some(_) => (day, 6),
+                    None => (day, day),
+                }),
+            },
+        };
+        let Some((from, to)) = bounds else {
+            named.push(item.to_string());
+            continue;
+        };
+        let step = match step {
+            Some(step) => step
+                .parse::<usize>()
+                .ok()
+                .filter(|s| *s > 0)
+                .ok_or_else(|| anyhow!("invalid day-of-week step {step:?}"))?,
+            None => 1,
+        };
+        if to > 7 || from > to {
+            return Err(anyhow!("day-of-week {range:?} out of range 0-7"));
+        }
+        days.extend((from..=to).step_by(step).map(|day| day % 7 + 1));
+    }
+    Ok(days
+        .iter()
+        .map(u32::to_string)
+        .chain(named)
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>()
+        .map_err(|_| anyhow!("invalid maintenance timezone: {name}"))
+}
+
+pub fn validate(cfg: &MaintenanceConfig) -> Result<()> {
+    parse_timezone(&cfg.timezone)?;
+    for window in &cfg.windows {
+        parse_schedule(&window.schedule)?;
+        if window.duration_mins == 0 {
+            return Err(anyhow!("maintenance window duration_mins must be > 0"));
+        }
+    }
+    Ok(())
+}
+
+/// 单个窗口在 now 之后（含正在进行中）的开放区间 [start, end)
+fn window_at(
+    window: &MaintenanceWindow,
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let schedule = parse_schedule(&window.schedule).ok()?;
+    let duration = ChronoDuration::minutes(window.duration_mins as i64);
+    // 从 now - duration 之后的第一个开始时间起算，覆盖已开始但尚未结束的窗口
+    let since = (now - duration).with_timezone(&tz);
+    schedule
+        .after(&since)
+        .map(|start| start.with_timezone(&Utc))
+        .map(|start| (start, start + duration))
+        .find(|(_, end)| *end > now)
+}
+
+/// 下一个开放时间；当前处于窗口内时返回 now，没有配置窗口时返回 None
+pub fn next_open(cfg: &MaintenanceConfig, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let tz = parse_timezone(&cfg.timezone).ok()?;
+    cfg.windows
+        .iter()
+        .filter_map(|w| window_at(w, tz, now))
+        .map(|(start, _)| start.max(now))
+        .min()
+}
+
+/// now 之后最近的窗口开始时间
+fn next_start(cfg: &MaintenanceConfig, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let tz = parse_timezone(&cfg.timezone).ok()?;
+    cfg.windows
+        .iter()
+        .filter_map(|w| {
+            let schedule = parse_schedule(&w.schedule).ok()?;
+            schedule.after(&now.with_timezone(&tz)).next()
+        })
+        .map(|start| start.with_timezone(&Utc))
+        .min()
+}
+
+/// 当前是否允许执行中断性操作
+pub fn is_open(cfg: &MaintenanceConfig, now: DateTime<Utc>) -> bool {
+    cfg.windows.is_empty() || next_open(cfg, now) == Some(now)
+}
+
+/// 窗口外将命令入队并返回 deferred 结果；窗口内返回 None，由调用方继续执行
+pub fn defer_outside_window(cmd: &ControlCmd) -> Result<Option<CmdResult>> {
+    let cfg = crate::config::global();
+    let now = Utc::now();
+    if is_open(&cfg.maintenance, now) {
+        return Ok(None);
+    }
+    crate::storage::global().defer_command(&cmd.id, &cmd.cmd, &cmd.payload)?;
+    let next = next_open(&cfg.maintenance, now)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| "never".to_string());
+    tracing::info!(cmd_id = %cmd.id, cmd = %cmd.cmd, next_window = %next, "command deferred until maintenance window");
+    Ok(Some(reply(
+        "deferred",
+        format!("outside maintenance window, queued until {next}"),
+    )))
+}
+
+/// 在窗口开放时执行排队的命令，结果以原命令 id 上报
+pub fn spawn_runner(
+    dispatcher: Arc<Dispatcher>,
+    outbound: Outbound,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    tokio::spawn(async move {
+        let mut cfg_rx = crate::config::subscribe();
+        loop {
+            let cfg = cfg_rx.borrow_and_update().clone();
+            let now = Utc::now();
+            if is_open(&cfg.maintenance, now) {
+                run_queued(&dispatcher, &outbound).await;
+            }
+            // 窗口内不会再有命令入队，等到下一个窗口开始即可
+            let wait = next_start(&cfg.maintenance, now)
+                .and_then(|t| (t - now).to_std().ok())
+                .unwrap_or(MAX_WAIT)
+                .min(MAX_WAIT);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = cfg_rx.changed() => {}
+                _ = shutdown.changed() => return,
+            }
+        }
+    });
+}
+
+async fn run_queued(dispatcher: &Dispatcher, outbound: &Outbound) {
+    let storage = crate::storage::global();
+    let queued = match storage.deferred_commands() {
+        Ok(queued) => queued,
+        Err(e) => {
+            tracing::warn!(error = %format!("{e:#}"), "failed to read deferred commands");
+            return;
+        }
+    };
+    for deferred in queued {
+        // 先出队再执行：窗口恰好关闭时命令会被重新入队
+        if let Err(e) = storage.delete_deferred(deferred.id) {
+            tracing::warn!(error = %format!("{e:#}"), "failed to dequeue deferred command");
+            return;
+        }
+        tracing::info!(cmd_id = %deferred.cmd_id, cmd = %deferred.cmd, "running deferred command");
+        let cmd = ControlCmd {
+            id: deferred.cmd_id,
+            cmd: deferred.cmd,
+            payload: deferred.payload,
+        };
+        let result = dispatcher.dispatch(cmd).await;
+        if outbound
+            .send(agent_message::Body::Result(result))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone, Weekday};
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    fn config(timezone: &str, windows: &[(&str, u64)]) -> MaintenanceConfig {
+        MaintenanceConfig {
+            timezone: timezone.to_string(),
+            windows: windows
+                .iter()
+                .map(|(schedule, duration_mins)| MaintenanceWindow {
+                    schedule: schedule.to_string(),
+                    duration_mins: *duration_mins,
+                })
+                .collect(),
+        }
+    }
+
+    /// 一周内各次开始时间落在星期几
+    fn weekdays(expr: &str) -> Vec<Weekday> {
+        let schedule = parse_schedule(expr).unwrap();
+        // 2026-10-18 为周日
+        schedule
+            .after(&at(2026, 10, 18, 0, 0))
+            .take_while(|t| *t < at(2026, 10, 25, 0, 0))
+            .map(|t| t.weekday())
+            .collect()
+    }
+
+    #[test]
+    fn day_of_week_uses_crontab_numbering() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 2 * * 0"), [Sun]);
+        assert_eq!(weekdays("0 2 * * 7"), [Sun]);
+        assert_eq!(weekdays("0 2 * * 1-5"), [Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 2 * * 5-7"), [Sun, Fri, Sat]);
+        assert_eq!(weekdays("0 2 * * */2"), [Sun, Tue, Thu, Sat]);
+        assert_eq!(weekdays("0 2 * * 1,3"), [Mon, Wed]);
+        assert_eq!(weekdays("0 2 * * Sat"), [Sat]);
+        // 带秒字段时同样按 crontab 编号
+        assert_eq!(weekdays("30 0 2 * * 6"), [Sat]);
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        for expr in [
+            "0 2 * * 8",
+            "0 2 * * 5-1",
+            "0 2 * * 1/0",
+            "0 25 * * *",
+            "daily",
+        ] {
+            assert!(parse_schedule(expr).is_err(), "{expr}");
+        }
+        assert!(validate(&config("UTC", &[("0 2 * * 6", 0)])).is_err());
+        assert!(validate(&config("Mars/Olympus", &[])).is_err());
+        assert!(validate(&config("Asia/Shanghai", &[("0 2 * * 6", 120)])).is_ok());
+    }
+
+    #[test]
+    fn windows_open_for_their_duration() {
+        let cfg = config("UTC", &[("0 2 * * 6", 120)]);
+        let saturday = at(2026, 10, 24, 2, 0);
+        assert!(!is_open(&cfg, at(2026, 10, 24, 1, 59)));
+        assert_eq!(next_open(&cfg, at(2026, 10, 24, 1, 59)), Some(saturday));
+        assert!(is_open(&cfg, saturday));
+        assert!(is_open(&cfg, at(2026, 10, 24, 3, 59)));
+        assert!(!is_open(&cfg, at(2026, 10, 24, 4, 0)));
+        assert_eq!(
+            next_open(&cfg, at(2026, 10, 24, 4, 0)),
+            Some(at(2026, 10, 31, 2, 0))
+        );
+        assert_eq!(next_start(&cfg, saturday), Some(at(2026, 10, 31, 2, 0)));
+    }
+
+    #[test]
+    fn without_windows_everything_is_allowed() {
+        let cfg = config("UTC", &[]);
+        assert!(is_open(&cfg, at(2026, 10, 18, 12, 0)));
+        assert_eq!(next_open(&cfg, at(2026, 10, 18, 12, 0)), None);
+    }
+
+    #[test]
+    fn windows_are_evaluated_in_the_configured_timezone() {
+        // 上海 02:00 为 UTC 前一天 18:00
+        let cfg = config("Asia/Shanghai", &[("0 2 * * *", 60)]);
+        assert!(is_open(&cfg, at(2026, 10, 18, 18, 30)));
+        assert!(!is_open(&cfg, at(2026, 10, 18, 2, 30)));
+        assert_eq!(
+            next_open(&cfg, at(2026, 10, 18, 2, 30)),
+            Some(at(2026, 10, 18, 18, 0))
+        );
+        // 周六按本地日期计算：上海周六 01:00 为 UTC 周五 17:00
+        let cfg = config("Asia/Shanghai", &[("0 1 * * 6", 60)]);
+        assert_eq!(
+            next_open(&cfg, at(2026, 10, 19, 0, 0)),
+            Some(at(2026, 10, 23, 17, 0))
+        );
+    }
+
+    #[test]
+    fn earliest_of_several_windows_wins() {
+        let cfg = config("UTC", &[("0 2 * * 6", 60), ("0 22 * * 1-5", 30)]);
+        // 周日中午之后最近的是周一 22:00
+        assert_eq!(
+            next_open(&cfg, at(2026, 10, 18, 12, 0)),
+            Some(at(2026, 10, 19, 22, 0))
+        );
+        assert!(is_open(&cfg, at(2026, 10, 19, 22, 15)));
+    }
+}
@@ -1,4 +1,11 @@
-mod service;
-mod state;
-mod updater;
-mod watchdog;
+pub mod admin;
+pub mod bundle;
+pub mod crash;
+pub mod identity;
+pub mod labels;
+pub mod maintenance;
+pub mod service;
+pub mod state;
+pub mod supervisor;
+pub mod updater;
+pub mod watchdog;
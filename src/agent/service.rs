@@ -1 +1,392 @@
+//! Agent lifecycle orchestrator.
+//!
+//! Owns the tokio runtime, starts subsystems in dependency order (storage,
+//! remote config, identity, security, admin, plugins, collectors, updater,
+//! gRPC, crash reporting, log forwarding, health), waits for SIGTERM/SIGINT or
+//! a restart request and stops them in reverse order, each bounded by its own
+//! timeout.
 
+use crate::agent::state::{self, AgentState};
+use crate::agent::watchdog;
+use crate::grpc::client::{GrpcClient, Outbound, outbound_channel};
+use crate::health;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+
+/// 默认的子系统停止超时
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 进程内重启请求（如升级完成或回滚后），在所有子系统停止后重新 exec 自身
+static RESTART: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// serve 的退出方式
+#[derive(Debug, PartialEq, Eq)]
+enum Exit {
+    Shutdown,
+    Restart,
+}
+
+/// 请求有序停止后重启 agent
+pub fn request_restart(reason: &str) {
+    tracing::info!(reason, "agent restart requested");
+    RESTART.notify_one();
+}
+
+/// 由编排器管理生命周期的子系统
+#[async_trait]
+pub trait Subsystem: Send {
+    fn name(&self) -> &'static str;
+
+    /// 启动子系统；可从上下文获取依赖并注册命令处理器
+    async fn start(&mut self, ctx: &mut Context) -> Result<()>;
+
+    /// 停止子系统，超过 stop_timeout 后放弃等待
+    async fn stop(&mut self) -> Result<()>;
+
+    fn stop_timeout(&self) -> Duration {
+        DEFAULT_STOP_TIMEOUT
+    }
+}
+
+/// 子系统之间共享的运行时上下文
+pub struct Context {
+    pub outbound: Outbound,
+    pub connections: watch::Receiver<u64>,
+    /// gRPC 客户端在 gRPC 子系统启动前用于注册命令处理器，启动时被取走
+    pub client: Option<GrpcClient>,
+}
+
+impl Context {
+    fn new() -> Self {
+        let (outbound, outbound_rx) = outbound_channel();
+        let client = GrpcClient::new(outbound.clone(), outbound_rx);
+        state::attach_reporter(outbound.clone());
+        Self {
+            outbound,
+            connections: client.connections(),
+            client: Some(client),
+        }
+    }
+
+    pub fn client_mut(&mut self) -> Result<&mut GrpcClient> {
+        self.client
+            .as_mut()
+            .ok_or_else(|| anyhow!("gRPC client already started"))
+    }
+}
+
+/// 按依赖顺序排列的子系统；安全模式下只保留与 master 通信及升级所需的核心子系统
+fn subsystems(safe_mode: bool) -> Vec<Box<dyn Subsystem>> {
+    let mut subsystems: Vec<Box<dyn Subsystem>> = vec![
+        Box::new(crate::storage::StorageSubsystem),
+        Box::new(crate::config::remote::RemoteConfigSubsystem),
+        Box::new(crate::agent::identity::IdentitySubsystem),
+        Box::new(crate::security::SecuritySubsystem),
+        Box::new(crate::agent::admin::AdminSubsystem::default()),
+    ];
+    if !safe_mode {
+        subsystems.push(Box::new(crate::plugin::host::PluginHost::default()));
+        subsystems.push(Box::new(
+            crate::collector::manager::CollectorManager::default(),
+        ));
+    }
+    subsystems.push(Box::new(crate::agent::updater::Updater::default()));
+    subsystems.push(Box::new(crate::grpc::GrpcSubsystem::default()));
+    subsystems.push(Box::new(crate::agent::crash::CrashReporter));
+    subsystems.push(Box::new(crate::telemetry::forward::LogForwarder::default()));
+    subsystems.push(Box::new(health::reporter::HealthReporter::default()));
+    subsystems
+}
+
+/// 有序停止所有子系统的最长时间，即各子系统停止超时之和；监督进程据此等待 worker 退出
+#[cfg(unix)]
+pub fn stop_budget(safe_mode: bool) -> Duration {
+    subsystems(safe_mode)
+        .iter()
+        .map(|subsystem| subsystem.stop_timeout())
+        .sum()
+}
+
+/// 运行 agent 直到收到 SIGTERM / SIGINT
+pub fn run() -> Result<()> {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("warden-worker")
+        .build()
+        .context("failed to build tokio runtime")?;
+    let exit = rt.block_on(serve());
+    drop(rt);
+    // 日志由后台线程异步写入文件，退出或 exec 前同步刷盘
+    crate::telemetry::logging::flush_global();
+    if exit? == Exit::Restart {
+        crate::agent::updater::exec_self()?;
+    }
+    Ok(())
+}
+
+async fn serve() -> Result<Exit> {
+    let mut ctx = Context::new();
+    watchdog::spawn_heartbeat();
+    let safe_mode = watchdog::safe_mode();
+    if safe_mode {
+        tracing::warn!("running in safe mode after a crash loop, plugins and collectors disabled");
+        health::checker().set(
+            "watchdog",
+            health::Status::Degraded("safe mode after crash loop".to_string()),
+        );
+    }
+    let started = match start_all(subsystems(safe_mode), &mut ctx).await {
+        Ok(started) => started,
+        Err((started, e)) => {
+            state::try_transition(AgentState::Draining, "startup failed");
+            stop_all(started).await;
+            state::try_transition(AgentState::Stopped, "startup failed");
+            return Err(e);
+        }
+    };
+    tracing::info!("agent started");
+
+    let (exit, reason) = tokio::select! {
+        signal = wait_for_signal() => (Exit::Shutdown, signal?),
+        _ = RESTART.notified() => (Exit::Restart, "restart"),
+    };
+    tracing::info!(reason, "shutdown requested");
+    state::transition(AgentState::Draining, reason)?;
+    stop_all(started).await;
+    crate::agent::crash::record_clean_stop();
+    state::transition(AgentState::Stopped, "shutdown complete")?;
+    tracing::info!("agent stopped");
+    Ok(exit)
+}
+
+/// 按顺序启动子系统；某个子系统启动失败时返回此前已启动的子系统，由调用方逆序停止
+async fn start_all(
+    subsystems: Vec<Box<dyn Subsystem>>,
+    ctx: &mut Context,
+) -> std::result::Result<Vec<Box<dyn Subsystem>>, (Vec<Box<dyn Subsystem>>, anyhow::Error)> {
+    let mut started: Vec<Box<dyn Subsystem>> = Vec::new();
+    for mut subsystem in subsystems {
+        let name = subsystem.name();
+        health::checker().set(name, health::Status::Starting);
+        if let Err(e) = subsystem.start(ctx).await {
+            tracing::error!(subsystem = name, error = %format!("{e:#}"), "subsystem failed to start");
+            health::checker().set(name, health::Status::Failed(format!("{e:#}")));
+            return Err((started, e.context(format!("failed to start {name}"))));
+        }
+        health::checker().set(name, health::Status::Healthy);
+        tracing::info!(subsystem = name, "subsystem started");
+        started.push(subsystem);
+    }
+    Ok(started)
+}
+
+/// 逆序停止已启动的子系统
+async fn stop_all(mut started: Vec<Box<dyn Subsystem>>) {
+    while let Some(mut subsystem) = started.pop() {
+        let name = subsystem.name();
+        let timeout = subsystem.stop_timeout();
+        match tokio::time::timeout(timeout, subsystem.stop()).await {
+            Ok(Ok(())) => tracing::info!(subsystem = name, "subsystem stopped"),
+            Ok(Err(e)) => {
+                tracing::warn!(subsystem = name, error = %format!("{e:#}"), "subsystem stopped with error")
+            }
+            Err(_) => tracing::warn!(
+                subsystem = name,
+                timeout_secs = timeout.as_secs(),
+                "subsystem stop timed out"
+            ),
+        }
+        health::checker().set(name, health::Status::Stopped);
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> Result<&'static str> {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut term = signal(SignalKind::terminate())?;
+    let mut int = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = term.recv() => Ok("SIGTERM"),
+        _ = int.recv() => Ok("SIGINT"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("ctrl-c")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// 记录启动与停止顺序的子系统
+    struct Fake {
+        name: &'static str,
+        log: Log,
+        fail_start: bool,
+        stop_delay: Duration,
+    }
+
+    fn fake(name: &'static str, log: &Log) -> Box<Fake> {
+        Box::new(Fake {
+            name,
+            log: log.clone(),
+            fail_start: false,
+            stop_delay: Duration::ZERO,
+        })
+    }
+
+    #[async_trait]
+    impl Subsystem for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn start(&mut self, _ctx: &mut Context) -> Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("start {}", self.name));
+            if self.fail_start {
+                return Err(anyhow!("boom"));
+            }
+            Ok(())
+        }
+
+        async fn stop(&mut self) -> Result<()> {
+            tokio::time::sleep(self.stop_delay).await;
+            self.log.lock().unwrap().push(format!("stop {}", self.name));
+            Ok(())
+        }
+
+        fn stop_timeout(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+    }
+
+    /// 不连接 master、不注册状态上报的上下文
+    fn context() -> Context {
+        let (outbound, _) = outbound_channel();
+        Context {
+            outbound,
+            connections: watch::channel(0).1,
+            client: None,
+        }
+    }
+
+    fn entries(log: &Log) -> Vec<String> {
+        log.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn starts_in_order_and_stops_in_reverse() {
+        let log = Log::default();
+        let subsystems: Vec<Box<dyn Subsystem>> = vec![
+            fake("test.order.a", &log),
+            fake("test.order.b", &log),
+            fake("test.order.c", &log),
+        ];
+        let started = start_all(subsystems, &mut context()).await.ok().unwrap();
+        assert_eq!(
+            health::checker().snapshot()["test.order.b"],
+            health::Status::Healthy
+        );
+        stop_all(started).await;
+        assert_eq!(
+            entries(&log),
+            [
+                "start test.order.a",
+                "start test.order.b",
+                "start test.order.c",
+                "stop test.order.c",
+                "stop test.order.b",
+                "stop test.order.a",
+            ]
+        );
+        assert_eq!(
+            health::checker().snapshot()["test.order.a"],
+            health::Status::Stopped
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_timeout_does_not_block_the_remaining_subsystems() {
+        let log = Log::default();
+        let mut slow = fake("test.timeout.slow", &log);
+        slow.stop_delay = Duration::from_secs(60);
+        let subsystems: Vec<Box<dyn Subsystem>> = vec![fake("test.timeout.a", &log), slow];
+        let started = start_all(subsystems, &mut context()).await.ok().unwrap();
+
+        let begin = std::time::Instant::now();
+        stop_all(started).await;
+        assert!(begin.elapsed() < Duration::from_secs(5));
+        // 超时的子系统没有完成停止，之后的子系统照常停止
+        assert_eq!(
+            entries(&log),
+            [
+                "start test.timeout.a",
+                "start test.timeout.slow",
+                "stop test.timeout.a"
+            ]
+        );
+        assert_eq!(
+            health::checker().snapshot()["test.timeout.slow"],
+            health::Status::Stopped
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_start_returns_the_started_subsystems_for_rollback() {
+        let log = Log::default();
+        let mut failing = fake("test.rollback.b", &log);
+        failing.fail_start = true;
+        let subsystems: Vec<Box<dyn Subsystem>> = vec![
+            fake("test.rollback.a", &log),
+            failing,
+            fake("test.rollback.c", &log),
+        ];
+        let (started, err) = start_all(subsystems, &mut context()).await.err().unwrap();
+        assert_eq!(format!("{err:#}"), "failed to start test.rollback.b: boom");
+        assert!(matches!(
+            health::checker().snapshot()["test.rollback.b"],
+            health::Status::Failed(_)
+        ));
+        stop_all(started).await;
+        assert_eq!(
+            entries(&log),
+            [
+                "start test.rollback.a",
+                "start test.rollback.b",
+                "stop test.rollback.a"
+            ]
+        );
+    }
+
+    #[test]
+    fn safe_mode_skips_plugins_and_collectors() {
+        let names = |safe_mode| {
+            subsystems(safe_mode)
+                .iter()
+                .map(|s| s.name())
+                .collect::<Vec<_>>()
+        };
+        let full = names(false);
+        let safe = names(true);
+        assert!(full.contains(&"plugins") && full.contains(&"collectors"));
+        assert_eq!(
+            safe,
+            full.iter()
+                .copied()
+                .filter(|name| !matches!(*name, "plugins" | "collectors"))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -1 +1,242 @@
+//! Agent state machine with guarded transitions.
+//!
+//! Every accepted transition is logged, recorded in the `events` table (once
+//! storage is open) and reported to the master. Subsystems can query the
+//! current state or wait for a state, e.g. before starting an update.
 
+use crate::grpc::client::Outbound;
+use crate::grpc::proto::{StateChange, agent_message};
+use crate::utils::time::now_millis;
+use anyhow::{Result, anyhow};
+use once_cell::sync::{Lazy, OnceCell};
+use serde_json::json;
+use std::fmt;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentState {
+    Initializing,
+    Enrolling,
+    Connecting,
+    Online,
+    Degraded,
+    Offline,
+    Updating,
+    Draining,
+    Stopped,
+}
+
+impl AgentState {
+    /// 与 master 之间存在可用连接
+    pub fn is_connected(self) -> bool {
+        matches!(self, AgentState::Online | AgentState::Degraded)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentState::Initializing => "initializing",
+            AgentState::Enrolling => "enrolling",
+            AgentState::Connecting => "connecting",
+            AgentState::Online => "online",
+            AgentState::Degraded => "degraded",
+            AgentState::Offline => "offline",
+            AgentState::Updating => "updating",
+            AgentState::Draining => "draining",
+            AgentState::Stopped => "stopped",
+        }
+    }
+
+    /// 允许的状态迁移
+    fn can_transition_to(self, to: AgentState) -> bool {
+        use AgentState::*;
+        match self {
+            Initializing => matches!(to, Enrolling | Connecting | Draining | Stopped),
+            Enrolling => matches!(to, Connecting | Draining | Stopped),
+            Connecting => matches!(to, Online | Offline | Updating | Draining),
+            Online => matches!(to, Degraded | Offline | Updating | Draining),
+            Degraded => matches!(to, Online | Offline | Updating | Draining),
+            Offline => matches!(to, Connecting | Online | Updating | Draining),
+            Updating => matches!(
+                to,
+                Connecting | Online | Degraded | Offline | Draining | Stopped
+            ),
+            Draining => matches!(to, Stopped),
+            Stopped => false,
+        }
+    }
+}
+
+impl fmt::Display for AgentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+static STATE: Lazy<watch::Sender<AgentState>> =
+    Lazy::new(|| watch::Sender::new(AgentState::Initializing));
+
+/// 状态变更上报通道，由编排器在启动时设置
+static REPORTER: OnceCell<Outbound> = OnceCell::new();
+
+pub fn attach_reporter(outbound: Outbound) {
+    let _ = REPORTER.set(outbound);
+}
+
+pub fn current() -> AgentState {
+    *STATE.borrow()
+}
+
+/// 等待直到状态满足条件，返回满足条件时的状态
+pub async fn wait_for(pred: impl FnMut(&AgentState) -> bool) -> AgentState {
+    let mut rx = STATE.subscribe();
+    match rx.wait_for(pred).await {
+        Ok(state) => *state,
+        Err(_) => current(),
+    }
+}
+
+/// 执行状态迁移；非法迁移返回错误，迁移到当前状态为空操作
+pub fn transition(to: AgentState, reason: &str) -> Result<()> {
+    let mut from = to;
+    let mut allowed = true;
+    STATE.send_if_modified(|state| {
+        from = *state;
+        if *state == to {
+            return false;
+        }
+        allowed = state.can_transition_to(to);
+        if allowed {
+            *state = to;
+        }
+        allowed
+    });
+    if !allowed {
+        return Err(anyhow!("invalid state transition {from} -> {to}"));
+    }
+    if from != to {
+        on_transition(from, to, reason);
+    }
+    Ok(())
+}
+
+/// 尝试迁移，非法迁移（如 draining 期间的重连失败）只记录 debug 日志
+pub fn try_transition(to: AgentState, reason: &str) -> bool {
+    match transition(to, reason) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::debug!(error = %e, reason, "state transition ignored");
+            false
+        }
+    }
+}
+
+fn on_transition(from: AgentState, to: AgentState, reason: &str) {
+    tracing::info!(from = %from, to = %to, reason, "agent state changed");
+    if let Some(storage) = crate::storage::try_global() {
+        let payload = json!({ "from": from.as_str(), "to": to.as_str(), "reason": reason });
+        if let Err(e) = storage.record_event("state.transition", &payload) {
+            tracing::warn!(error = %format!("{e:#}"), "failed to record state transition");
+        }
+    }
+    if let Some(outbound) = REPORTER.get() {
+        let report = StateChange {
+            id: crate::agent::identity::agent_id(),
+            ts: now_millis(),
+            from: from.as_str().to_string(),
+            to: to.as_str().to_string(),
+            reason: reason.to_string(),
+        };
+        // 出站队列满时丢弃，master 可从下一次心跳/健康报告中恢复状态
+        let _ = outbound.try_send(agent_message::Body::State(report));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AgentState::{self, *};
+
+    const ALL: [AgentState; 9] = [
+        Initializing,
+        Enrolling,
+        Connecting,
+        Online,
+        Degraded,
+        Offline,
+        Updating,
+        Draining,
+        Stopped,
+    ];
+
+    #[test]
+    fn allows_the_documented_transitions() {
+        let allowed = [
+            (Initializing, Enrolling),
+            (Initializing, Connecting),
+            (Enrolling, Connecting),
+            (Connecting, Online),
+            (Connecting, Offline),
+            (Online, Degraded),
+            (Degraded, Online),
+            (Online, Offline),
+            (Offline, Connecting),
+            (Offline, Online),
+            (Online, Updating),
+            (Offline, Updating),
+            (Updating, Online),
+            (Updating, Connecting),
+            (Updating, Stopped),
+            (Online, Draining),
+            (Draining, Stopped),
+        ];
+        for (from, to) in allowed {
+            assert!(
+                from.can_transition_to(to),
+                "{from} -> {to} should be allowed"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_other_transitions() {
+        let refused = [
+            (Initializing, Online),
+            (Initializing, Updating),
+            (Enrolling, Online),
+            (Connecting, Enrolling),
+            (Online, Connecting),
+            (Online, Stopped),
+            (Draining, Online),
+            (Draining, Updating),
+            (Degraded, Stopped),
+        ];
+        for (from, to) in refused {
+            assert!(
+                !from.can_transition_to(to),
+                "{from} -> {to} should be refused"
+            );
+        }
+    }
+
+    #[test]
+    fn draining_and_stopped_are_terminal() {
+        for to in ALL {
+            assert_eq!(
+                Draining.can_transition_to(to),
+                to == Stopped,
+                "draining -> {to}"
+            );
+            assert!(!Stopped.can_transition_to(to), "stopped -> {to}");
+        }
+        // 除 stopped 外任何状态都能进入 draining，保证可以正常退出
+        for from in ALL.into_iter().filter(|s| !matches!(s, Draining | Stopped)) {
+            assert!(from.can_transition_to(Draining), "{from} -> draining");
+        }
+    }
+
+    #[test]
+    fn only_online_and_degraded_count_as_connected() {
+        for state in ALL {
+            assert_eq!(state.is_connected(), matches!(state, Online | Degraded));
+        }
+    }
+}
@@ -0,0 +1,440 @@
+//! In-process supervision tree for background tokio tasks.
+//!
+//! A [`Supervisor`] owns a set of child tasks built from [`ChildSpec`]
+//! factories. When a child panics or returns an error it is restarted
+//! according to its [`Restart`] type and the supervisor [`Strategy`]; more than
+//! `supervisor.max_restarts` restarts within `supervisor.restart_window_secs`
+//! means the failure is not transient, so the supervisor stops all of its
+//! children and reports itself failed instead of flapping. Every child and the
+//! supervisor itself are reported to `health::checker` as
+//! `supervisor.<name>/<child>` and `supervisor.<name>`, apart from the
+//! subsystem names the orchestrator reports.
+//!
+//! This only covers tasks inside the process; a hung or crashed process is
+//! handled by the process-level watchdog (`run --supervised`).
+
+use crate::config::schema::SupervisorConfig;
+use crate::health::{Status, checker};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+
+/// 子任务收到停止信号后的退出等待时间，超时后中止
+const CHILD_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 子任务退出后是否重启
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// 总是重启，包括正常返回
+    Permanent,
+    /// 仅在 panic 或返回错误时重启，正常返回视为完成
+    Transient,
+}
+
+/// 一个子任务需要重启时影响的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// 只重启失败的子任务
+    OneForOne,
+    /// 停止并重启全部子任务，适用于相互依赖的任务
+    OneForAll,
+}
+
+type TaskFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+type Factory = Arc<dyn Fn(watch::Receiver<bool>) -> TaskFuture + Send + Sync>;
+
+/// 子任务定义：每次（重新）启动时调用工厂生成新的任务
+pub struct ChildSpec {
+    name: String,
+    restart: Restart,
+    factory: Factory,
+}
+
+impl ChildSpec {
+    /// 工厂收到停止信号接收端，任务应在其变为 true 时尽快返回
+    pub fn new<F, Fut>(name: impl Into<String>, restart: Restart, factory: F) -> Self
+    where
+        F: Fn(watch::Receiver<bool>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            restart,
+            factory: Arc::new(move |shutdown| Box::pin(factory(shutdown))),
+        }
+    }
+}
+
+pub struct Supervisor {
+    name: &'static str,
+    strategy: Strategy,
+    children: Vec<ChildSpec>,
+}
+
+/// 运行中的监督者，stop 时通知并等待全部子任务退出
+pub struct SupervisorHandle {
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl SupervisorHandle {
+    pub async fn stop(self) {
+        self.shutdown.send_replace(true);
+        let _ = self.task.await;
+    }
+}
+
+/// 子任务的一次退出
+enum Exit {
+    Normal,
+    Error(String),
+    Panic(String),
+}
+
+impl Exit {
+    fn from_join(result: Result<Result<()>, JoinError>) -> Self {
+        match result {
+            Ok(Ok(())) => Exit::Normal,
+            Ok(Err(e)) => Exit::Error(format!("{e:#}")),
+            Err(e) if e.is_panic() => {
+                let payload = e.into_panic();
+                let msg = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                Exit::Panic(msg)
+            }
+            Err(_) => Exit::Error("cancelled".to_string()),
+        }
+    }
+
+    fn should_restart(&self, restart: Restart) -> bool {
+        match restart {
+            Restart::Permanent => true,
+            Restart::Transient => !matches!(self, Exit::Normal),
+        }
+    }
+}
+
+/// 运行中的子任务
+struct Running {
+    index: usize,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Supervisor {
+    pub fn new(name: &'static str, strategy: Strategy) -> Self {
+        Self {
+            name,
+            strategy,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn child(mut self, spec: ChildSpec) -> Self {
+        self.children.push(spec);
+        self
+    }
+
+    /// 启动全部子任务并在后台监督
+    pub fn spawn(self) -> SupervisorHandle {
+        let limits = crate::config::global().supervisor.clone();
+        let (tx, rx) = watch::channel(false);
+        let task = tokio::spawn(self.run(limits, rx));
+        SupervisorHandle { shutdown: tx, task }
+    }
+
+    /// 健康状态键，与子系统名区分
+    fn health_key(&self) -> String {
+        format!("supervisor.{}", self.name)
+    }
+
+    fn component(&self, index: usize) -> String {
+        format!("{}/{}", self.health_key(), self.children[index].name)
+    }
+
+    async fn run(self, limits: SupervisorConfig, mut shutdown: watch::Receiver<bool>) {
+        let mut set = JoinSet::new();
+        let mut running: HashMap<tokio::task::Id, Running> = HashMap::new();
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+        let window = Duration::from_secs(limits.restart_window_secs);
+        let delay = Duration::from_millis(limits.restart_delay_ms);
+
+        for index in 0..self.children.len() {
+            self.start_child(&mut set, &mut running, index);
+        }
+        checker().set(&self.health_key(), Status::Healthy);
+
+        loop {
+            // 没有运行中的子任务时仅等待停止信号
+            if set.is_empty() {
+                let _ = shutdown.changed().await;
+                break;
+            }
+            let joined = tokio::select! {
+                _ = shutdown.changed() => break,
+                Some(joined) = set.join_next_with_id() => joined,
+            };
+            let (id, exit) = match joined {
+                Ok((id, result)) => (id, Exit::from_join(Ok(result))),
+                Err(e) => (e.id(), Exit::from_join(Err(e))),
+            };
+            let Some(child) = running.remove(&id) else {
+                continue;
+            };
+            let spec = &self.children[child.index];
+            let component = self.component(child.index);
+
+            let restart = exit.should_restart(spec.restart);
+            match &exit {
+                Exit::Normal => {
+                    tracing::info!(supervisor = self.name, task = %spec.name, "task finished")
+                }
+                Exit::Error(e) => {
+                    tracing::error!(supervisor = self.name, task = %spec.name, error = %e, restart, "task failed")
+                }
+                Exit::Panic(msg) => {
+                    tracing::error!(supervisor = self.name, task = %spec.name, panic = %msg, restart, "task panicked")
+                }
+            }
+            if !restart {
+                let status = match &exit {
+                    Exit::Normal => Status::Stopped,
+                    Exit::Error(e) => Status::Failed(e.clone()),
+                    Exit::Panic(msg) => Status::Failed(format!("panicked: {msg}")),
+                };
+                checker().set(&component, status);
+                continue;
+            }
+
+            // 超过重启强度说明故障不是偶发的，停止整棵子树
+            let now = Instant::now();
+            restarts.push_back(now);
+            while restarts
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > window)
+            {
+                restarts.pop_front();
+            }
+            if restarts.len() > limits.max_restarts as usize {
+                let reason = format!(
+                    "restart intensity exceeded ({} failures in {}s), last in {}",
+                    restarts.len(),
+                    limits.restart_window_secs,
+                    spec.name
+                );
+                tracing::error!(supervisor = self.name, reason = %reason, "supervisor giving up");
+                checker().set(&component, Status::Failed(reason.clone()));
+                self.stop_children(&mut set, &mut running).await;
+                checker().set(&self.health_key(), Status::Failed(reason));
+                // 保持放弃状态直到 agent 停止
+                let _ = shutdown.changed().await;
+                return;
+            }
+
+            let reason = match &exit {
+                Exit::Normal => "exited".to_string(),
+                Exit::Error(e) => e.clone(),
+                Exit::Panic(msg) => format!("panicked: {msg}"),
+            };
+            checker().set(
+                &component,
+                Status::Degraded(format!("restarting: {reason}")),
+            );
+            let mut indexes = vec![child.index];
+            if self.strategy == Strategy::OneForAll {
+                self.stop_children(&mut set, &mut running).await;
+                indexes = (0..self.children.len()).collect();
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.changed() => break,
+            }
+            for index in indexes {
+                tracing::info!(supervisor = self.name, task = %self.children[index].name, "restarting task");
+                self.start_child(&mut set, &mut running, index);
+            }
+        }
+
+        self.stop_children(&mut set, &mut running).await;
+        checker().set(&self.health_key(), Status::Stopped);
+    }
+
+    fn start_child(
+        &self,
+        set: &mut JoinSet<Result<()>>,
+        running: &mut HashMap<tokio::task::Id, Running>,
+        index: usize,
+    ) {
+        let (tx, rx) = watch::channel(false);
+        let handle = set.spawn((self.children[index].factory)(rx));
+        running.insert(
+            handle.id(),
+            Running {
+                index,
+                shutdown: tx,
+            },
+        );
+        checker().set(&self.component(index), Status::Healthy);
+    }
+
+    /// 通知全部子任务停止，超时后中止
+    async fn stop_children(
+        &self,
+        set: &mut JoinSet<Result<()>>,
+        running: &mut HashMap<tokio::task::Id, Running>,
+    ) {
+        for child in running.values() {
+            child.shutdown.send_replace(true);
+        }
+        let drained = tokio::time::timeout(CHILD_STOP_TIMEOUT, async {
+            while set.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            tracing::warn!(
+                supervisor = self.name,
+                "tasks did not stop in time, aborting"
+            );
+            set.shutdown().await;
+        }
+        for child in running.drain().map(|(_, c)| c) {
+            checker().set(&self.component(child.index), Status::Stopped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn limits(max_restarts: u32) -> SupervisorConfig {
+        SupervisorConfig {
+            max_restarts,
+            restart_window_secs: 60,
+            restart_delay_ms: 1,
+        }
+    }
+
+    /// 前 failures 次启动立即失败，之后运行到停止信号
+    fn flaky(name: &str, failures: usize, starts: Arc<AtomicUsize>) -> ChildSpec {
+        ChildSpec::new(name, Restart::Permanent, move |mut shutdown| {
+            let n = starts.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if n <= failures {
+                    anyhow::bail!("failure {n}");
+                }
+                let _ = shutdown.wait_for(|s| *s).await;
+                Ok(())
+            }
+        })
+    }
+
+    fn status(component: &str) -> Option<Status> {
+        checker().snapshot().get(component).cloned()
+    }
+
+    async fn wait_until(cond: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !cond() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("condition not reached in time");
+    }
+
+    fn spawn(supervisor: Supervisor, limits: SupervisorConfig) -> SupervisorHandle {
+        let (tx, rx) = watch::channel(false);
+        let task = tokio::spawn(supervisor.run(limits, rx));
+        SupervisorHandle { shutdown: tx, task }
+    }
+
+    #[tokio::test]
+    async fn one_for_one_restarts_only_the_failed_child() {
+        let (failing, steady) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let supervisor = Supervisor::new("test_one_for_one", Strategy::OneForOne)
+            .child(flaky("failing", 2, failing.clone()))
+            .child(flaky("steady", 0, steady.clone()));
+        let handle = spawn(supervisor, limits(5));
+        wait_until(|| failing.load(Ordering::SeqCst) == 3).await;
+        wait_until(|| status("supervisor.test_one_for_one/failing") == Some(Status::Healthy)).await;
+        assert_eq!(steady.load(Ordering::SeqCst), 1);
+        assert_eq!(status("supervisor.test_one_for_one"), Some(Status::Healthy));
+
+        handle.stop().await;
+        assert_eq!(status("supervisor.test_one_for_one"), Some(Status::Stopped));
+        assert_eq!(
+            status("supervisor.test_one_for_one/steady"),
+            Some(Status::Stopped)
+        );
+    }
+
+    #[tokio::test]
+    async fn one_for_all_restarts_every_child() {
+        let (failing, steady) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let supervisor = Supervisor::new("test_one_for_all", Strategy::OneForAll)
+            .child(flaky("failing", 1, failing.clone()))
+            .child(flaky("steady", 0, steady.clone()));
+        let handle = spawn(supervisor, limits(5));
+        wait_until(|| failing.load(Ordering::SeqCst) == 2).await;
+        wait_until(|| steady.load(Ordering::SeqCst) == 2).await;
+        handle.stop().await;
+    }
+
+    #[tokio::test]
+    async fn transient_children_that_finish_are_not_restarted() {
+        let (transient, permanent) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let counter = transient.clone();
+        let once = ChildSpec::new("once", Restart::Transient, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        });
+        let counter = permanent.clone();
+        let looping = ChildSpec::new("looping", Restart::Permanent, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        });
+        let supervisor = Supervisor::new("test_transient", Strategy::OneForOne)
+            .child(once)
+            .child(looping);
+        let handle = spawn(supervisor, limits(100));
+        wait_until(|| permanent.load(Ordering::SeqCst) >= 3).await;
+        assert_eq!(transient.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            status("supervisor.test_transient/once"),
+            Some(Status::Stopped)
+        );
+        handle.stop().await;
+    }
+
+    #[tokio::test]
+    async fn exceeding_restart_intensity_fails_the_supervisor() {
+        let (failing, steady) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let supervisor = Supervisor::new("test_intensity", Strategy::OneForOne)
+            .child(flaky("failing", usize::MAX, failing.clone()))
+            .child(flaky("steady", 0, steady.clone()));
+        let handle = spawn(supervisor, limits(2));
+        wait_until(|| matches!(status("supervisor.test_intensity"), Some(Status::Failed(_)))).await;
+        // 两次重启后第三次失败超出强度，不再重启
+        assert_eq!(failing.load(Ordering::SeqCst), 3);
+        assert!(matches!(
+            status("supervisor.test_intensity/failing"),
+            Some(Status::Failed(_))
+        ));
+        assert_eq!(
+            status("supervisor.test_intensity/steady"),
+            Some(Status::Stopped)
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(failing.load(Ordering::SeqCst), 3);
+        handle.stop().await;
+    }
+}
@@ -1 +1,982 @@
+//! Agent self-update: download, verify, swap the binary atomically and restart.
+//!
+//! An `agent.update` command carries a download URL, a manifest and an
+//! ed25519 signature over the SHA-256 digest of the manifest, like the
+//! manifest of an offline bundle. The manifest is the JSON object
+//! `{"version", "channel", "sha256"}` naming the binary's version, release
+//! channel and SHA-256 digest; it is verified against `update.trusted_keys`
+//! before anything is downloaded, and the version and channel checks use the
+//! signed values only. The binary is installed when its digest matches.
+//!
+//! The running binary is kept as `<exe>.prev`; after the restart the new
+//! version has `update.health_deadline_secs` to finish starting with storage
+//! open and the master stream connected, otherwise the previous binary is
+//! restored. Each step is recorded in the `updates` table.
+//!
+//! A request may also carry a delta patch against the installed version; the
+//! reconstructed binary is checked against the same signed digest and the
+//! full binary is downloaded when the patch does not apply. The bytes saved
+//! are recorded as `bytes_saved` in the update meta.
+//!
+//! Requests must match the configured `update.channel` (stable, beta or a
+//! pinned version) and are deferred to the next maintenance window when one
+//! is configured.
+//!
+//! A new binary that crashes before this subsystem starts cannot roll itself
+//! back; under `run --supervised` the watchdog restores the previous binary
+//! when it detects the crash loop.
 
+use crate::agent::maintenance;
+use crate::agent::service::{Context, Subsystem, request_restart};
+use crate::agent::state::{self, AgentState};
+use crate::config::schema::UpdateConfig;
+use crate::grpc::handler::{self, CommandHandler, reply};
+use crate::grpc::proto::{CmdResult, ControlCmd};
+use crate::health;
+use crate::plugin::installer;
+use crate::storage::{Storage, UpdateRecord};
+use crate::utils::time::now_millis;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ed25519_dalek::{Signature, VerifyingKey};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, watch};
+use tokio::task::JoinHandle;
+
+/// 当前运行的 agent 版本
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// `updates.name` 中 agent 二进制的组件名
+pub(crate) const COMPONENT: &str = "agent";
+
+/// 仅包含插件的离线 bundle 在 updates 表中的组件名
+pub const PLUGINS_COMPONENT: &str = "plugins";
+
+pub const STATE_PENDING: &str = "pending";
+pub const STATE_APPLIED: &str = "applied";
+pub const STATE_FAILED: &str = "failed";
+pub const STATE_ROLLED_BACK: &str = "rolled_back";
+
+/// 新二进制的大小上限，防止异常补丁或下载占满磁盘
+pub(crate) const MAX_BINARY_BYTES: u64 = 512 * 1024 * 1024;
+
+/// zstd 解码窗口的上下限（window log）
+const MIN_WINDOW_LOG: u32 = 10;
+const MAX_WINDOW_LOG: u32 = 31;
+
+/// 重启前等待其它在途命令结束的最长时间
+const IDLE_WAIT: Duration = Duration::from_secs(60);
+
+/// 启动时的可执行文件路径；替换后 /proc/self/exe 会指向已删除的旧文件，因此提前缓存
+static EXE: OnceCell<PathBuf> = OnceCell::new();
+
+pub(crate) fn exe_path() -> Result<&'static PathBuf> {
+    EXE.get_or_try_init(|| std::env::current_exe().context("failed to locate agent executable"))
+}
+
+/// 解析 base64 编码的 ed25519 公钥
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey> {
+    let bytes = BASE64
+        .decode(key.trim())
+        .context("key is not valid base64")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("ed25519 public key must be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).context("invalid ed25519 public key")
+}
+
+/// 校验 SHA-256 摘要
+pub fn check_sha256(digest: &[u8], sha256_hex: &str) -> Result<()> {
+    let expected = hex::decode(sha256_hex.trim()).context("sha256 is not valid hex")?;
+    if digest != &expected[..] {
+        return Err(anyhow!(
+            "sha256 mismatch: expected {}, got {}",
+            sha256_hex.trim().to_ascii_lowercase(),
+            hex::encode(digest)
+        ));
+    }
+    Ok(())
+}
+
+/// 校验任一受信任公钥对数据 SHA-256 摘要的签名
+pub fn verify_signature(data: &[u8], signature_b64: &str, trusted_keys: &[String]) -> Result<()> {
+    if trusted_keys.is_empty() {
+        return Err(anyhow!("no trusted update keys configured"));
+    }
+    let digest = Sha256::digest(data);
+    let signature = BASE64
+        .decode(signature_b64.trim())
+        .context("signature is not valid base64")?;
+    let signature = Signature::from_slice(&signature).context("invalid ed25519 signature")?;
+    for key in trusted_keys {
+        if parse_public_key(key)?
+            .verify_strict(&digest, &signature)
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    Err(anyhow!("signature does not match any trusted key"))
+}
+
+/// 签名的升级清单：版本、发布通道与二进制摘要
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateManifest {
+    pub version: String,
+    #[serde(default = "default_channel")]
+    pub channel: String,
+    pub sha256: String,
+}
+
+/// 校验清单签名后解析清单；其中的值才可用于版本与通道检查
+pub fn verify_manifest(
+    manifest: &str,
+    signature_b64: &str,
+    trusted_keys: &[String],
+) -> Result<UpdateManifest> {
+    verify_signature(manifest.as_bytes(), signature_b64, trusted_keys)
+        .context("update manifest signature rejected")?;
+    serde_json::from_str(manifest).context("invalid update manifest")
+}
+
+/// 旧版本二进制的保存路径
+pub fn previous_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_os_string();
+    name.push(".prev");
+    PathBuf::from(name)
+}
+
+/// 新版本二进制的暂存路径，与目标位于同一目录以保证 rename 原子性
+fn staging_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_os_string();
+    name.push(".update");
+    PathBuf::from(name)
+}
+
+/// 增量补丁的下载路径
+fn patch_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_os_string();
+    name.push(".patch");
+    PathBuf::from(name)
+}
+
+/// 流式写入的暂存文件：边写边计算摘要，超过大小上限即失败；未校验通过的文件在丢弃时删除
+struct Staging {
+    path: PathBuf,
+    file: fs::File,
+    hasher: Sha256,
+    bytes: u64,
+    keep: bool,
+}
+
+impl Staging {
+    fn create(path: PathBuf) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        Ok(Self {
+            path,
+            file,
+            hasher: Sha256::new(),
+            bytes: 0,
+            keep: false,
+        })
+    }
+
+    /// 回到文件开头以读取已写入的内容
+    fn reader(&mut self) -> Result<&mut fs::File> {
+        self.file.seek(SeekFrom::Start(0))?;
+        Ok(&mut self.file)
+    }
+
+    /// 刷盘并校验摘要，通过后返回暂存路径
+    fn finish(mut self, sha256_hex: &str) -> Result<PathBuf> {
+        self.file.sync_all()?;
+        check_sha256(&self.hasher.clone().finalize(), sha256_hex)?;
+        self.keep = true;
+        Ok(self.path.clone())
+    }
+}
+
+impl Write for Staging {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bytes + buf.len() as u64 > MAX_BINARY_BYTES {
+            return Err(io::Error::other(format!(
+                "{} exceeds {MAX_BINARY_BYTES} bytes",
+                self.path.display()
+            )));
+        }
+        let n = self.file.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// 将已校验的二进制写入暂存文件
+pub fn stage(data: &[u8], target: &Path) -> Result<PathBuf> {
+    let staged = staging_path(target);
+    let mut file = fs::File::create(&staged)
+        .with_context(|| format!("failed to create {}", staged.display()))?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    Ok(staged)
+}
+
+/// 保留当前二进制为 `<target>.prev`，再将暂存文件原子地替换到目标位置
+pub fn install(staged: &Path, target: &Path) -> Result<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(staged, fs::Permissions::from_mode(0o755))?;
+    }
+    let prev = previous_path(target);
+    if prev.exists() {
+        fs::remove_file(&prev).with_context(|| format!("failed to remove {}", prev.display()))?;
+    }
+    // 硬链接保证替换期间目标路径始终存在；跨文件系统等情况退化为复制
+    if fs::hard_link(target, &prev).is_err() {
+        fs::copy(target, &prev)
+            .with_context(|| format!("failed to keep previous binary {}", prev.display()))?;
+    }
+    fs::rename(staged, target)
+        .with_context(|| format!("failed to install {}", target.display()))?;
+    Ok(prev)
+}
+
+/// 用 `<target>.prev` 原子地恢复旧版本
+pub fn rollback(target: &Path) -> Result<()> {
+    let prev = previous_path(target);
+    if !prev.exists() {
+        return Err(anyhow!("no previous binary at {}", prev.display()));
+    }
+    fs::rename(&prev, target).with_context(|| format!("failed to restore {}", target.display()))?;
+    Ok(())
+}
+
+/// 以相同参数重新执行当前可执行文件，仅在运行时关闭后调用
+#[cfg(unix)]
+pub(crate) fn exec_self() -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    let exe = exe_path()?;
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    // 新版本不继承监督进程设置的安全模式
+    let err = std::process::Command::new(exe)
+        .args(args)
+        .env_remove(crate::agent::watchdog::SAFE_MODE_ENV)
+        .exec();
+    Err(anyhow!(err).context(format!("failed to exec {}", exe.display())))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn exec_self() -> Result<()> {
+    let exe = exe_path()?;
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    std::process::Command::new(exe).args(args).spawn()?;
+    Ok(())
+}
+
+fn push_step(meta: &mut Value, step: &str, detail: Value) {
+    let entry = json!({ "step": step, "ts": now_millis(), "detail": detail });
+    match meta.get_mut("steps").and_then(Value::as_array_mut) {
+        Some(steps) => steps.push(entry),
+        None => meta["steps"] = json!([entry]),
+    }
+}
+
+/// 追加一个步骤到升级记录的 meta.steps 并更新状态
+pub(crate) fn record_step(id: i64, state: &str, meta: &mut Value, step: &str, detail: Value) {
+    record_step_in(&crate::storage::global(), id, state, meta, step, detail);
+}
+
+pub(crate) fn record_step_in(
+    storage: &Storage,
+    id: i64,
+    state: &str,
+    meta: &mut Value,
+    step: &str,
+    detail: Value,
+) {
+    push_step(meta, step, detail);
+    if let Err(e) = storage.set_update(id, state, meta) {
+        tracing::warn!(update_id = id, step, error = %format!("{e:#}"), "failed to record update step");
+    }
+}
+
+/// `agent.update` 命令的 payload
+#[derive(Debug, Deserialize)]
+struct UpdateRequest {
+    url: String,
+    manifest: String,  // 签名的 UpdateManifest JSON，按原样校验
+    signature: String, // 对 manifest 摘要的签名
+    #[serde(default)]
+    delta: Option<DeltaRequest>,
+}
+
+/// 相对当前安装版本的增量补丁：以旧二进制为参考前缀的 zstd 帧（`zstd --patch-from=<old> <new>`）
+#[derive(Debug, Deserialize)]
+struct DeltaRequest {
+    url: String,
+    from_version: String,
+    from_sha256: String, // 补丁基于的旧二进制摘要
+}
+
+fn default_channel() -> String {
+    "stable".to_string()
+}
+
+/// 检查版本是否符合本机配置的升级通道
+pub fn check_channel(cfg: &UpdateConfig, channel: &str, version: &str) -> Result<()> {
+    match cfg.channel.as_str() {
+        "pinned" if version != cfg.pinned_version => {
+            Err(anyhow!("agent is pinned to version {}", cfg.pinned_version))
+        }
+        "pinned" => Ok(()),
+        "stable" if channel != "stable" => Err(anyhow!(
+            "{channel} release {version} not accepted on the stable channel"
+        )),
+        "beta" if channel != "stable" && channel != "beta" => Err(anyhow!(
+            "{channel} release {version} not accepted on the beta channel"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 分块下载到暂存文件，不在内存中保留整个文件
+async fn download(url: &str, out: &mut Staging) -> Result<()> {
+    if let Some(path) = url.strip_prefix("file://") {
+        let mut file = fs::File::open(path).with_context(|| format!("failed to read {path}"))?;
+        io::copy(&mut file, out).with_context(|| format!("failed to read {path}"))?;
+        return Ok(());
+    }
+    let timeout = Duration::from_secs(crate::config::global().update.download_timeout_secs);
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    while let Some(chunk) = response.chunk().await? {
+        out.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+/// 还原补丁时旧二进制与解码窗口同时驻留内存，两者合计不超过 max_memory_mb 的一半，
+/// 其余留给 agent 自身；返回可用的 window log，0 表示不限制
+pub fn delta_window_log(base_len: u64, max_memory_mb: u32) -> Result<u32> {
+    if max_memory_mb == 0 {
+        return Ok(MAX_WINDOW_LOG);
+    }
+    let budget = u64::from(max_memory_mb) * 1024 * 1024 / 2;
+    let available = budget.saturating_sub(base_len).max(1);
+    let window_log = (63 - available.leading_zeros()).min(MAX_WINDOW_LOG);
+    // --patch-from 的窗口需覆盖整个旧文件
+    if window_log < MIN_WINDOW_LOG || 1u64 << window_log < base_len {
+        return Err(anyhow!(
+            "applying a delta against a {base_len} byte binary does not fit in max_memory_mb {max_memory_mb}"
+        ));
+    }
+    Ok(window_log)
+}
+
+/// 用旧二进制作为参考前缀还原新二进制，流式写入 `out`，返回写入的字节数
+pub fn apply_delta(
+    base: &[u8],
+    patch: impl Read,
+    out: &mut impl Write,
+    window_log_max: u32,
+) -> Result<u64> {
+    let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(io::BufReader::new(patch), base)
+        .context("invalid delta patch")?;
+    decoder.window_log_max(window_log_max)?;
+    io::copy(&mut decoder, out).context("failed to apply delta patch")
+}
+
+/// `agent.update` 命令处理器
+#[derive(Default)]
+struct UpdateHandler {
+    busy: Mutex<()>,
+}
+
+impl UpdateHandler {
+    async fn apply(
+        &self,
+        req: &UpdateRequest,
+        manifest: &UpdateManifest,
+        id: i64,
+        meta: &mut Value,
+    ) -> Result<()> {
+        let target = exe_path()?;
+        let delta = match &req.delta {
+            Some(delta) => match self.fetch_delta(manifest, delta).await {
+                Ok((staged, bytes, patch_bytes)) => {
+                    let saved = bytes.saturating_sub(patch_bytes);
+                    meta["bytes_saved"] = json!(saved);
+                    record_step(
+                        id,
+                        STATE_PENDING,
+                        meta,
+                        "delta_applied",
+                        json!({ "patch_bytes": patch_bytes, "bytes": bytes, "bytes_saved": saved }),
+                    );
+                    Some(staged)
+                }
+                Err(e) => {
+                    // 补丁无法应用（基准不符、内存预算不足、下载失败或还原结果校验失败）时退回完整下载
+                    tracing::warn!(update_id = id, error = %format!("{e:#}"), "delta update failed, downloading full binary");
+                    record_step(
+                        id,
+                        STATE_PENDING,
+                        meta,
+                        "delta_failed",
+                        json!(format!("{e:#}")),
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        let staged = match delta {
+            Some(staged) => staged,
+            None => {
+                let mut staging = Staging::create(staging_path(target))?;
+                download(&req.url, &mut staging).await?;
+                record_step(
+                    id,
+                    STATE_PENDING,
+                    meta,
+                    "downloaded",
+                    json!({ "bytes": staging.bytes }),
+                );
+                let sha256 = manifest.sha256.clone();
+                tokio::task::spawn_blocking(move || staging.finish(&sha256)).await??
+            }
+        };
+        record_step(id, STATE_PENDING, meta, "verified", Value::Null);
+
+        let prev = tokio::task::spawn_blocking(move || {
+            install(&staged, target).inspect_err(|_| {
+                let _ = fs::remove_file(&staged);
+            })
+        })
+        .await??;
+        meta["boot_attempts"] = json!(0);
+        record_step(
+            id,
+            STATE_PENDING,
+            meta,
+            "installed",
+            json!({ "path": target, "previous": prev }),
+        );
+        Ok(())
+    }
+
+    /// 下载补丁并基于当前二进制还原到暂存文件，返回已校验的暂存路径、新二进制及补丁大小
+    async fn fetch_delta(
+        &self,
+        manifest: &UpdateManifest,
+        delta: &DeltaRequest,
+    ) -> Result<(PathBuf, u64, u64)> {
+        if delta.from_version != VERSION {
+            return Err(anyhow!(
+                "delta is based on {}, running {VERSION}",
+                delta.from_version
+            ));
+        }
+        let target = exe_path()?;
+        let base_len = fs::metadata(target)
+            .with_context(|| format!("failed to stat {}", target.display()))?
+            .len();
+        let window_log = delta_window_log(base_len, crate::config::global().basic.max_memory_mb)?;
+        let mut patch = Staging::create(patch_path(target))?;
+        download(&delta.url, &mut patch).await?;
+        let patch_bytes = patch.bytes;
+        let from_sha256 = delta.from_sha256.clone();
+        let sha256 = manifest.sha256.clone();
+        tokio::task::spawn_blocking(move || {
+            let base =
+                fs::read(target).with_context(|| format!("failed to read {}", target.display()))?;
+            let actual = hex::encode(Sha256::digest(&base));
+            if !actual.eq_ignore_ascii_case(from_sha256.trim()) {
+                return Err(anyhow!(
+                    "installed binary does not match delta base: expected {from_sha256}, got {actual}"
+                ));
+            }
+            let mut staging = Staging::create(staging_path(target))?;
+            apply_delta(&base, patch.reader()?, &mut staging, window_log)?;
+            drop(base);
+            let bytes = staging.bytes;
+            Ok((staging.finish(&sha256)?, bytes, patch_bytes))
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl CommandHandler for UpdateHandler {
+    async fn handle(&self, cmd: &ControlCmd) -> Result<CmdResult> {
+        let req: UpdateRequest = match serde_json::from_slice(&cmd.payload) {
+            Ok(req) => req,
+            Err(e) => return Ok(reply("rejected", format!("invalid update request: {e}"))),
+        };
+        let update_cfg = &crate::config::global().update;
+        let manifest =
+            match verify_manifest(&req.manifest, &req.signature, &update_cfg.trusted_keys) {
+                Ok(manifest) => manifest,
+                Err(e) => return Ok(reply("rejected", format!("{e:#}"))),
+            };
+        if manifest.version == VERSION {
+            return Ok(reply("rejected", format!("already running {VERSION}")));
+        }
+        if let Err(e) = check_channel(update_cfg, &manifest.channel, &manifest.version) {
+            return Ok(reply("rejected", format!("{e:#}")));
+        }
+        if let Some(deferred) = maintenance::defer_outside_window(cmd)? {
+            return Ok(deferred);
+        }
+        let Ok(_guard) = self.busy.try_lock() else {
+            return Ok(reply("rejected", "update already in progress"));
+        };
+
+        // 先写入升级记录再进入 updating，记录失败时状态保持不变
+        let mut meta = json!({
+            "from": VERSION,
+            "url": req.url,
+            "sha256": manifest.sha256,
+            "channel": manifest.channel,
+            "cmd_id": cmd.id,
+        });
+        let id = match crate::storage::global().insert_update(
+            COMPONENT,
+            &manifest.version,
+            STATE_PENDING,
+            &meta,
+        ) {
+            Ok(id) => id,
+            Err(e) => return Ok(reply("failed", format!("failed to record update: {e:#}"))),
+        };
+        if let Err(e) = state::transition(AgentState::Updating, "agent update") {
+            record_step(
+                id,
+                STATE_FAILED,
+                &mut meta,
+                "rejected",
+                json!(format!("{e:#}")),
+            );
+            return Ok(reply("rejected", format!("{e:#}")));
+        }
+        tracing::info!(update_id = id, from = VERSION, to = %manifest.version, "agent update started");
+
+        if let Err(e) = self.apply(&req, &manifest, id, &mut meta).await {
+            tracing::warn!(update_id = id, error = %format!("{e:#}"), "agent update failed");
+            record_step(
+                id,
+                STATE_FAILED,
+                &mut meta,
+                "failed",
+                json!(format!("{e:#}")),
+            );
+            state::try_transition(AgentState::Online, "agent update failed");
+            return Ok(reply("failed", format!("{e:#}")));
+        }
+
+        // 等待其它命令结束后再重启，新版本启动后确认健康
+        tokio::spawn(async move {
+            if tokio::time::timeout(IDLE_WAIT, handler::wait_idle())
+                .await
+                .is_err()
+            {
+                tracing::warn!("commands still running, restarting for update anyway");
+            }
+            request_restart("agent update installed");
+        });
+        Ok(reply(
+            "restarting",
+            format!("installed {}", manifest.version),
+        ))
+    }
+}
+
+/// 升级子系统：注册 `agent.update` 并确认上一次升级（新版本 agent 或离线安装的插件）的结果
+pub struct Updater {
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Default for Updater {
+    fn default() -> Self {
+        Self {
+            shutdown: watch::Sender::new(false),
+            tasks: Vec::new(),
+        }
+    }
+}
+
+impl Updater {
+    /// 升级后首次启动：累计启动次数，超过上限直接回滚，否则等待健康确认
+    fn resume(&mut self, component: &'static str, mut record: UpdateRecord) -> Result<()> {
+        if component == COMPONENT && record.version != VERSION {
+            // 替换后未能以新版本启动（exec 失败或已被外部恢复）
+            record_step(
+                record.id,
+                STATE_FAILED,
+                &mut record.meta,
+                "failed",
+                json!(format!("agent restarted on version {VERSION}")),
+            );
+            return Ok(());
+        }
+        let attempts = record.meta["boot_attempts"].as_u64().unwrap_or(0) + 1;
+        record.meta["boot_attempts"] = json!(attempts);
+        record_step(
+            record.id,
+            STATE_PENDING,
+            &mut record.meta,
+            "started",
+            json!({ "attempt": attempts }),
+        );
+
+        let cfg = crate::config::global().update.clone();
+        if attempts > u64::from(cfg.max_boot_attempts) {
+            roll_back(component, record, "crash loop after update");
+            return Ok(());
+        }
+
+        let mut rx = self.shutdown.subscribe();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(cfg.health_deadline_secs);
+        // 离线 bundle 安装的站点可能永远连不上 master，只要求本地组件健康
+        let offline = record.meta["source"] == "bundle";
+        let plugins = record.meta.get("plugin_backup").is_some();
+        self.tasks.push(tokio::spawn(async move {
+            loop {
+                let online = state::current() == AgentState::Online;
+                if confirmed(&health::checker().snapshot(), online, offline, plugins) {
+                    tracing::info!(
+                        update_id = record.id,
+                        component,
+                        version = %record.version,
+                        "update confirmed"
+                    );
+                    record_step(
+                        record.id,
+                        STATE_APPLIED,
+                        &mut record.meta,
+                        "confirmed",
+                        Value::Null,
+                    );
+                    return;
+                }
+                if tokio::time::Instant::now() >= deadline {
+                    roll_back(component, record, "health deadline exceeded");
+                    return;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = rx.changed() => return,
+                }
+            }
+        }));
+        Ok(())
+    }
+}
+
+/// 确认升级只看关键组件：所有子系统已启动（health 最后启动）、存储已打开，
+/// 非离线安装还要求与 master 的流已连接，安装了插件时还要求插件已加载（安全模式下不加载）；
+/// 其它组件降级或停止（如已结束的 Transient 子进程）不影响确认
+fn confirmed(
+    components: &BTreeMap<String, health::Status>,
+    online: bool,
+    offline: bool,
+    plugins: bool,
+) -> bool {
+    let healthy = |name: &str| components.get(name) == Some(&health::Status::Healthy);
+    healthy("health")
+        && healthy("storage")
+        && (offline || (online && healthy("grpc")))
+        && (!plugins || healthy("plugins"))
+}
+
+/// 撤销升级并请求重启
+fn roll_back(component: &str, mut record: UpdateRecord, reason: &str) {
+    tracing::warn!(
+        update_id = record.id,
+        component,
+        version = %record.version,
+        reason,
+        "rolling back update"
+    );
+    match undo(component, &record) {
+        Ok(()) => {
+            record_step(
+                record.id,
+                STATE_ROLLED_BACK,
+                &mut record.meta,
+                "rolled_back",
+                json!(reason),
+            );
+            state::try_transition(AgentState::Updating, "rolling back update");
+            request_restart("update rolled back");
+        }
+        Err(e) => {
+            tracing::error!(update_id = record.id, component, error = %format!("{e:#}"), "update rollback failed");
+            record_step(
+                record.id,
+                STATE_FAILED,
+                &mut record.meta,
+                "rollback_failed",
+                json!(format!("{reason}: {e:#}")),
+            );
+        }
+    }
+}
+
+/// agent 升级恢复旧二进制及随之安装的插件；插件 bundle 只恢复插件
+fn undo(component: &str, record: &UpdateRecord) -> Result<()> {
+    if component != COMPONENT {
+        return restore_plugins(record);
+    }
+    rollback(exe_path()?)?;
+    if let Err(e) = restore_plugins(record) {
+        tracing::error!(update_id = record.id, error = %format!("{e:#}"), "failed to restore plugins");
+    }
+    Ok(())
+}
+
+/// 恢复 bundle 安装插件前的插件集合
+fn restore_plugins(record: &UpdateRecord) -> Result<()> {
+    let Some(backup) = record.meta.get("plugin_backup") else {
+        return Ok(());
+    };
+    installer::restore(&serde_json::from_value(backup.clone())?)
+}
+
+/// 由监督进程在 worker 崩溃循环时调用：新版本或新插件若在 Updater 子系统启动前就崩溃，
+/// 无法自行回滚，此时撤销升级。返回是否存在已安装但未确认的升级并已回滚
+#[cfg(unix)]
+pub(crate) fn roll_back_unconfirmed(storage: &Storage, reason: &str) -> Result<bool> {
+    let mut rolled_back = false;
+    for component in [COMPONENT, PLUGINS_COMPONENT] {
+        let Some(mut record) = storage.pending_update(component)? else {
+            continue;
+        };
+        // 仍在下载或校验，尚未替换任何文件
+        if record.meta.get("boot_attempts").is_none() {
+            continue;
+        }
+        tracing::warn!(
+            update_id = record.id,
+            component,
+            version = %record.version,
+            reason,
+            "rolling back update from supervisor"
+        );
+        let result = undo(component, &record);
+        let state = match &result {
+            Ok(()) => {
+                push_step(&mut record.meta, "rolled_back", json!(reason));
+                STATE_ROLLED_BACK
+            }
+            Err(e) => {
+                push_step(
+                    &mut record.meta,
+                    "rollback_failed",
+                    json!(format!("{reason}: {e:#}")),
+                );
+                STATE_FAILED
+            }
+        };
+        storage.set_update(record.id, state, &record.meta)?;
+        result?;
+        rolled_back = true;
+    }
+    Ok(rolled_back)
+}
+
+#[async_trait]
+impl Subsystem for Updater {
+    fn name(&self) -> &'static str {
+        "updater"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        exe_path()?;
+        ctx.client_mut()?
+            .register("agent.update", Arc::new(UpdateHandler::default()));
+        let storage = crate::storage::global();
+        for component in [COMPONENT, PLUGINS_COMPONENT] {
+            if let Some(record) = storage.pending_update(component)? {
+                self.resume(component, record)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.shutdown.send_replace(true);
+        for task in self.tasks.drain(..) {
+            task.await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(channel: &str, pinned_version: &str) -> UpdateConfig {
+        UpdateConfig {
+            channel: channel.to_string(),
+            pinned_version: pinned_version.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stable_channel_accepts_only_stable_releases() {
+        let cfg = channel("stable", "");
+        assert!(check_channel(&cfg, "stable", "1.2.0").is_ok());
+        assert!(check_channel(&cfg, "beta", "1.3.0-beta.1").is_err());
+        assert!(check_channel(&cfg, "nightly", "1.3.0-nightly").is_err());
+    }
+
+    #[test]
+    fn beta_channel_also_accepts_stable_releases() {
+        let cfg = channel("beta", "");
+        assert!(check_channel(&cfg, "stable", "1.2.0").is_ok());
+        assert!(check_channel(&cfg, "beta", "1.3.0-beta.1").is_ok());
+        assert!(check_channel(&cfg, "nightly", "1.3.0-nightly").is_err());
+    }
+
+    #[test]
+    fn pinned_channel_accepts_only_the_pinned_version() {
+        let cfg = channel("pinned", "1.2.0");
+        assert!(check_channel(&cfg, "stable", "1.2.0").is_ok());
+        assert!(check_channel(&cfg, "beta", "1.2.0").is_ok());
+        assert!(check_channel(&cfg, "stable", "1.2.1").is_err());
+    }
+
+    #[tokio::test]
+    async fn download_streams_into_a_verified_staging_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("warden-new");
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let target = dir.path().join("warden");
+
+        let mut staging = Staging::create(staging_path(&target)).unwrap();
+        download(&format!("file://{}", source.display()), &mut staging)
+            .await
+            .unwrap();
+        assert_eq!(staging.bytes, data.len() as u64);
+        let staged = staging.finish(&hex::encode(Sha256::digest(&data))).unwrap();
+        assert_eq!(fs::read(staged).unwrap(), data);
+    }
+
+    #[test]
+    fn staging_file_with_wrong_digest_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = staging_path(&dir.path().join("warden"));
+        let mut staging = Staging::create(path.clone()).unwrap();
+        staging.write_all(b"evil agent binary").unwrap();
+        let err = staging
+            .finish(&hex::encode(Sha256::digest(b"new agent binary")))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("sha256 mismatch"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn delta_window_fits_the_memory_budget() {
+        const MB: u64 = 1024 * 1024;
+        assert_eq!(delta_window_log(20 * MB, 0).unwrap(), MAX_WINDOW_LOG);
+        // 32 MB 的一半扣除 4 MB 旧二进制后余 12 MB，窗口取 8 MB
+        assert_eq!(delta_window_log(4 * MB, 32).unwrap(), 23);
+        // 旧二进制与覆盖它的窗口放不进预算时不使用补丁
+        assert!(delta_window_log(10 * MB, 32).is_err());
+        assert!(delta_window_log(20 * MB, 32).is_err());
+    }
+
+    fn components(entries: &[(&str, health::Status)]) -> BTreeMap<String, health::Status> {
+        entries
+            .iter()
+            .map(|(name, status)| (name.to_string(), status.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn finished_transient_child_does_not_block_confirmation() {
+        let components = components(&[
+            ("storage", health::Status::Healthy),
+            ("grpc", health::Status::Healthy),
+            ("supervisor.collector", health::Status::Healthy),
+            ("supervisor.collector/inventory", health::Status::Stopped),
+            (
+                "watchdog",
+                health::Status::Degraded("safe mode".to_string()),
+            ),
+            ("health", health::Status::Healthy),
+        ]);
+        assert!(confirmed(&components, true, false, false));
+    }
+
+    #[test]
+    fn confirmation_waits_for_startup_storage_and_stream() {
+        let all = [
+            ("storage", health::Status::Healthy),
+            ("grpc", health::Status::Healthy),
+            ("health", health::Status::Healthy),
+        ];
+        assert!(!confirmed(&components(&all[..2]), true, false, false));
+        assert!(!confirmed(&components(&all), false, false, false));
+        let mut missing_storage = components(&all);
+        missing_storage.insert("storage".to_string(), health::Status::Starting);
+        assert!(!confirmed(&missing_storage, true, false, false));
+
+        // 离线安装不要求连上 master
+        let mut disconnected = components(&all);
+        disconnected.insert(
+            "grpc".to_string(),
+            health::Status::Degraded("unreachable".to_string()),
+        );
+        assert!(!confirmed(&disconnected, false, false, false));
+        assert!(confirmed(&disconnected, false, true, false));
+    }
+
+    #[test]
+    fn installed_plugins_must_be_loaded_before_confirmation() {
+        let mut components = components(&[
+            ("storage", health::Status::Healthy),
+            ("health", health::Status::Healthy),
+        ]);
+        // 安全模式下插件不加载，不能确认 bundle 安装的插件
+        assert!(!confirmed(&components, false, true, true));
+        components.insert("plugins".to_string(), health::Status::Healthy);
+        assert!(confirmed(&components, false, true, true));
+    }
+}
@@ -1 +1,629 @@
+//! Process-level supervisor for `warden run --supervised`.
+//!
+//! The supervisor re-executes the agent binary as a worker process and keeps
+//! it alive: it restarts the worker when it exits abnormally, stops sending
+//! heartbeats over the inherited pipe (hang) or grows beyond
+//! `basic.max_memory_mb`, with exponential backoff between restarts. When the
+//! worker crashes `crash_loop_threshold` times within the window, the crash
+//! loop is recorded and the worker is restarted in safe mode (core subsystems
+//! only) instead of flapping. If the crash loop follows an update that was
+//! never confirmed, the previous binary is restored first. On SIGTERM the
+//! worker gets its current drain timeout plus every subsystem's stop timeout
+//! to shut down before it is killed.
+//!
+//! Supervision is only available on unix; the memory limit is enforced where
+//! `/proc` exists (Linux).
 
+use anyhow::Result;
+#[cfg(unix)]
+use {
+    crate::config::schema::WatchdogConfig,
+    crate::storage::Storage,
+    anyhow::{Context, anyhow},
+    serde_json::json,
+    std::collections::VecDeque,
+    std::ffi::OsString,
+    std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    std::os::unix::process::{CommandExt, ExitStatusExt},
+    std::path::Path,
+    std::process::{Child, Command, ExitStatus},
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::time::{Duration, Instant},
+};
+
+/// worker 继承的心跳管道写端描述符
+#[cfg(unix)]
+const HEARTBEAT_FD: RawFd = 3;
+
+/// 告知 worker 心跳管道描述符的环境变量
+#[cfg(unix)]
+const HEARTBEAT_FD_ENV: &str = "WARDEN_WATCHDOG_FD";
+
+/// 告知 worker 以安全模式运行的环境变量
+pub const SAFE_MODE_ENV: &str = "WARDEN_SAFE_MODE";
+
+/// 停止 worker 时在各子系统停止超时之外额外等待的时间（运行时关闭、日志刷盘）
+#[cfg(unix)]
+const STOP_SLACK: Duration = Duration::from_secs(5);
+
+/// 监督循环的检查间隔
+#[cfg(unix)]
+const POLL_INTERVAL_MS: i32 = 1000;
+
+#[cfg(unix)]
+static STOP: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_stop_signal(_: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+/// worker 是否运行在安全模式（由监督进程在检测到崩溃循环后设置）
+pub fn safe_mode() -> bool {
+    std::env::var_os(SAFE_MODE_ENV).is_some()
+}
+
+/// 在 worker 中启动心跳任务；未处于监督模式时不做任何事
+#[cfg(unix)]
+pub fn spawn_heartbeat() {
+    let Some(fd) = std::env::var(HEARTBEAT_FD_ENV)
+        .ok()
+        .and_then(|v| v.parse::<RawFd>().ok())
+    else {
+        return;
+    };
+    // 非阻塞写，监督进程读取不及时也不会阻塞运行时
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    let interval = Duration::from_secs(crate::config::global().watchdog.heartbeat_interval_secs);
+    // 心跳来自 tokio 任务，运行时卡死时心跳随之停止
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            unsafe {
+                libc::write(fd, b".".as_ptr().cast(), 1);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_heartbeat() {}
+
+/// worker 为何结束
+#[cfg(unix)]
+#[derive(Debug)]
+enum Outcome {
+    /// 收到停止信号，worker 已被停止
+    Stopped,
+    /// worker 自行退出
+    Exited(ExitStatus),
+    /// 心跳超时被杀死
+    Hung,
+    /// 内存超限被杀死
+    OutOfMemory(u64),
+}
+
+/// 重启退避与崩溃循环检测
+#[cfg(unix)]
+struct RestartPolicy {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    stable_after: Duration,
+    window: Duration,
+    threshold: usize,
+    backoff: Duration,
+    crashes: VecDeque<Instant>,
+}
+
+#[cfg(unix)]
+impl RestartPolicy {
+    fn new(wd: &WatchdogConfig) -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(wd.initial_backoff_secs),
+            max_backoff: Duration::from_secs(wd.max_backoff_secs),
+            stable_after: Duration::from_secs(wd.stable_after_secs),
+            window: Duration::from_secs(wd.crash_loop_window_secs),
+            threshold: wd.crash_loop_threshold as usize,
+            backoff: Duration::from_secs(wd.initial_backoff_secs),
+            crashes: VecDeque::new(),
+        }
+    }
+
+    /// 记录一次 worker 失败，返回窗口内的失败次数；稳定运行一段时间后重新计算退避
+    fn record_failure(&mut self, now: Instant, ran: Duration) -> usize {
+        if ran >= self.stable_after {
+            self.backoff = self.initial_backoff;
+        }
+        self.crashes.push_back(now);
+        while self
+            .crashes
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > self.window)
+        {
+            self.crashes.pop_front();
+        }
+        self.crashes.len()
+    }
+
+    /// 窗口内的失败次数是否达到崩溃循环阈值
+    fn crash_loop(&self) -> bool {
+        self.crashes.len() >= self.threshold
+    }
+
+    /// 返回本次重启前的等待时间，下次翻倍直至上限
+    fn next_backoff(&mut self) -> Duration {
+        let backoff = self.backoff;
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+        backoff
+    }
+
+    /// 回滚升级后重新统计
+    fn reset(&mut self) {
+        self.crashes.clear();
+        self.backoff = self.initial_backoff;
+    }
+}
+
+/// 以监督模式运行：启动并看护 worker 进程，直到收到 SIGTERM / SIGINT
+#[cfg(unix)]
+pub fn supervise() -> Result<()> {
+    let handler = on_stop_signal as extern "C" fn(libc::c_int) as *const () as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+    // 升级会替换磁盘上的二进制，之后 /proc/self/exe 指向已删除的文件，因此启动时解析一次
+    let exe = crate::agent::updater::exe_path()?;
+    let cfg = crate::config::global();
+    let wd = cfg.watchdog.clone();
+    let max_memory_kb = u64::from(cfg.basic.max_memory_mb) * 1024;
+    let storage = Storage::open(&cfg.basic.sqlite_path)
+        .inspect_err(|e| tracing::warn!(error = %format!("{e:#}"), "watchdog cannot record events"))
+        .ok();
+    let record = |kind: &str, payload: serde_json::Value| {
+        if let Some(storage) = &storage
+            && let Err(e) = storage.record_event(kind, &payload)
+        {
+            tracing::warn!(error = %format!("{e:#}"), "failed to record watchdog event");
+        }
+    };
+
+    let mut policy = RestartPolicy::new(&wd);
+    let mut safe = false;
+    loop {
+        let started = Instant::now();
+        let (mut child, heartbeats) = match spawn_worker(exe, safe) {
+            Ok(worker) => worker,
+            Err(e) => {
+                // 启动失败（如二进制正被替换）不结束监督进程，退避后重试
+                let backoff = policy.next_backoff();
+                tracing::error!(error = %format!("{e:#}"), retry_in_secs = backoff.as_secs(), "failed to spawn worker");
+                record(
+                    "watchdog.spawn_failed",
+                    json!({ "error": format!("{e:#}"), "safe_mode": safe }),
+                );
+                if sleep_unless_stopped(backoff) {
+                    return Ok(());
+                }
+                continue;
+            }
+        };
+        let pid = child.id();
+        tracing::info!(pid, safe_mode = safe, "worker started");
+        let outcome = monitor(&mut child, &heartbeats, &wd, max_memory_kb, || {
+            stop_grace(storage.as_ref(), safe)
+        })?;
+        let ran = started.elapsed();
+
+        let reason = match outcome {
+            Outcome::Stopped => {
+                tracing::info!(pid, "worker stopped, supervisor exiting");
+                return Ok(());
+            }
+            Outcome::Exited(status) if status.success() => {
+                tracing::info!(pid, "worker exited cleanly, supervisor exiting");
+                return Ok(());
+            }
+            Outcome::Exited(status) => match status.signal() {
+                Some(signal) => format!("killed by signal {signal}"),
+                None => format!("exited with code {}", status.code().unwrap_or(-1)),
+            },
+            Outcome::Hung => format!("no heartbeat for {}s", wd.hang_timeout_secs),
+            Outcome::OutOfMemory(rss_kb) => format!(
+                "rss {} MB exceeds max_memory_mb {}",
+                rss_kb / 1024,
+                cfg.basic.max_memory_mb
+            ),
+        };
+
+        let crashes = policy.record_failure(Instant::now(), ran);
+        let backoff = policy.next_backoff();
+        tracing::warn!(pid, reason = %reason, restart_in_secs = backoff.as_secs(), "worker failed");
+        record(
+            "watchdog.restart",
+            json!({ "pid": pid, "reason": reason, "uptime_secs": ran.as_secs(), "safe_mode": safe }),
+        );
+        if !safe && policy.crash_loop() {
+            // 新版本在 Updater 启动前崩溃时无法自行回滚，先恢复旧版本再重试
+            let rolled_back = storage.as_ref().map(|storage| {
+                crate::agent::updater::roll_back_unconfirmed(storage, "crash loop after update")
+            });
+            match rolled_back {
+                Some(Ok(true)) => {
+                    tracing::error!(
+                        crashes,
+                        "worker crash loop after update, update rolled back"
+                    );
+                    record(
+                        "watchdog.update_rolled_back",
+                        json!({ "crashes": crashes, "last_reason": reason }),
+                    );
+                    policy.reset();
+                    continue;
+                }
+                Some(Err(e)) => {
+                    tracing::error!(error = %format!("{e:#}"), "failed to roll back agent update")
+                }
+                _ => {}
+            }
+            safe = true;
+            tracing::error!(
+                crashes,
+                window_secs = wd.crash_loop_window_secs,
+                "worker crash loop detected, switching to safe mode"
+            );
+            record(
+                "watchdog.crash_loop",
+                json!({ "crashes": crashes, "window_secs": wd.crash_loop_window_secs, "last_reason": reason }),
+            );
+        }
+
+        if sleep_unless_stopped(backoff) {
+            return Ok(());
+        }
+    }
+}
+
+/// 停止 worker 时的等待时间：排空在途命令的时间加上各子系统的停止超时。
+/// 排空时间可能已被 master 远程调高，因此按 worker 当前生效的远程配置层重新计算
+#[cfg(unix)]
+fn stop_grace(storage: Option<&Storage>, safe: bool) -> Duration {
+    let layer = storage.and_then(|storage| {
+        storage
+            .active_config_layer()
+            .inspect_err(
+                |e| tracing::warn!(error = %format!("{e:#}"), "failed to read remote config layer"),
+            )
+            .ok()
+            .flatten()
+    });
+    let drain_timeout_secs = layer
+        .and_then(|active| {
+            crate::config::store()
+                .load_with_layer(Some(&active.layer))
+                .ok()
+        })
+        .unwrap_or_else(crate::config::global)
+        .basic
+        .drain_timeout_secs;
+    grace_for(drain_timeout_secs, safe)
+}
+
+#[cfg(unix)]
+fn grace_for(drain_timeout_secs: u64, safe: bool) -> Duration {
+    Duration::from_secs(drain_timeout_secs) + crate::agent::service::stop_budget(safe) + STOP_SLACK
+}
+
+#[cfg(not(unix))]
+pub fn supervise() -> Result<()> {
+    anyhow::bail!("--supervised is only supported on unix")
+}
+
+/// 以相同参数（去掉 --supervised）启动 worker，心跳管道写端映射到固定描述符；
+/// 返回 worker 进程及管道读端
+#[cfg(unix)]
+fn spawn_worker(exe: &Path, safe: bool) -> Result<(Child, OwnedFd)> {
+    let (read_end, write_end) = pipe()?;
+    let mut cmd = worker_command(exe, std::env::args_os().skip(1), safe);
+    let write_fd = write_end.as_raw_fd();
+    unsafe {
+        // dup2 得到的描述符不带 CLOEXEC，worker 升级后 exec 自身时仍可继续使用
+        cmd.pre_exec(move || {
+            let ok = if write_fd == HEARTBEAT_FD {
+                libc::fcntl(HEARTBEAT_FD, libc::F_SETFD, 0) == 0
+            } else {
+                libc::dup2(write_fd, HEARTBEAT_FD) >= 0
+            };
+            if !ok {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("failed to spawn worker {}", exe.display()))?;
+    drop(write_end);
+    Ok((child, read_end))
+}
+
+/// worker 的启动命令：去掉 --supervised，并通过环境变量传递心跳描述符与安全模式
+#[cfg(unix)]
+fn worker_command(exe: &Path, args: impl Iterator<Item = OsString>, safe: bool) -> Command {
+    let mut cmd = Command::new(exe);
+    cmd.args(args.filter(|a| a != "--supervised"))
+        .env(HEARTBEAT_FD_ENV, HEARTBEAT_FD.to_string());
+    if safe {
+        cmd.env(SAFE_MODE_ENV, "1");
+    } else {
+        cmd.env_remove(SAFE_MODE_ENV);
+    }
+    cmd
+}
+
+/// 监视 worker 直到其退出、挂起、内存超限或收到停止信号
+#[cfg(unix)]
+fn monitor(
+    child: &mut Child,
+    heartbeats: &OwnedFd,
+    wd: &WatchdogConfig,
+    max_memory_kb: u64,
+    grace: impl FnOnce() -> Duration,
+) -> Result<Outcome> {
+    let fd = heartbeats.as_raw_fd();
+    let hang_timeout = Duration::from_secs(wd.hang_timeout_secs);
+    let mut last_beat = Instant::now();
+    let mut pipe_open = true;
+    loop {
+        if STOP.load(Ordering::SeqCst) {
+            stop_worker(child, grace());
+            return Ok(Outcome::Stopped);
+        }
+        if let Some(status) = child.try_wait()? {
+            return Ok(Outcome::Exited(status));
+        }
+        if pipe_open {
+            match read_heartbeats(fd) {
+                Some(0) => pipe_open = false,
+                Some(_) => last_beat = Instant::now(),
+                None => {}
+            }
+        } else {
+            std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS as u64));
+        }
+        if last_beat.elapsed() > hang_timeout {
+            kill(child);
+            return Ok(Outcome::Hung);
+        }
+        if max_memory_kb > 0
+            && let Some(rss_kb) = rss_kb(child.id())
+            && rss_kb > max_memory_kb
+        {
+            kill(child);
+            return Ok(Outcome::OutOfMemory(rss_kb));
+        }
+    }
+}
+
+#[cfg(unix)]
+/// 等待心跳数据；返回 Some(0) 表示写端已全部关闭，None 表示超时
+fn read_heartbeats(fd: RawFd) -> Option<usize> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ready = unsafe { libc::poll(&mut pfd, 1, POLL_INTERVAL_MS) };
+    if ready <= 0 {
+        return None;
+    }
+    let mut buf = [0u8; 64];
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+    (n >= 0).then_some(n as usize)
+}
+
+#[cfg(unix)]
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0 as RawFd; 2];
+    #[cfg(target_os = "linux")]
+    let ok = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == 0;
+    // 没有 pipe2 的平台上分两步设置 CLOEXEC；监督进程是单线程的，不会在其间 fork
+    #[cfg(not(target_os = "linux"))]
+    let ok = unsafe {
+        libc::pipe(fds.as_mut_ptr()) == 0
+            && fds
+                .iter()
+                .all(|&fd| libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == 0)
+    };
+    if !ok {
+        return Err(anyhow!(std::io::Error::last_os_error()).context("failed to create pipe"));
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// 读取进程常驻内存（VmRSS），单位 kB
+#[cfg(target_os = "linux")]
+fn rss_kb(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+/// 没有 /proc 时不检查内存上限
+#[cfg(all(unix, not(target_os = "linux")))]
+fn rss_kb(_pid: u32) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+/// 转发 SIGTERM 让 worker 有序退出，超时后强制杀死
+fn stop_worker(child: &mut Child, grace: Duration) {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if matches!(child.try_wait(), Ok(Some(_))) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    tracing::warn!(
+        pid = child.id(),
+        grace_secs = grace.as_secs(),
+        "worker did not stop in time, killing"
+    );
+    kill(child);
+}
+
+#[cfg(unix)]
+/// 可被停止信号打断的等待，返回是否收到停止信号
+fn sleep_unless_stopped(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if STOP.load(Ordering::SeqCst) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    STOP.load(Ordering::SeqCst)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    fn policy() -> RestartPolicy {
+        RestartPolicy::new(&WatchdogConfig {
+            initial_backoff_secs: 1,
+            max_backoff_secs: 8,
+            stable_after_secs: 60,
+            crash_loop_threshold: 3,
+            crash_loop_window_secs: 100,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit_and_resets_after_a_stable_run() {
+        let mut policy = policy();
+        let now = Instant::now();
+        let backoffs: Vec<u64> = (0..5)
+            .map(|_| {
+                policy.record_failure(now, Duration::from_secs(1));
+                policy.next_backoff().as_secs()
+            })
+            .collect();
+        assert_eq!(backoffs, [1, 2, 4, 8, 8]);
+
+        policy.record_failure(now, Duration::from_secs(60));
+        assert_eq!(policy.next_backoff(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn crash_loop_counts_only_failures_within_the_window() {
+        let mut policy = policy();
+        let start = Instant::now();
+        let ran = Duration::from_secs(1);
+        assert_eq!(policy.record_failure(start, ran), 1);
+        assert_eq!(
+            policy.record_failure(start + Duration::from_secs(50), ran),
+            2
+        );
+        // 第一次失败已滑出 100 秒的窗口
+        assert_eq!(
+            policy.record_failure(start + Duration::from_secs(101), ran),
+            2
+        );
+        assert!(!policy.crash_loop());
+        assert_eq!(
+            policy.record_failure(start + Duration::from_secs(120), ran),
+            3
+        );
+        assert!(policy.crash_loop());
+
+        policy.reset();
+        assert!(!policy.crash_loop());
+        assert_eq!(policy.next_backoff(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn safe_mode_is_passed_to_the_worker() {
+        let args = || {
+            ["run", "--supervised", "-c", "c.yaml"]
+                .map(OsString::from)
+                .into_iter()
+        };
+        let env = |cmd: &Command| {
+            cmd.get_envs()
+                .find(|(key, _)| *key == OsStr::new(SAFE_MODE_ENV))
+                .map(|(_, value)| value.map(OsStr::to_owned))
+        };
+
+        let cmd = worker_command(Path::new("/bin/warden"), args(), true);
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            ["run", "-c", "c.yaml"].map(OsStr::new)
+        );
+        assert_eq!(env(&cmd), Some(Some(OsString::from("1"))));
+        // 崩溃循环前启动的 worker 不继承监督进程环境中的安全模式
+        let cmd = worker_command(Path::new("/bin/warden"), args(), false);
+        assert_eq!(env(&cmd), Some(None));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn worker_over_the_memory_limit_is_killed() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        // 持有写端，worker 既不发送心跳也不关闭管道
+        let (heartbeats, _write_end) = pipe().unwrap();
+        let outcome = monitor(
+            &mut child,
+            &heartbeats,
+            &WatchdogConfig::default(),
+            1,
+            || Duration::ZERO,
+        )
+        .unwrap();
+        assert!(matches!(outcome, Outcome::OutOfMemory(rss_kb) if rss_kb > 1));
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[test]
+    fn stop_grace_covers_the_drain_and_every_subsystem() {
+        crate::config::init_for_tests();
+        // 此前固定的 30 秒短于 gRPC 自身的停止超时
+        assert!(grace_for(30, false) > crate::agent::service::stop_budget(false));
+        assert!(grace_for(30, false) > Duration::from_secs(30 + 10));
+        assert!(grace_for(30, true) < grace_for(30, false));
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+        let drain_timeout_secs = crate::config::global().basic.drain_timeout_secs;
+        assert_eq!(
+            stop_grace(Some(&storage), false),
+            grace_for(drain_timeout_secs, false)
+        );
+        storage
+            .insert_config_layer(
+                "cmd-1",
+                &json!({ "basic": { "drain_timeout_secs": 300 } }),
+                crate::config::remote::STATE_APPLIED,
+            )
+            .unwrap();
+        assert_eq!(stop_grace(Some(&storage), false), grace_for(300, false));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::agent::identity::{self, Check};
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde_json::json;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct Id {
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        global = true,
+        help = "Path to the configuration file [default: config.yaml, may be absent]"
+    )]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub action: IdAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IdAction {
+    /// Show the persisted agent id and whether this host looks like a clone
+    Show,
+    /// Discard the persisted agent id and generate a new random one
+    Reset,
+}
+
+impl Id {
+    pub fn execute(&self) -> Result<()> {
+        crate::config::init_global(&crate::config::LoadOptions {
+            path: self.config.as_ref().map(PathBuf::from),
+            allow_missing: false,
+        })?;
+        let cfg = crate::config::global();
+        let path = identity::id_path(&cfg);
+        match self.action {
+            IdAction::Show => {
+                let Some(current) = identity::read(&path)? else {
+                    println!(
+                        "no agent id at {}; one is created on first start",
+                        path.display()
+                    );
+                    return Ok(());
+                };
+                let status = match identity::check(&current) {
+                    Check::Ok => "ok",
+                    Check::Reprovisioned => "machine-id changed, id is re-derived on next start",
+                    Check::Cloned => "cloned image suspected, run `warden id reset`",
+                };
+                println!("id:     {}", current.id);
+                println!("source: {}", current.source);
+                println!("file:   {}", path.display());
+                println!("status: {status}");
+            }
+            IdAction::Reset => {
+                let previous = identity::read(&path)?.map(|i| i.id);
+                // machine-id 派生的 id 重置后不变，因此总是随机生成
+                let fresh = identity::create(identity::SOURCE_GENERATED)?;
+                identity::write(&path, &fresh)?;
+                let storage = crate::storage::Storage::open(&cfg.basic.sqlite_path)?;
+                storage.record_event(
+                    "identity.reset",
+                    &json!({ "previous": previous, "id": fresh.id }),
+                )?;
+                println!("agent id reset: {}", fresh.id);
+                println!("restart the agent for the new id to take effect");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::agent::admin::{self, AdminRequest, LOG_LEVEL_CMD, LogLevelRequest};
+use anyhow::{Result, anyhow};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Parser)]
+pub struct Log {
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        global = true,
+        help = "Path to the configuration file [default: config.yaml, may be absent]"
+    )]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub action: LogAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LogAction {
+    /// Show the log filter of the running agent
+    Show,
+    /// Change the log filter of the running agent, e.g. `info,warden::grpc=trace`
+    Level {
+        #[arg(value_name = "FILTER")]
+        filter: String,
+        /// Revert to the configured level after this long, e.g. 90s, 15m, 1h
+        #[arg(long, value_name = "DURATION", value_parser = parse_ttl)]
+        ttl: Option<u64>,
+    },
+    /// Revert the log filter to the configured level
+    Reset,
+}
+
+/// 解析 TTL，单位 s / m / h，不带单位时按秒
+fn parse_ttl(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("invalid duration: {value}"))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(anyhow!("invalid duration unit in {value}, use s, m or h")),
+    };
+    number
+        .checked_mul(scale)
+        .ok_or_else(|| anyhow!("duration out of range: {value}"))
+}
+
+impl Log {
+    pub fn execute(&self) -> Result<()> {
+        crate::config::init_global(&crate::config::LoadOptions {
+            path: self.config.as_ref().map(PathBuf::from),
+            allow_missing: false,
+        })?;
+        let socket = crate::config::global().basic.admin_socket.clone();
+        if socket.is_empty() {
+            return Err(anyhow!("basic.admin_socket is not configured"));
+        }
+        let payload = match &self.action {
+            LogAction::Show => LogLevelRequest::default(),
+            LogAction::Level { filter, ttl } => LogLevelRequest {
+                filter: filter.clone(),
+                ttl_secs: ttl.unwrap_or_default(),
+                reset: false,
+            },
+            LogAction::Reset => LogLevelRequest {
+                reset: true,
+                ..Default::default()
+            },
+        };
+        let response = admin::request(
+            Path::new(&socket),
+            &AdminRequest {
+                cmd: LOG_LEVEL_CMD.to_string(),
+                payload: serde_json::to_value(payload)?,
+            },
+        )?;
+        match response.status.as_str() {
+            "ok" | "applied" => {
+                println!("{}", response.message);
+                Ok(())
+            }
+            status => Err(anyhow!("{status}: {}", response.message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ttl_scales_units_and_rejects_overflow() {
+        assert_eq!(parse_ttl("90").unwrap(), 90);
+        assert_eq!(parse_ttl("5m").unwrap(), 300);
+        assert_eq!(parse_ttl(" 2h ").unwrap(), 7200);
+        assert!(parse_ttl("3d").is_err());
+        assert!(parse_ttl("m").is_err());
+
+        let huge = format!("{}h", u64::MAX / 3600 + 1);
+        let err = parse_ttl(&huge).unwrap_err();
+        assert!(err.to_string().contains("out of range"), "{err}");
+        assert!(parse_ttl(&format!("{}m", u64::MAX)).is_err());
+    }
+}
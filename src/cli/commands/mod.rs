@@ -1,9 +1,21 @@
+mod id;
+mod log;
 mod run;
+mod update;
 
 use clap::Subcommand;
+use id::Id;
+use log::Log;
 use run::Run;
+use update::Update;
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     Run(Run),
+    /// Show or reset the persistent agent id
+    Id(Id),
+    /// Apply offline update bundles
+    Update(Update),
+    /// Inspect or change the log filter of the running agent
+    Log(Log),
 }
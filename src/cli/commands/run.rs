@@ -1,4 +1,6 @@
+use anyhow::Result;
 use clap::Parser;
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 pub struct Run {
@@ -6,18 +8,60 @@ pub struct Run {
         short,
         long,
         value_name = "FILE",
-        default_value = "config.yaml",
-        help = "Path to the configuration file"
+        help = "Path to the configuration file [default: config.yaml, may be absent]"
     )]
-    pub config: String,
+    pub config: Option<String>,
+
+    #[arg(
+        long,
+        help = "Start with defaults when the given configuration file does not exist"
+    )]
+    pub allow_missing_config: bool,
+
+    #[arg(
+        long,
+        help = "Run the agent under a supervisor process that restarts it on crash, hang or memory overrun"
+    )]
+    pub supervised: bool,
 }
 
 impl Run {
-    pub fn execute(&self) {
+    pub fn execute(&self) -> Result<()> {
         // 初始化全局配置
-        crate::config::init_global_from_file(self.config.clone()).unwrap();
-        // 初始化全局日志（基于配置）
+        let report = crate::config::init_global(&crate::config::LoadOptions {
+            path: self.config.as_ref().map(PathBuf::from),
+            allow_missing: self.allow_missing_config,
+        })
+        .map_err(
+            |e| match e.downcast_ref::<crate::config::ConfigNotFound>() {
+                // 只有 run 支持以默认配置启动
+                Some(missing) => {
+                    anyhow::anyhow!("{missing} (use --allow-missing-config to start with defaults)")
+                }
+                None => e,
+            },
+        )?;
+        // 初始化全局日志（基于配置）；监督进程只输出到 stderr，日志文件由 worker 写入与轮转
         let cfg = crate::config::global();
-        let _ = crate::telemetry::logging::init_global_logging(&cfg.telemetry);
+        if self.supervised {
+            let telemetry = crate::telemetry::logging::supervisor_config(&cfg.telemetry)?;
+            crate::telemetry::logging::init_global_logging(&telemetry)?;
+        } else {
+            crate::telemetry::logging::init_global_logging(&cfg.telemetry)?;
+        }
+        // panic 时记录事件并刷写日志文件
+        crate::agent::crash::install_panic_hook(&cfg.basic.sqlite_path);
+        tracing::info!(sources = ?report.sources, "configuration loaded");
+        for skipped in &report.skipped {
+            tracing::warn!(source = %skipped, "configuration source skipped");
+        }
+        if self.supervised {
+            // 监督进程只看护 worker，不启动任何子系统
+            let result = crate::agent::watchdog::supervise();
+            crate::telemetry::logging::flush_global();
+            return result;
+        }
+        // 启动 agent 并运行至收到退出信号
+        crate::agent::service::run()
     }
 }
@@ -0,0 +1,62 @@
+use crate::agent::bundle::{self, Applied};
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+pub struct Update {
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        global = true,
+        help = "Path to the configuration file [default: config.yaml, may be absent]"
+    )]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub action: UpdateAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UpdateAction {
+    /// Verify and install a signed offline update bundle (agent binary and/or plugins)
+    Apply {
+        #[arg(value_name = "BUNDLE")]
+        bundle: PathBuf,
+    },
+}
+
+impl Update {
+    pub fn execute(&self) -> Result<()> {
+        crate::config::init_global(&crate::config::LoadOptions {
+            path: self.config.as_ref().map(PathBuf::from),
+            allow_missing: false,
+        })?;
+        let cfg = crate::config::global();
+        match &self.action {
+            UpdateAction::Apply { bundle: path } => {
+                let bundle = bundle::open(path, &cfg.update.trusted_keys)?;
+                crate::storage::init_global(&cfg.basic.sqlite_path)?;
+                let source = path.canonicalize().unwrap_or_else(|_| path.clone());
+                match bundle::apply(&bundle, &source)? {
+                    Applied::Agent { id, version } => {
+                        println!("agent {version} installed (update {id})");
+                        println!(
+                            "restart the agent to switch; it is rolled back unless healthy within {}s",
+                            cfg.update.health_deadline_secs
+                        );
+                    }
+                    Applied::Plugins { id, count } => {
+                        println!("{count} plugin(s) installed (update {id})");
+                        println!(
+                            "restart the agent to load them; they are restored unless healthy within {}s",
+                            cfg.update.health_deadline_secs
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
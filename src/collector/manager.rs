@@ -1 +1,54 @@
+//! Collector manager: owns the registered collectors and supervises their
+//! scheduling tasks, so a panicking collector is restarted on its own.
 
+use crate::agent::service::{Context, Subsystem};
+use crate::agent::supervisor::{ChildSpec, Restart, Strategy, Supervisor, SupervisorHandle};
+use crate::collector::metrics::LoggingMetrics;
+use crate::collector::{Collector, scheduler};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+pub struct CollectorManager {
+    collectors: Vec<Arc<dyn Collector>>,
+    supervisor: Option<SupervisorHandle>,
+}
+
+impl Default for CollectorManager {
+    fn default() -> Self {
+        Self {
+            collectors: vec![Arc::new(LoggingMetrics)],
+            supervisor: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Subsystem for CollectorManager {
+    fn name(&self) -> &'static str {
+        "collectors"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        let mut supervisor = Supervisor::new("collectors", Strategy::OneForOne);
+        for collector in &self.collectors {
+            let collector = collector.clone();
+            let outbound = ctx.outbound.clone();
+            // 采集循环只在 outbound 关闭时正常返回，此时无需重启
+            supervisor = supervisor.child(ChildSpec::new(
+                collector.name(),
+                Restart::Transient,
+                move |shutdown| scheduler::run(collector.clone(), outbound.clone(), shutdown),
+            ));
+        }
+        self.supervisor = Some(supervisor.spawn());
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.stop().await;
+        }
+        Ok(())
+    }
+}
@@ -1 +1,43 @@
+//! Agent self metrics collected from the logging pipeline.
 
+use crate::collector::Collector;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+/// 日志文件写入队列：排队行数、字节数与丢弃计数，合计及按 sink 分列
+pub struct LoggingMetrics;
+
+#[async_trait]
+impl Collector for LoggingMetrics {
+    fn name(&self) -> &'static str {
+        "logging"
+    }
+
+    async fn collect(&self) -> Result<serde_json::Value> {
+        let stats = crate::telemetry::logging::queue_stats().unwrap_or_default();
+        let sinks: serde_json::Map<String, serde_json::Value> =
+            crate::telemetry::logging::sink_queue_stats()
+                .into_iter()
+                .map(|(name, s)| {
+                    (
+                        name,
+                        json!({
+                            "queued": s.queued,
+                            "queued_bytes": s.queued_bytes,
+                            "dropped_total": s.dropped,
+                            "dropped_errors_total": s.dropped_errors,
+                        }),
+                    )
+                })
+                .collect();
+        Ok(json!({
+            "queued": stats.queued,
+            "queued_bytes": stats.queued_bytes,
+            "capacity": stats.capacity,
+            "dropped_total": stats.dropped,
+            "dropped_errors_total": stats.dropped_errors,
+            "sinks": sinks,
+        }))
+    }
+}
@@ -1,4 +1,14 @@
 mod cache;
-mod manager;
+pub mod manager;
 mod metrics;
 mod scheduler;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// 数据采集器，由 scheduler 按 collector.interval_secs 周期调用
+#[async_trait]
+pub trait Collector: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn collect(&self) -> Result<serde_json::Value>;
+}
@@ -1 +1,47 @@
+//! Periodic collection loop; the interval follows `collector.interval_secs` at runtime.
 
+use crate::collector::Collector;
+use crate::grpc::client::Outbound;
+use crate::grpc::proto::{CollectData, agent_message};
+use crate::utils::time::now_millis;
+use anyhow::Result;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// 采集循环，由 CollectorManager 的监督者启动，panic 后会被重新启动
+pub async fn run(
+    collector: Arc<dyn Collector>,
+    outbound: Outbound,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut cfg_rx = crate::config::subscribe();
+    loop {
+        let interval = Duration::from_secs(cfg_rx.borrow_and_update().collector.interval_secs);
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            // 间隔变更后立即按新间隔重新计时
+            _ = cfg_rx.changed() => continue,
+            _ = shutdown.changed() => return Ok(()),
+        }
+        let name = collector.name();
+        match collector.collect().await {
+            Ok(data) => {
+                let payload = json!({ "collector": name, "data": data });
+                let body = agent_message::Body::Collect(CollectData {
+                    id: name.to_string(),
+                    payload: payload.to_string().into_bytes(),
+                    ts: now_millis(),
+                    ..Default::default()
+                });
+                if outbound.send(body).await.is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) => {
+                tracing::warn!(collector = name, error = %format!("{e:#}"), "collection failed")
+            }
+        }
+    }
+}
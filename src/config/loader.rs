@@ -1,44 +1,246 @@
 //! Config loader: load and validate application config from file or environment.
 
+use crate::config::resolve;
 use crate::config::schema::Config as AppConfig;
 use anyhow::{Context, Result};
 use config::{Config as RawConfig, Environment, File};
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+/// 默认配置文件路径（未显式指定时使用，允许不存在）
+pub const DEFAULT_CONFIG_PATH: &str = "config.yaml";
+
+/// 配置加载选项
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// 显式指定的配置文件路径（--config），为 None 时使用默认路径
+    pub path: Option<PathBuf>,
+    /// 显式指定的配置文件不存在时回退到默认值，而不是报错
+    pub allow_missing: bool,
+}
+
+/// 实际参与合并的配置来源，按优先级从低到高排列，用于启动时记录日志。
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub sources: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// 显式指定的配置文件不存在；调用方可据此提示如何以默认配置启动
+#[derive(Debug)]
+pub struct ConfigNotFound {
+    pub path: PathBuf,
+    pub from_env: bool, // 来自 WARDEN_CONFIG_PATH 而非 --config
+}
+
+impl fmt::Display for ConfigNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.from_env {
+            write!(
+                f,
+                "config file from WARDEN_CONFIG_PATH not found: {}",
+                self.path.display()
+            )
+        } else {
+            write!(f, "config file not found: {}", self.path.display())
+        }
+    }
+}
+
+impl std::error::Error for ConfigNotFound {}
+
 /// 加载配置文件，支持默认值、环境变量覆盖和校验。
-pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<AppConfig> {
+/// `remote_layer` 为 master 下发并持久化的配置层，位于文件与环境变量之间。
+pub fn load(
+    opts: &LoadOptions,
+    remote_layer: Option<&serde_json::Value>,
+) -> Result<(AppConfig, LoadReport)> {
+    let mut report = LoadReport::default();
+
     // 默认配置
     let default = AppConfig::default();
     let mut builder = RawConfig::builder();
     let default_str = serde_json::to_string(&default)?;
     builder = builder.add_source(File::from_str(&default_str, config::FileFormat::Json));
+    report.sources.push("defaults".to_string());
 
-    // 优先使用环境变量指定的配置文件
-    let mut env_path = std::env::var("WARDEN_CONFIG_PATH")
-        .ok()
-        .map(std::path::PathBuf::from);
-    if let Some(ref p) = env_path {
-        if !p.exists() {
-            env_path = None; // 不存在则忽略
-        }
+    let env_path = std::env::var_os("WARDEN_CONFIG_PATH").map(PathBuf::from);
+    if let Some(path) = resolve_file_path(opts, env_path, &mut report)? {
+        builder = builder.add_source(File::from(path.as_path()).required(true));
+        report.sources.push(format!("file:{}", path.display()));
     }
-    let path_ref: &Path = env_path.as_deref().unwrap_or_else(|| path.as_ref());
-    if path_ref.exists() {
-        builder = builder.add_source(File::from(path_ref));
+
+    // 远程配置层：其中的值按字面量使用，引用只在本地来源中解析
+    if let Some(layer) = remote_layer {
+        let mut layer = layer.clone();
+        resolve::escape_references(&mut layer);
+        builder = builder.add_source(File::from_str(&layer.to_string(), config::FileFormat::Json));
+        report.sources.push("remote".to_string());
     }
 
     // 环境变量覆盖配置项
     builder = builder.add_source(Environment::with_prefix("WARDEN").separator("_"));
-    let raw_cfg = builder.build().context("Failed to build config")?;
+    let mut env_keys: Vec<String> = std::env::vars()
+        .map(|(k, _)| k)
+        .filter(|k| k.starts_with("WARDEN_") && k != "WARDEN_CONFIG_PATH")
+        .collect();
+    if !env_keys.is_empty() {
+        env_keys.sort();
+        report.sources.push(format!("env:{}", env_keys.join(",")));
+    }
+
+    let mut raw_cfg = builder.build().context("Failed to build config")?;
+    // 解析 ${file:...} / ${env:...} 引用
+    resolve::resolve_value(&mut raw_cfg.cache).context("Failed to resolve config references")?;
     let cfg: AppConfig = raw_cfg
         .try_deserialize()
         .context("Failed to deserialize config")?;
     cfg.validate().context("Config validation failed")?;
-    Ok(cfg)
+    Ok((cfg, report))
+}
+
+/// 选择要加载的配置文件：WARDEN_CONFIG_PATH 优先，其次 --config，最后默认路径。
+/// 显式指定的路径不存在时报错，除非设置了 allow_missing。
+fn resolve_file_path(
+    opts: &LoadOptions,
+    env_path: Option<PathBuf>,
+    report: &mut LoadReport,
+) -> Result<Option<PathBuf>> {
+    if let Some(env_path) = env_path {
+        if env_path.exists() {
+            return Ok(Some(env_path));
+        }
+        if !opts.allow_missing {
+            return Err(ConfigNotFound {
+                path: env_path,
+                from_env: true,
+            }
+            .into());
+        }
+        report.skipped.push(format!(
+            "WARDEN_CONFIG_PATH={} (not found)",
+            env_path.display()
+        ));
+    }
+
+    match &opts.path {
+        Some(path) if path.exists() => Ok(Some(path.clone())),
+        Some(path) if opts.allow_missing => {
+            report
+                .skipped
+                .push(format!("file:{} (not found)", path.display()));
+            Ok(None)
+        }
+        Some(path) => Err(ConfigNotFound {
+            path: path.clone(),
+            from_env: false,
+        }
+        .into()),
+        None => {
+            let path = Path::new(DEFAULT_CONFIG_PATH);
+            if path.exists() {
+                Ok(Some(path.to_path_buf()))
+            } else {
+                report
+                    .skipped
+                    .push(format!("file:{} (not found)", path.display()));
+                Ok(None)
+            }
+        }
+    }
 }
 
 /// 加载配置并返回 Arc 包装，便于多处共享。
-pub fn load_arc_from_file<P: AsRef<Path>>(path: P) -> Result<Arc<AppConfig>> {
-    Ok(Arc::new(load_from_file(path)?))
+pub fn load_arc(
+    opts: &LoadOptions,
+    remote_layer: Option<&serde_json::Value>,
+) -> Result<(Arc<AppConfig>, LoadReport)> {
+    let (cfg, report) = load(opts, remote_layer)?;
+    Ok((Arc::new(cfg), report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(path: &Path, allow_missing: bool) -> LoadOptions {
+        LoadOptions {
+            path: Some(path.to_path_buf()),
+            allow_missing,
+        }
+    }
+
+    fn not_found(err: &anyhow::Error) -> &ConfigNotFound {
+        err.downcast_ref::<ConfigNotFound>().unwrap()
+    }
+
+    #[test]
+    fn missing_config_path_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.yaml");
+        let err = resolve_file_path(&opts(&missing, false), None, &mut LoadReport::default())
+            .unwrap_err();
+        assert_eq!(not_found(&err).path, missing);
+        assert!(!not_found(&err).from_env);
+    }
+
+    #[test]
+    fn allow_missing_skips_the_file_and_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.yaml");
+        let mut report = LoadReport::default();
+        let path = resolve_file_path(&opts(&missing, true), None, &mut report).unwrap();
+        assert!(path.is_none());
+        assert_eq!(
+            report.skipped,
+            [format!("file:{} (not found)", missing.display())]
+        );
+
+        let (cfg, report) = load(&opts(&missing, true), None).unwrap();
+        assert_eq!(report.sources[0], "defaults");
+        assert!(!report.sources.iter().any(|s| s.starts_with("file:")));
+        assert_eq!(
+            cfg.basic.drain_timeout_secs,
+            AppConfig::default().basic.drain_timeout_secs
+        );
+    }
+
+    #[test]
+    fn missing_env_config_path_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.yaml");
+        std::fs::write(&config, "basic: {}\n").unwrap();
+        let missing = dir.path().join("missing.yaml");
+        let err = resolve_file_path(
+            &opts(&config, false),
+            Some(missing.clone()),
+            &mut LoadReport::default(),
+        )
+        .unwrap_err();
+        assert_eq!(not_found(&err).path, missing);
+        assert!(not_found(&err).from_env);
+
+        // allow_missing 时回退到 --config
+        let mut report = LoadReport::default();
+        let path = resolve_file_path(&opts(&config, true), Some(missing), &mut report).unwrap();
+        assert_eq!(path, Some(config));
+        assert_eq!(report.skipped.len(), 1);
+    }
+
+    #[test]
+    fn env_config_path_takes_precedence_over_the_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        let from_flag = dir.path().join("flag.yaml");
+        let from_env = dir.path().join("env.yaml");
+        std::fs::write(&from_flag, "basic: {}\n").unwrap();
+        std::fs::write(&from_env, "basic: {}\n").unwrap();
+        let path = resolve_file_path(
+            &opts(&from_flag, false),
+            Some(from_env.clone()),
+            &mut LoadReport::default(),
+        )
+        .unwrap();
+        assert_eq!(path, Some(from_env));
+    }
 }
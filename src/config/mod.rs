@@ -1,24 +1,98 @@
 mod loader;
+pub mod remote;
+mod resolve;
 pub mod schema;
+pub mod secret;
 
 use anyhow::Result;
 use once_cell::sync::OnceCell;
-use std::path::Path;
 use std::sync::Arc;
+use tokio::sync::watch;
 
-static GLOBAL_CONFIG: OnceCell<Arc<schema::Config>> = OnceCell::new();
+pub use loader::{ConfigNotFound, LoadOptions, LoadReport};
 
-pub fn init_global_from_file<P: AsRef<Path>>(path: P) -> Result<()> {
-    let arc_cfg = loader::load_arc_from_file(path)?;
+/// 配置存储：保留加载选项以便叠加远程配置层后重新加载，变更通过 watch 通知订阅者
+pub struct ConfigStore {
+    opts: LoadOptions,
+    tx: watch::Sender<Arc<schema::Config>>,
+}
+
+impl ConfigStore {
+    /// 按加载选项加载配置，返回实际加载的配置来源
+    pub fn load(opts: &LoadOptions) -> Result<(Self, LoadReport)> {
+        let (arc_cfg, report) = loader::load_arc(opts, None)?;
+        let store = Self {
+            opts: opts.clone(),
+            tx: watch::Sender::new(arc_cfg),
+        };
+        Ok((store, report))
+    }
+
+    pub fn current(&self) -> Arc<schema::Config> {
+        self.tx.borrow().clone()
+    }
+
+    /// 订阅配置变更
+    pub fn subscribe(&self) -> watch::Receiver<Arc<schema::Config>> {
+        self.tx.subscribe()
+    }
+
+    /// 以给定的远程配置层重新加载并校验配置（不发布）
+    pub fn load_with_layer(
+        &self,
+        layer: Option<&serde_json::Value>,
+    ) -> Result<Arc<schema::Config>> {
+        let (cfg, _) = loader::load_arc(&self.opts, layer)?;
+        Ok(cfg)
+    }
+
+    /// 发布新的配置并通知订阅者
+    pub fn publish(&self, cfg: Arc<schema::Config>) {
+        self.tx.send_replace(cfg);
+    }
+}
+
+static GLOBAL_CONFIG: OnceCell<Arc<ConfigStore>> = OnceCell::new();
+
+/// 初始化全局配置，返回实际加载的配置来源
+pub fn init_global(opts: &LoadOptions) -> Result<LoadReport> {
+    let (store, report) = ConfigStore::load(opts)?;
     GLOBAL_CONFIG
-        .set(arc_cfg)
+        .set(Arc::new(store))
         .map_err(|_| anyhow::anyhow!("Global config already initialized"))?;
-    Ok(())
+    Ok(report)
 }
 
-pub fn global() -> Arc<schema::Config> {
+/// 测试中以默认配置初始化全局配置，可重复调用
+#[cfg(test)]
+pub(crate) fn init_for_tests() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        init_global(&LoadOptions {
+            path: None,
+            allow_missing: true,
+        })
+        .unwrap();
+    });
+}
+
+/// 全局配置存储
+pub fn store() -> Arc<ConfigStore> {
     GLOBAL_CONFIG
         .get()
         .expect("Global config not initialized")
         .clone()
 }
+
+fn global_state() -> &'static ConfigStore {
+    GLOBAL_CONFIG.get().expect("Global config not initialized")
+}
+
+pub fn global() -> Arc<schema::Config> {
+    global_state().current()
+}
+
+/// 订阅配置变更
+pub fn subscribe() -> watch::Receiver<Arc<schema::Config>> {
+    global_state().subscribe()
+}
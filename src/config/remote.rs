@@ -0,0 +1,470 @@
+//! Remote configuration: patches pushed by the master, persisted as a layer in SQLite.
+//!
+//! Each accepted patch is merged (RFC 7396 merge patch) into the current remote
+//! layer, validated through the regular loader, persisted and published. When a
+//! patch touches `grpc` or `tls`, the layer stays `pending` until the agent has
+//! reconnected; otherwise it is rolled back after `grpc.rollback_grace_secs`.
+//! Further patches are refused while a layer is pending, so a rollback never
+//! has to unwind layers merged on top of it. A patch may only touch the paths in
+//! [`REMOTE_PATHS`]; update trust, file paths and identity stay local-only.
+//!
+//! [`RemoteConfigSubsystem`] restores the persisted layer at startup and
+//! registers the `config.patch` and `labels.update` commands.
+
+use crate::agent::labels::LabelsUpdateHandler;
+use crate::agent::service::{Context, Subsystem};
+use crate::config::ConfigStore;
+use crate::config::schema::Config;
+use crate::grpc::client::Outbound;
+use crate::grpc::handler::{CommandHandler, reply};
+use crate::grpc::proto::{CmdResult, ControlCmd, agent_message};
+use crate::storage::Storage;
+use crate::utils::time::now_millis;
+use anyhow::{Context as _, Result, bail};
+use async_trait::async_trait;
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, watch};
+
+pub const STATE_PENDING: &str = "pending";
+pub const STATE_APPLIED: &str = "applied";
+pub const STATE_ROLLED_BACK: &str = "rolled_back";
+
+/// master 可以修改的配置路径（含其子项）。升级信任（update.*）、文件路径、
+/// 身份与脱敏规则只能在本地配置中修改
+pub const REMOTE_PATHS: &[&str] = &[
+    "basic.labels",
+    "basic.drain_timeout_secs",
+    "grpc",
+    "tls",
+    "telemetry.log_level",
+    "telemetry.log_format",
+    "telemetry.log_forward",
+    "collector",
+    "supervisor",
+    "maintenance",
+];
+
+/// 一次补丁应用的结果
+pub struct Applied {
+    pub layer_id: i64,
+    /// 是否修改了连接相关配置，需要等待重连确认
+    pub needs_confirm: bool,
+}
+
+pub struct RemoteConfig {
+    storage: Arc<Storage>,
+    /// 叠加远程配置层并发布的目标，运行时为全局配置
+    config: Arc<ConfigStore>,
+    lock: Mutex<()>,
+}
+
+impl RemoteConfig {
+    pub fn new(storage: Arc<Storage>, config: Arc<ConfigStore>) -> Self {
+        Self {
+            storage,
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// 启动时恢复已持久化的远程配置层，返回仍待确认的记录 id
+    pub fn restore(&self) -> Result<Option<i64>> {
+        let Some(active) = self.storage.active_config_layer()? else {
+            return Ok(None);
+        };
+        match check_patch(&active.layer)
+            .and_then(|()| self.config.load_with_layer(Some(&active.layer)))
+        {
+            Ok(cfg) => {
+                self.config.publish(cfg);
+                tracing::info!(layer_id = active.id, cmd_id = %active.cmd_id, state = %active.state, "remote config layer restored");
+                Ok((active.state == STATE_PENDING).then_some(active.id))
+            }
+            Err(e) => {
+                // 持久化的配置层已不再有效（例如本地文件变更），回退到上一层
+                tracing::warn!(layer_id = active.id, error = %format!("{e:#}"), "persisted remote config invalid, rolling back");
+                self.rollback_layer(active.id)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// 合并补丁、校验、持久化并发布
+    pub async fn apply(&self, cmd_id: &str, patch: &Value) -> Result<Applied> {
+        check_patch(patch)?;
+        let _guard = self.lock.lock().await;
+        let current = self.config.current();
+        let active = self.storage.active_config_layer()?;
+        if let Some(pending) = active.as_ref().filter(|l| l.state == STATE_PENDING) {
+            bail!(
+                "config layer {} from command {} is still awaiting reconnect confirmation",
+                pending.id,
+                pending.cmd_id
+            );
+        }
+        let mut layer = active
+            .map(|l| l.layer)
+            .unwrap_or_else(|| Value::Object(Map::new()));
+        merge_patch(&mut layer, patch);
+
+        let cfg = self
+            .config
+            .load_with_layer(Some(&layer))
+            .context("config patch rejected")?;
+        let needs_confirm = touches_connection(&current, &cfg);
+        let state = if needs_confirm {
+            STATE_PENDING
+        } else {
+            STATE_APPLIED
+        };
+        let layer_id = self.storage.insert_config_layer(cmd_id, &layer, state)?;
+        self.config.publish(cfg);
+        self.storage.record_event(
+            "config.applied",
+            &json!({ "cmd_id": cmd_id, "layer_id": layer_id, "state": state }),
+        )?;
+        tracing::info!(cmd_id = %cmd_id, layer_id, state, "remote config applied");
+        Ok(Applied {
+            layer_id,
+            needs_confirm,
+        })
+    }
+
+    /// 等待新的连接建立；超时未连上则回滚到上一层。返回是否已确认。
+    pub async fn confirm_or_rollback(
+        &self,
+        layer_id: i64,
+        mut connections: watch::Receiver<u64>,
+        baseline: u64,
+    ) -> Result<bool> {
+        let grace = Duration::from_secs(self.config.current().grpc.rollback_grace_secs);
+        let connected = tokio::time::timeout(grace, connections.wait_for(|n| *n > baseline))
+            .await
+            .is_ok_and(|r| r.is_ok());
+        let _guard = self.lock.lock().await;
+        if connected {
+            self.storage
+                .set_config_layer_state(layer_id, STATE_APPLIED)?;
+            tracing::info!(layer_id, "remote config confirmed after reconnect");
+        } else {
+            tracing::warn!(
+                layer_id,
+                grace_secs = grace.as_secs(),
+                "no reconnect within grace period, rolling back remote config"
+            );
+            self.rollback_layer(layer_id)?;
+        }
+        Ok(connected)
+    }
+
+    fn rollback_layer(&self, layer_id: i64) -> Result<()> {
+        self.storage
+            .set_config_layer_state(layer_id, STATE_ROLLED_BACK)?;
+        let previous = self.storage.previous_applied_config_layer(layer_id)?;
+        let cfg = self
+            .config
+            .load_with_layer(previous.as_ref().map(|l| &l.layer))
+            .context("failed to reload previous config")?;
+        self.config.publish(cfg);
+        self.storage.record_event(
+            "config.rolled_back",
+            &json!({ "layer_id": layer_id, "restored_layer_id": previous.map(|l| l.id) }),
+        )?;
+        Ok(())
+    }
+}
+
+/// 远程配置子系统：恢复持久化的远程配置层并注册配置相关命令
+pub struct RemoteConfigSubsystem;
+
+#[async_trait]
+impl Subsystem for RemoteConfigSubsystem {
+    fn name(&self) -> &'static str {
+        "remote_config"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        let remote = Arc::new(RemoteConfig::new(
+            crate::storage::global(),
+            crate::config::store(),
+        ));
+        if let Some(layer_id) = remote.restore()? {
+            spawn_confirm(remote.clone(), layer_id, ctx.connections.clone(), 0, None);
+        }
+
+        let connections = ctx.connections.clone();
+        let outbound = ctx.outbound.clone();
+        let client = ctx.client_mut()?;
+        client.register(
+            "config.patch",
+            Arc::new(ConfigPatchHandler {
+                remote: remote.clone(),
+                connections,
+                outbound,
+            }),
+        );
+        client.register("labels.update", Arc::new(LabelsUpdateHandler { remote }));
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// `config.patch` 命令：payload 为 JSON merge patch
+pub struct ConfigPatchHandler {
+    pub remote: Arc<RemoteConfig>,
+    pub connections: watch::Receiver<u64>,
+    pub outbound: Outbound,
+}
+
+#[async_trait]
+impl CommandHandler for ConfigPatchHandler {
+    async fn handle(&self, cmd: &ControlCmd) -> Result<CmdResult> {
+        let patch: Value = match serde_json::from_slice(&cmd.payload) {
+            Ok(v @ Value::Object(_)) => v,
+            Ok(_) => return Ok(reply("rejected", "config patch must be a JSON object")),
+            Err(e) => return Ok(reply("rejected", format!("invalid JSON: {e}"))),
+        };
+        // 引用（${file:...} 等）只允许出现在本地配置中，否则 master 可借此读取主机上的文件
+        if super::resolve::contains_reference(&patch) {
+            return Ok(reply(
+                "rejected",
+                "config patch must not contain ${...} references",
+            ));
+        }
+        let baseline = *self.connections.borrow();
+        let applied = match self.remote.apply(&cmd.id, &patch).await {
+            Ok(applied) => applied,
+            Err(e) => return Ok(reply("rejected", format!("{e:#}"))),
+        };
+        if applied.needs_confirm {
+            spawn_confirm(
+                self.remote.clone(),
+                applied.layer_id,
+                self.connections.clone(),
+                baseline,
+                Some((cmd.id.clone(), self.outbound.clone())),
+            );
+            return Ok(reply("pending", "awaiting reconnect with new settings"));
+        }
+        Ok(reply("applied", ""))
+    }
+}
+
+/// 后台等待确认，结束后（若有）向 master 上报最终状态
+pub fn spawn_confirm(
+    remote: Arc<RemoteConfig>,
+    layer_id: i64,
+    connections: watch::Receiver<u64>,
+    baseline: u64,
+    notify: Option<(String, Outbound)>,
+) {
+    tokio::spawn(async move {
+        let status = match remote
+            .confirm_or_rollback(layer_id, connections, baseline)
+            .await
+        {
+            Ok(true) => STATE_APPLIED,
+            Ok(false) => STATE_ROLLED_BACK,
+            Err(e) => {
+                tracing::error!(layer_id, error = %format!("{e:#}"), "remote config confirmation failed");
+                return;
+            }
+        };
+        if let Some((cmd_id, outbound)) = notify {
+            let result = CmdResult {
+                id: cmd_id,
+                ts: now_millis(),
+                ..reply(status, "")
+            };
+            let _ = outbound.send(agent_message::Body::Result(result)).await;
+        }
+    });
+}
+
+fn touches_connection(old: &Config, new: &Config) -> bool {
+    old.grpc != new.grpc || old.tls != new.tls
+}
+
+/// 补丁中的每个叶子（含 null 删除）都必须位于 [`REMOTE_PATHS`] 之下
+fn check_patch(patch: &Value) -> Result<()> {
+    fn walk(value: &Value, path: &str) -> Result<()> {
+        if let Value::Object(map) = value {
+            for (key, child) in map {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                walk(child, &child_path)?;
+            }
+            return Ok(());
+        }
+        let allowed = REMOTE_PATHS.iter().any(|allowed| {
+            path == *allowed
+                || path
+                    .strip_prefix(allowed)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        });
+        if !allowed {
+            bail!("config path `{path}` cannot be changed remotely");
+        }
+        Ok(())
+    }
+    walk(patch, "")
+}
+
+/// RFC 7396 JSON merge patch：对象递归合并，null 删除键
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().expect("object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 与全局配置隔离的配置存储，测试之间互不影响
+    fn remote(storage: &Arc<Storage>) -> (RemoteConfig, Arc<ConfigStore>) {
+        let (config, _) = ConfigStore::load(&crate::config::LoadOptions {
+            path: None,
+            allow_missing: true,
+        })
+        .unwrap();
+        let config = Arc::new(config);
+        (RemoteConfig::new(storage.clone(), config.clone()), config)
+    }
+
+    #[test]
+    fn merge_patch_follows_rfc_7396() {
+        let mut target = json!({ "a": "b", "c": { "d": "e", "f": "g" }, "l": [1, 2] });
+        merge_patch(
+            &mut target,
+            &json!({ "a": "z", "c": { "f": null, "h": 1 }, "l": [3], "n": { "x": true } }),
+        );
+        assert_eq!(
+            target,
+            json!({ "a": "z", "c": { "d": "e", "h": 1 }, "l": [3], "n": { "x": true } })
+        );
+
+        // 非对象的补丁整体替换，对象补丁覆盖非对象目标
+        let mut target = json!({ "a": { "b": 1 } });
+        merge_patch(&mut target, &json!({ "a": "flat" }));
+        assert_eq!(target, json!({ "a": "flat" }));
+        merge_patch(&mut target, &json!({ "a": { "b": 2 } }));
+        assert_eq!(target, json!({ "a": { "b": 2 } }));
+        merge_patch(&mut target, &json!({ "a": null, "missing": null }));
+        assert_eq!(target, json!({}));
+    }
+
+    #[test]
+    fn patches_may_only_touch_remote_paths() {
+        for allowed in [
+            json!({ "basic": { "labels": { "team": "ops", "old": null } } }),
+            json!({ "grpc": { "masters": ["m1:50051"], "reconnect": { "max_attempts": 3 } } }),
+            json!({ "telemetry": { "log_level": "debug", "log_forward": { "enabled": true } } }),
+            json!({ "maintenance": null }),
+            json!({ "update": {} }),
+        ] {
+            check_patch(&allowed).unwrap_or_else(|e| panic!("{allowed}: {e:#}"));
+        }
+        for (denied, path) in [
+            (
+                json!({ "update": { "trusted_keys": ["AAAA"] } }),
+                "update.trusted_keys",
+            ),
+            (json!({ "update": null }), "update"),
+            (
+                json!({ "basic": { "sqlite_path": "/tmp/x" } }),
+                "basic.sqlite_path",
+            ),
+            (json!({ "basic": null }), "basic"),
+            (
+                json!({ "basic": { "labels_extra": "x" } }),
+                "basic.labels_extra",
+            ),
+            (
+                json!({ "telemetry": { "log_file": "/etc/cron.d/x" } }),
+                "telemetry.log_file",
+            ),
+            (
+                json!({ "identity": { "source": "generated" } }),
+                "identity.source",
+            ),
+        ] {
+            let err = check_patch(&denied).unwrap_err();
+            assert!(format!("{err:#}").contains(&format!("`{path}`")), "{err:#}");
+        }
+    }
+
+    #[tokio::test]
+    async fn patch_to_update_trusted_keys_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(Storage::open(dir.path().join("agent.db")).unwrap());
+        let (remote, config) = remote(&storage);
+
+        let patch = json!({ "update": { "trusted_keys": ["bWFzdGVyLWNvbnRyb2xsZWQta2V5"] } });
+        let err = remote.apply("evil", &patch).await.err().unwrap();
+        assert!(
+            format!("{err:#}").contains("update.trusted_keys"),
+            "{err:#}"
+        );
+        assert!(storage.active_config_layer().unwrap().is_none());
+        assert!(config.current().update.trusted_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pending_connection_layer_blocks_later_patches_until_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(Storage::open(dir.path().join("agent.db")).unwrap());
+        let (remote, config) = remote(&storage);
+        let default_send = Config::default().grpc.max_send_message_mb;
+
+        let a = remote
+            .apply(
+                "a",
+                &json!({ "grpc": { "max_send_message_mb": default_send + 1 } }),
+            )
+            .await
+            .unwrap();
+        assert!(a.needs_confirm);
+
+        let labels = json!({ "basic": { "labels": { "team": "ops" } } });
+        let err = remote.apply("b", &labels).await.err().unwrap();
+        assert!(format!("{err:#}").contains("awaiting reconnect"), "{err:#}");
+
+        // A 超时回滚：不留下包含 A 设置的后续层
+        remote.rollback_layer(a.layer_id).unwrap();
+        assert!(storage.active_config_layer().unwrap().is_none());
+        assert_eq!(config.current().grpc.max_send_message_mb, default_send);
+
+        let b = remote.apply("b", &labels).await.unwrap();
+        assert!(!b.needs_confirm);
+        let active = storage.active_config_layer().unwrap().unwrap();
+        assert_eq!(active.layer, labels);
+        assert_eq!(active.state, STATE_APPLIED);
+        let cfg = config.current();
+        assert_eq!(cfg.grpc.max_send_message_mb, default_send);
+        assert_eq!(
+            cfg.basic.labels.get("team").map(String::as_str),
+            Some("ops")
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Value references in config, resolved at load time:
+//! - `${file:/path}` / `${env:VAR}`: secret references
+//! - `${host.hostname}` / `${host.ip}` / `${host.machine_id}` / `${env.VAR}`: host templating
+//!
+//! `$${` is written out as a literal `${`.
+
+use crate::utils::host;
+use anyhow::{Context, Result, anyhow};
+use config::{Value, ValueKind};
+
+/// 递归解析配置树中所有字符串值里的引用。
+pub fn resolve_value(value: &mut Value) -> Result<()> {
+    resolve_at(value, "")
+}
+
+fn resolve_at(value: &mut Value, path: &str) -> Result<()> {
+    match &mut value.kind {
+        ValueKind::String(s) if s.contains("${") => {
+            *s = resolve_str(s).with_context(|| format!("failed to resolve `{}`", path))?;
+        }
+        ValueKind::Table(table) => {
+            for (key, child) in table.iter_mut() {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                resolve_at(child, &child_path)?;
+            }
+        }
+        ValueKind::Array(items) => {
+            for (i, child) in items.iter_mut().enumerate() {
+                resolve_at(child, &format!("{}[{}]", path, i))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 展开字符串中的全部 `${...}` 引用，未识别的引用视为错误；`$${` 输出字面量 `${`。
+fn resolve_str(input: &str) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        if start > 0 && rest.as_bytes()[start - 1] == b'$' {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("unterminated reference in {:?}", input))?;
+        out.push_str(&lookup(&after[..end])?);
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// 不可信来源（master 下发的配置层）中的值按字面量使用：把 `${` 转义为 `$${`
+pub fn escape_references(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) if s.contains("${") => *s = s.replace("${", "$${"),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(escape_references),
+        serde_json::Value::Object(map) => map.values_mut().for_each(escape_references),
+        _ => {}
+    }
+}
+
+/// 值中是否出现引用语法（含转义形式）
+pub fn contains_reference(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(s) => s.contains("${"),
+        serde_json::Value::Array(items) => items.iter().any(contains_reference),
+        serde_json::Value::Object(map) => map.values().any(contains_reference),
+        _ => false,
+    }
+}
+
+fn lookup(reference: &str) -> Result<String> {
+    if let Some(fact) = reference.strip_prefix("host.") {
+        return host_fact(fact);
+    }
+    if let Some(name) = reference.strip_prefix("env.") {
+        return std::env::var(name)
+            .with_context(|| format!("environment variable {} not set", name));
+    }
+    match reference.split_once(':') {
+        Some(("file", path)) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read secret file {}", path))?;
+            Ok(content.trim_end_matches(['\r', '\n']).to_string())
+        }
+        Some(("env", name)) => {
+            std::env::var(name).with_context(|| format!("environment variable {} not set", name))
+        }
+        _ => Err(anyhow!("unknown reference ${{{}}}", reference)),
+    }
+}
+
+fn host_fact(name: &str) -> Result<String> {
+    let facts = host::facts();
+    let value = match name {
+        "hostname" => facts.hostname.clone(),
+        "ip" => facts.ip.clone(),
+        "machine_id" => facts.machine_id.clone(),
+        "os" => Some(facts.os.clone()),
+        "arch" => Some(facts.arch.clone()),
+        other => return Err(anyhow!("unknown host fact: {}", other)),
+    };
+    value.ok_or_else(|| anyhow!("host fact {} unavailable", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn resolves_env_and_file_references() {
+        // SAFETY: 测试专用变量，不与其他测试共享
+        unsafe { std::env::set_var("WARDEN_TEST_RESOLVE_TOKEN", "s3cret") };
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "from-file").unwrap();
+        let path = file.path().display().to_string();
+
+        assert_eq!(
+            resolve_str("token=${env:WARDEN_TEST_RESOLVE_TOKEN}").unwrap(),
+            "token=s3cret"
+        );
+        assert_eq!(
+            resolve_str("${env.WARDEN_TEST_RESOLVE_TOKEN}").unwrap(),
+            "s3cret"
+        );
+        assert_eq!(
+            resolve_str(&format!("${{file:{path}}}")).unwrap(),
+            "from-file"
+        );
+        assert_eq!(resolve_str("no references").unwrap(), "no references");
+    }
+
+    #[test]
+    fn rejects_unknown_unterminated_and_missing_references() {
+        assert!(resolve_str("${vault:secret/x}").is_err());
+        assert!(resolve_str("${env:WARDEN_TEST_RESOLVE_UNSET").is_err());
+        assert!(resolve_str("${env:WARDEN_TEST_RESOLVE_UNSET}").is_err());
+        assert!(resolve_str("${host.nope}").is_err());
+        assert!(resolve_str("${file:/nonexistent/warden/secret}").is_err());
+    }
+
+    #[test]
+    fn expands_host_facts() {
+        assert_eq!(
+            resolve_str("agent-${host.os}-${host.arch}").unwrap(),
+            format!("agent-{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+        );
+    }
+
+    #[test]
+    fn double_dollar_escapes_a_literal_reference() {
+        assert_eq!(resolve_str("$${env:HOME}").unwrap(), "${env:HOME}");
+        assert_eq!(resolve_str("a $${b} c").unwrap(), "a ${b} c");
+        assert_eq!(resolve_str("cost $5").unwrap(), "cost $5");
+    }
+
+    #[test]
+    fn escaped_values_resolve_to_themselves() {
+        let mut value = serde_json::json!({
+            "labels": { "x": "${file:/etc/shadow}", "y": "$${env:HOME}" },
+            "masters": ["${env:HOME}"],
+            "n": 1
+        });
+        assert!(contains_reference(&value));
+        escape_references(&mut value);
+        assert_eq!(
+            resolve_str(value["labels"]["x"].as_str().unwrap()).unwrap(),
+            "${file:/etc/shadow}"
+        );
+        assert_eq!(
+            resolve_str(value["labels"]["y"].as_str().unwrap()).unwrap(),
+            "$${env:HOME}"
+        );
+        assert_eq!(
+            resolve_str(value["masters"][0].as_str().unwrap()).unwrap(),
+            "${env:HOME}"
+        );
+        assert!(!contains_reference(
+            &serde_json::json!({ "a": ["$", 1, null] })
+        ));
+    }
+
+    #[test]
+    fn resolves_nested_tables_and_arrays() {
+        // SAFETY: 同上
+        unsafe { std::env::set_var("WARDEN_TEST_RESOLVE_NESTED", "x") };
+        let mut value = Value::from(config::Map::from([(
+            "grpc".to_string(),
+            Value::from(config::Map::from([(
+                "masters".to_string(),
+                Value::from(vec!["${env:WARDEN_TEST_RESOLVE_NESTED}:1".to_string()]),
+            )])),
+        )]));
+        resolve_value(&mut value).unwrap();
+        let table = value.into_table().unwrap();
+        let grpc = table["grpc"].clone().into_table().unwrap();
+        let masters = grpc["masters"].clone().into_array().unwrap();
+        assert_eq!(masters[0].clone().into_string().unwrap(), "x:1");
+
+        let mut bad = Value::from(config::Map::from([(
+            "key".to_string(),
+            Value::from("${env:WARDEN_TEST_RESOLVE_UNSET}"),
+        )]));
+        let err = format!("{:#}", resolve_value(&mut bad).unwrap_err());
+        assert!(err.contains("`key`"), "{err}");
+    }
+}
@@ -1,32 +1,35 @@
+use crate::config::secret::Secret;
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     pub basic: BasicConfig,
     pub grpc: GrpcConfig,
     pub tls: TlsConfig,
     pub telemetry: TelemetryConfig,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            basic: BasicConfig::default(),
-            grpc: GrpcConfig::default(),
-            tls: TlsConfig::default(),
-            telemetry: TelemetryConfig::default(),
-        }
-    }
+    pub collector: CollectorConfig,
+    pub update: UpdateConfig,
+    pub watchdog: WatchdogConfig,
+    pub supervisor: SupervisorConfig,
+    pub maintenance: MaintenanceConfig,
+    pub identity: IdentityConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BasicConfig {
-    pub plugin_dir: String,    // 插件目录
-    pub sqlite_path: String,   // SQLite数据库文件路径
-    pub max_memory_mb: u32,    // 最大内存，单位 mb
-    pub max_cpu_percent: u32,  // 最大CPU使用百分比
-    pub max_file_handles: u32, // 最大文件句柄数
+    pub plugin_dir: String,      // 插件目录
+    pub sqlite_path: String,     // SQLite数据库文件路径
+    pub max_memory_mb: u32,      // 最大内存，单位 mb；监督模式下超过时重启 worker，0 表示不限制
+    pub max_cpu_percent: u32,    // 最大CPU使用百分比
+    pub max_file_handles: u32,   // 最大文件句柄数
+    pub drain_timeout_secs: u64, // 退出时等待在途命令完成的时间，超时取消，单位 秒
+    pub admin_socket: String,    // 本地管理 socket 路径（warden log 等命令使用），为空时不监听
+    #[serde(default)]
+    pub plugin_credentials: HashMap<String, Secret<String>>, // 插件凭据，按插件名索引
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>, // agent 标签，如 env / role / owner
 }
 
 impl Default for BasicConfig {
@@ -37,18 +40,26 @@ impl Default for BasicConfig {
             max_memory_mb: 32,
             max_cpu_percent: 3,
             max_file_handles: 32,
+            drain_timeout_secs: 30,
+            admin_socket: "./data/admin.sock".to_string(),
+            plugin_credentials: HashMap::new(),
+            labels: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GrpcConfig {
-    pub masters: Vec<String>,        // master地址列表
-    pub connect_timeout_secs: u64,   // 连接超时时间，单位 秒
-    pub max_receive_message_mb: u32, // 最大接收消息大小，单位 mb
-    pub max_send_message_mb: u32,    // 最大发送消息大小，单位 mb
-    pub keepalive: KeepaliveConfig,  // 保持连接的配置
-    pub reconnect: ReconnectConfig,  // 重连的配置
+    pub masters: Vec<String>,         // master地址列表
+    pub connect_timeout_secs: u64,    // 连接超时时间，单位 秒
+    pub max_receive_message_mb: u32,  // 最大接收消息大小，单位 mb
+    pub max_send_message_mb: u32,     // 最大发送消息大小，单位 mb
+    pub keepalive: KeepaliveConfig,   // 保持连接的配置
+    pub reconnect: ReconnectConfig,   // 重连的配置
+    pub heartbeat_interval_secs: u64, // 心跳间隔，单位 秒
+    pub rollback_grace_secs: u64,     // 远程修改 grpc/tls 配置后等待重连成功的时间，超时自动回滚
+    #[serde(default, skip_serializing_if = "Secret::is_empty")]
+    pub auth_token: Secret<String>, // 连接 master 的认证令牌
 }
 
 impl Default for GrpcConfig {
@@ -60,11 +71,14 @@ impl Default for GrpcConfig {
             max_send_message_mb: 16,
             keepalive: KeepaliveConfig::default(),
             reconnect: ReconnectConfig::default(),
+            heartbeat_interval_secs: 15,
+            rollback_grace_secs: 120,
+            auth_token: Secret::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct KeepaliveConfig {
     pub time_secs: u64,             // 发送keepalive的时间间隔，单位 秒
     pub timeout_secs: u64,          // 等待keepalive响应的时间，单位 秒
@@ -81,7 +95,7 @@ impl Default for KeepaliveConfig {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReconnectConfig {
     pub max_attempts: u32,         // 最大重试次数
     pub initial_backoff_secs: u64, // 初始重试间隔，单位 秒
@@ -100,12 +114,13 @@ impl Default for ReconnectConfig {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TlsConfig {
-    pub enable: bool,                 // 是否启用tls
-    pub ca_file: String,              // CA证书文件路径
-    pub cert_file: String,            // 客户端证书文件路径
-    pub key_file: String,             // 客户端私钥文件路径
+    pub enable: bool,      // 是否启用tls
+    pub ca_file: String,   // CA证书文件路径
+    pub cert_file: String, // 客户端证书文件路径
+    #[serde(default, skip_serializing_if = "Secret::is_empty")]
+    pub key_file: Secret<String>, // 客户端私钥文件路径
     pub server_name_override: String, // 服务器名称覆盖
 }
 
@@ -115,7 +130,7 @@ impl Default for TlsConfig {
             enable: false, // 默认不启用TLS
             ca_file: "".to_string(),
             cert_file: "".to_string(),
-            key_file: "".to_string(),
+            key_file: Secret::default(),
             server_name_override: "".to_string(),
         }
     }
@@ -124,10 +139,18 @@ impl Default for TlsConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TelemetryConfig {
     pub log_level: String,               // 日志级别
-    pub log_format: String,              // 日志格式 (如 json, plain)
-    pub log_output: String,              // 日志输出位置，如 stdout, file
-    pub log_file: String,                // 日志文件路径，当 log_output 为 file 时生效
+    pub log_format: String,              // 日志格式：json / plain / logfmt / ecs / otel
+    pub log_encoding: LogEncodingConfig, // 时间戳时区与字段命名，对所有格式生效
+    pub log_output: String, // 日志输出位置，逗号分隔的组合：stdout, stderr, file, syslog, journald（both 即 stdout,file）
+    pub log_file: String,   // 日志文件路径，当 log_output 含 file 时生效
+    #[serde(default)]
+    pub log_sinks: Vec<LogSinkConfig>, // 具名 sink；非空时替代 log_output 中的 stdout/stderr/file 及 log_format/log_file/log_rotation
     pub log_rotation: LogRotationConfig, // 日志轮转配置
+    pub log_queue: LogQueueConfig,       // 日志文件写入队列配置
+    pub log_forward: LogForwardConfig,   // 日志转发到 master 的配置
+    pub log_syslog: SyslogConfig,        // log_output 含 syslog 时的配置
+    pub log_journald: JournaldConfig,    // log_output 含 journald 时的配置
+    pub log_redaction: RedactionConfig,  // 日志脱敏规则，对所有输出与转发生效
 
     pub metrics_port: u16,    // 指标端口
     pub metrics_path: String, // 指标路径
@@ -138,9 +161,16 @@ impl Default for TelemetryConfig {
         Self {
             log_level: "info".to_string(),
             log_format: "json".to_string(),
+            log_encoding: LogEncodingConfig::default(),
             log_output: "stdout".to_string(),
             log_file: "./log/agent.log".to_string(),
+            log_sinks: Vec::new(),
             log_rotation: LogRotationConfig::default(),
+            log_queue: LogQueueConfig::default(),
+            log_forward: LogForwardConfig::default(),
+            log_syslog: SyslogConfig::default(),
+            log_journald: JournaldConfig::default(),
+            log_redaction: RedactionConfig::default(),
             metrics_port: 9090,
             metrics_path: "/metrics".to_string(),
         }
@@ -148,10 +178,16 @@ impl Default for TelemetryConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LogRotationConfig {
-    pub max_size_mb: u32, // 最大日志文件大小，单位 mb
-    pub max_files: u32,   // 最大日志文件数量
-    pub compress: bool,   // 是否压缩旧日志文件
+    pub max_size_mb: u32,        // 最大日志文件大小，单位 mb
+    pub max_files: u32,          // 最大日志文件数量，0 表示不限（须设置 max_age_days）
+    pub compress: bool,          // 是否压缩旧日志文件
+    pub compression: String,     // 压缩格式：gzip / zstd
+    pub policy: String,          // 轮转策略：size / time / size_or_time（任一阈值先到即轮转）
+    pub interval: String,        // 按时间轮转的周期：daily / hourly
+    pub max_age_days: u32,       // 轮转文件保留天数，期间不因 max_files 删除；0 表示不按时间清理
+    pub rotate_on_startup: bool, // 启动时轮转已有的非空日志文件
 }
 
 impl Default for LogRotationConfig {
@@ -160,34 +196,383 @@ impl Default for LogRotationConfig {
             max_size_mb: 100,
             max_files: 7,
             compress: true,
+            compression: "gzip".to_string(),
+            policy: "size".to_string(),
+            interval: "daily".to_string(),
+            max_age_days: 0,
+            rotate_on_startup: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogEncodingConfig {
+    pub timezone: String, // 时间戳时区：UTC、local、固定偏移如 +08:00，或 IANA 名称如 Asia/Shanghai
+    pub timestamp_field: String, // 时间字段名，为空时使用格式默认值（json: timestamp，logfmt: ts，ecs: @timestamp，otel: Timestamp）
+    pub level_field: String, // 级别字段名，为空时使用格式默认值（json/logfmt: level，ecs: log.level，otel: SeverityText）
+    pub message_field: String, // 消息字段名，为空时使用格式默认值（json/ecs: message，logfmt: msg，otel: Body）
+}
+
+impl Default for LogEncodingConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            timestamp_field: String::new(),
+            level_field: String::new(),
+            message_field: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogSinkConfig {
+    pub name: String,                 // sink 名称，用于指标与日志
+    pub output: String,               // stdout、stderr、file 或其组合
+    pub format: String,               // json / plain / logfmt / ecs / otel
+    pub level: String,                // 最低级别，在 log_level 之后生效
+    pub targets: Vec<String>,         // 只接收这些 target（含子模块）的日志，空表示全部
+    pub exclude_targets: Vec<String>, // 不接收这些 target（含子模块）的日志
+    pub file: String,                 // 日志文件路径，output 含 file 时生效
+    pub rotation: LogRotationConfig,  // 日志轮转配置
+}
+
+impl Default for LogSinkConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            output: "stdout".to_string(),
+            format: "json".to_string(),
+            level: "trace".to_string(),
+            targets: Vec::new(),
+            exclude_targets: Vec::new(),
+            file: String::new(),
+            rotation: LogRotationConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogQueueConfig {
+    pub capacity: usize,           // 等待写入文件的最大日志行数
+    pub max_size_mb: u64, // 排队日志的最大总大小，单位 MB，与 capacity 任一先到即视为队列已满；0 表示只限制行数
+    pub overflow: String, // 队列满时的策略：block / drop_newest / drop_oldest（保留 ERROR 日志）
+    pub report_interval_secs: u64, // 输出丢弃计数日志的间隔，单位 秒，0 表示不输出
+}
+
+impl Default for LogQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 8192,
+            max_size_mb: 8,
+            overflow: "drop_oldest".to_string(),
+            report_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogForwardConfig {
+    pub enabled: bool,            // 是否把日志转发到 master
+    pub min_level: String,        // 转发的最低级别，低于 log_level 的日志不会被转发
+    pub batch_size: usize,        // 每批最多记录数，攒够一批立即发送
+    pub flush_interval_secs: u64, // 未攒够一批时的发送间隔，单位 秒
+    pub rate_limit_per_sec: u32,  // 每秒最多转发的记录数，超出的计入 dropped，0 表示不限速
+    pub buffer_size: usize,       // 离线时本地缓冲的最大记录数，超出时丢弃最旧的记录
+}
+
+impl Default for LogForwardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_level: "warn".to_string(),
+            batch_size: 100,
+            flush_interval_secs: 5,
+            rate_limit_per_sec: 50,
+            buffer_size: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyslogConfig {
+    pub address: String,  // unix:///dev/log、udp://host:514 或 tcp://host:601
+    pub facility: String, // 如 daemon、user、local0..local7
+    pub app_name: String, // RFC 5424 APP-NAME
+    pub sd_id: String,    // 结构化数据的 SD-ID，name@<IANA 私有企业号>
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            address: "unix:///dev/log".to_string(),
+            facility: "daemon".to_string(),
+            app_name: "warden".to_string(),
+            // 32473 是 RFC 5424 示例用的企业号，部署时应换成本组织注册的企业号
+            sd_id: "fields@32473".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournaldConfig {
+    pub socket: String,     // journald 原生协议 socket
+    pub identifier: String, // SYSLOG_IDENTIFIER
+}
+
+impl Default for JournaldConfig {
+    fn default() -> Self {
+        Self {
+            socket: "/run/systemd/journal/socket".to_string(),
+            identifier: "warden".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    pub enabled: bool,         // 是否启用脱敏
+    pub fields: Vec<String>, // 整体替换取值的字段名，不区分大小写，也匹配 "_"/"." 分隔的后缀（token 匹配 auth_token）
+    pub patterns: Vec<String>, // 对 message 与字符串字段生效的正则，有捕获组时只替换第一个捕获组
+    pub replacement: String, // 替换后的文本
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fields: [
+                "password",
+                "passwd",
+                "secret",
+                "token",
+                "api_key",
+                "apikey",
+                "authorization",
+                "private_key",
+                "credentials",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            patterns: vec![
+                r"(?i)bearer\s+([A-Za-z0-9._~+/-]+=*)".to_string(),
+                r#"(?i)(?:password|passwd|secret|token|api[_-]?key)["']?\s*[:=]\s*["']?([^\s"',;&]+)"#
+                    .to_string(),
+                r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----"
+                    .to_string(),
+            ],
+            replacement: "[REDACTED]".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectorConfig {
+    pub interval_secs: u64, // 采集间隔，单位 秒
+}
+
+impl Default for CollectorConfig {
+    fn default() -> Self {
+        Self { interval_secs: 60 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateConfig {
+    pub trusted_keys: Vec<String>, // 受信任的 ed25519 公钥（base64），为空时拒绝所有升级
+    pub health_deadline_secs: u64, // 新版本需在此时间内上线且健康，否则自动回滚，单位 秒
+    pub download_timeout_secs: u64, // 下载超时时间，单位 秒
+    pub max_boot_attempts: u32,    // 新版本启动次数上限，超过视为崩溃循环并回滚
+    pub channel: String,           // 升级通道：stable、beta（同时接受 stable）、pinned
+    pub pinned_version: String,    // channel 为 pinned 时只接受此版本
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            trusted_keys: Vec::new(),
+            health_deadline_secs: 300,
+            download_timeout_secs: 300,
+            max_boot_attempts: 3,
+            channel: "stable".to_string(),
+            pinned_version: "".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    pub heartbeat_interval_secs: u64, // worker 向监督进程发送心跳的间隔，单位 秒
+    pub hang_timeout_secs: u64,       // 超过此时间未收到心跳视为挂起并重启，单位 秒
+    pub initial_backoff_secs: u64,    // 首次重启等待时间，单位 秒
+    pub max_backoff_secs: u64,        // 最大重启等待时间，单位 秒
+    pub stable_after_secs: u64,       // worker 持续运行超过此时间后重置退避，单位 秒
+    pub crash_loop_threshold: u32,    // 时间窗口内崩溃次数达到此值进入安全模式
+    pub crash_loop_window_secs: u64,  // 崩溃循环统计窗口，单位 秒
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_secs: 5,
+            hang_timeout_secs: 30,
+            initial_backoff_secs: 1,
+            max_backoff_secs: 60,
+            stable_after_secs: 120,
+            crash_loop_threshold: 5,
+            crash_loop_window_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    pub max_restarts: u32, // 窗口内允许的最大重启次数，超过后停止该组任务并标记为 failed
+    pub restart_window_secs: u64, // 重启次数统计窗口，单位 秒
+    pub restart_delay_ms: u64, // 任务失败后重启前的等待时间，单位 毫秒
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            restart_window_secs: 60,
+            restart_delay_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    pub timezone: String, // 窗口计划使用的时区（IANA 名称），如 Asia/Shanghai
+    pub windows: Vec<MaintenanceWindow>, // 维护窗口，为空时不限制；升级等中断性操作在窗口外推迟执行
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            windows: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub schedule: String, // 窗口开始时间，crontab 表达式（分 时 日 月 周，可带秒字段；周 0-7，0 与 7 为周日）
+    pub duration_mins: u64, // 窗口持续时间，单位 分钟
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityConfig {
+    pub source: String, // id 来源：auto（有 machine-id 时由其与系统 UUID 派生，否则随机生成）、machine_id、generated
+    pub id_file: String, // 持久化 id 的文件，为空时放在 sqlite 所在目录；放在安装目录之外可在重装后保留
+    pub regenerate_on_clone: bool, // 检测到克隆镜像时重新生成 id
+}
+
+impl Default for IdentityConfig {
+    fn default() -> Self {
+        Self {
+            source: "auto".to_string(),
+            id_file: "".to_string(),
+            regenerate_on_clone: true,
         }
     }
 }
 
 impl Config {
     pub fn validate(&self) -> Result<()> {
-        if self.grpc.masters.len() == 0 {
+        if self.grpc.masters.is_empty() {
             return Err(anyhow!("masters is empty"));
         }
         if self.basic.sqlite_path.is_empty() {
             return Err(anyhow!("sqlite_path is empty"));
         }
+        for key in self.basic.labels.keys() {
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'))
+            {
+                return Err(anyhow!("invalid label key: {:?}", key));
+            }
+        }
+        if self.grpc.heartbeat_interval_secs == 0 {
+            return Err(anyhow!("heartbeat_interval_secs must be > 0"));
+        }
+        if self.collector.interval_secs == 0 {
+            return Err(anyhow!("collector interval_secs must be > 0"));
+        }
+        for key in &self.update.trusted_keys {
+            crate::agent::updater::parse_public_key(key)
+                .map_err(|e| anyhow!("invalid update trusted key: {e:#}"))?;
+        }
+        if self.update.health_deadline_secs == 0 {
+            return Err(anyhow!("update health_deadline_secs must be > 0"));
+        }
+        if self.watchdog.heartbeat_interval_secs == 0
+            || self.watchdog.hang_timeout_secs <= self.watchdog.heartbeat_interval_secs
+        {
+            return Err(anyhow!(
+                "watchdog hang_timeout_secs must be greater than heartbeat_interval_secs (> 0)"
+            ));
+        }
+        match self.update.channel.as_str() {
+            "stable" | "beta" => {}
+            "pinned" if !self.update.pinned_version.is_empty() => {}
+            "pinned" => {
+                return Err(anyhow!(
+                    "update pinned_version required when channel=pinned"
+                ));
+            }
+            other => return Err(anyhow!("invalid update channel: {}", other)),
+        }
+        crate::agent::maintenance::validate(&self.maintenance)?;
+        if self.supervisor.restart_window_secs == 0 {
+            return Err(anyhow!("supervisor restart_window_secs must be > 0"));
+        }
+        match self.identity.source.as_str() {
+            "auto" | "machine_id" | "generated" => {}
+            other => return Err(anyhow!("invalid identity source: {}", other)),
+        }
         match self.telemetry.log_level.to_ascii_lowercase().as_str() {
             "error" | "warn" | "info" | "debug" | "trace" => {}
             other => return Err(anyhow!("invalid log_level: {}", other)),
         }
-        match self.telemetry.log_format.to_ascii_lowercase().as_str() {
-            "json" | "plain" => {}
-            other => return Err(anyhow!("invalid log_format: {}", other)),
+        crate::telemetry::format::Encoding::parse(&self.telemetry.log_format)?;
+        crate::telemetry::format::TimeZone::parse(&self.telemetry.log_encoding.timezone)?;
+        let outputs = crate::telemetry::logging::Outputs::parse(&self.telemetry.log_output)?;
+        if outputs.syslog {
+            crate::telemetry::syslog::Address::parse(&self.telemetry.log_syslog.address)?;
+            crate::telemetry::syslog::parse_facility(&self.telemetry.log_syslog.facility)?;
+            crate::telemetry::syslog::parse_sd_id(&self.telemetry.log_syslog.sd_id)?;
         }
-        match self.telemetry.log_output.to_ascii_lowercase().as_str() {
-            "stdout" | "file" | "both" => {}
-            other => return Err(anyhow!("invalid log_output: {}", other)),
+        match self.telemetry.log_rotation.compression.as_str() {
+            "gzip" | "zstd" => {}
+            other => return Err(anyhow!("invalid log compression: {}", other)),
         }
-        if (self.telemetry.log_output == "file" || self.telemetry.log_output == "both")
-            && self.telemetry.log_file.trim().is_empty()
-        {
-            return Err(anyhow!("log_file required when output=file/both"));
+        match self.telemetry.log_queue.overflow.as_str() {
+            "block" | "drop_newest" | "drop_oldest" => {}
+            other => return Err(anyhow!("invalid log queue overflow policy: {}", other)),
+        }
+        if self.telemetry.log_queue.capacity == 0 {
+            return Err(anyhow!("log_queue.capacity must be > 0"));
+        }
+        crate::telemetry::forward::parse_level(&self.telemetry.log_forward.min_level)?;
+        crate::telemetry::redact::Redactor::from_config(&self.telemetry.log_redaction)?;
+        crate::telemetry::logging::validate_sinks(&self.telemetry.log_sinks)?;
+        match self.telemetry.log_rotation.policy.as_str() {
+            "size" | "time" | "size_or_time" => {}
+            other => return Err(anyhow!("invalid log rotation policy: {}", other)),
+        }
+        match self.telemetry.log_rotation.interval.as_str() {
+            "daily" | "hourly" => {}
+            other => return Err(anyhow!("invalid log rotation interval: {}", other)),
+        }
+        if outputs.file && self.telemetry.log_file.trim().is_empty() {
+            return Err(anyhow!("log_file required when output includes file"));
         }
         Ok(())
     }
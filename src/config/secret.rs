@@ -0,0 +1,102 @@
+//! Secret wrapper: keeps sensitive config values out of Debug and Serialize output.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Placeholder emitted instead of the wrapped value.
+const REDACTED: &str = "******";
+
+/// 敏感配置值（令牌、私钥路径、插件凭据等）。
+/// Debug / Serialize 输出均被脱敏，只能通过 `expose` 显式取值。
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    /// Access the underlying value. Callers must not log the result.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl Secret<String> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({REDACTED})")
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LoadOptions;
+    use crate::config::loader::load;
+
+    #[test]
+    fn debug_and_serialize_are_redacted() {
+        let secret = Secret::from("hunter2".to_string());
+        assert_eq!(format!("{secret:?}"), "Secret(******)");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""******""#);
+        assert_eq!(secret.expose(), "hunter2");
+    }
+
+    #[test]
+    fn deserialization_is_transparent() {
+        let secret: Secret<String> = serde_json::from_str(r#""hunter2""#).unwrap();
+        assert_eq!(secret.expose(), "hunter2");
+        let secrets: std::collections::HashMap<String, Secret<String>> =
+            serde_json::from_str(r#"{"p": "token"}"#).unwrap();
+        assert_eq!(secrets["p"].expose(), "token");
+    }
+
+    #[test]
+    fn empty_secrets_survive_the_defaults_layer() {
+        // 默认值层由序列化得到，空值被跳过而不是写成占位符
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.yaml");
+        let opts = LoadOptions {
+            path: Some(missing),
+            allow_missing: true,
+        };
+        let (cfg, _) = load(&opts, None).unwrap();
+        assert!(cfg.grpc.auth_token.is_empty());
+        assert!(cfg.tls.key_file.is_empty());
+
+        let path = dir.path().join("config.yaml");
+        std::fs::write(
+            &path,
+            "grpc:\n  auth_token: s3cr3t-token\nbasic:\n  plugin_credentials:\n    p: s3cr3t-cred\n",
+        )
+        .unwrap();
+        let opts = LoadOptions {
+            path: Some(path),
+            allow_missing: false,
+        };
+        let (cfg, _) = load(&opts, None).unwrap();
+        assert_eq!(cfg.grpc.auth_token.expose(), "s3cr3t-token");
+        assert_eq!(cfg.basic.plugin_credentials["p"].expose(), "s3cr3t-cred");
+        assert!(!format!("{cfg:?}").contains("s3cr3t"));
+    }
+}
@@ -1 +1,37 @@
+//! Bearer-token authentication for outgoing gRPC requests.
 
+use crate::config::secret::Secret;
+use anyhow::{Context, Result};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// 为每个请求附加 `authorization: Bearer <token>`，令牌为空时不附加
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    header: Option<MetadataValue<Ascii>>,
+}
+
+impl AuthInterceptor {
+    pub fn new(token: &Secret<String>) -> Result<Self> {
+        let header = if token.is_empty() {
+            None
+        } else {
+            let mut value: MetadataValue<Ascii> = format!("Bearer {}", token.expose())
+                .parse()
+                .context("auth_token contains invalid characters")?;
+            value.set_sensitive(true);
+            Some(value)
+        };
+        Ok(Self { header })
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.header {
+            req.metadata_mut().insert("authorization", value.clone());
+        }
+        Ok(req)
+    }
+}
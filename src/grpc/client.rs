@@ -0,0 +1,521 @@
+//! Master connection: keeps the bidi stream up, forwards outbound messages and dispatches commands.
+
+use crate::agent::identity;
+use crate::agent::state::{self, AgentState};
+use crate::config::schema::{Config, GrpcConfig, TlsConfig};
+use crate::grpc::auth::AuthInterceptor;
+use crate::grpc::handler::{CommandHandler, Dispatcher};
+use crate::grpc::proto::agent_client::AgentClient;
+use crate::grpc::proto::{AgentMessage, CmdResult, GoingAway, Heartbeat, agent_message};
+use crate::grpc::reconnect::Backoff;
+use crate::health;
+use crate::security::cert;
+use crate::storage::Storage;
+use crate::utils::time::now_millis;
+use anyhow::{Context, Result, anyhow};
+use prost::Message;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::AbortHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Channel, Endpoint};
+
+/// 出站队列容量
+const OUTBOUND_CAPACITY: usize = 1024;
+
+/// 出站队列发送端，可在各子系统间克隆共享
+#[derive(Clone)]
+pub struct Outbound {
+    tx: mpsc::Sender<AgentMessage>,
+}
+
+impl Outbound {
+    pub async fn send(&self, body: agent_message::Body) -> Result<()> {
+        self.tx
+            .send(AgentMessage { body: Some(body) })
+            .await
+            .map_err(|_| anyhow!("outbound queue closed"))
+    }
+
+    /// 非阻塞发送，队列满或已关闭时返回错误
+    pub fn try_send(&self, body: agent_message::Body) -> Result<()> {
+        self.tx
+            .try_send(AgentMessage { body: Some(body) })
+            .map_err(|e| anyhow!("outbound queue unavailable: {e}"))
+    }
+}
+
+/// 创建出站队列
+pub fn outbound_channel() -> (Outbound, mpsc::Receiver<AgentMessage>) {
+    let (tx, rx) = mpsc::channel(OUTBOUND_CAPACITY);
+    (Outbound { tx }, rx)
+}
+
+/// 关闭 stream 后等待 master 结束响应流的时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 退出时尚未发出的出站消息，由存储子系统在最后写入 SQLite
+static UNSENT: Mutex<Option<mpsc::Receiver<AgentMessage>>> = Mutex::new(None);
+
+/// 保存客户端退出后的出站队列
+pub fn park_unsent(rx: mpsc::Receiver<AgentMessage>) {
+    *UNSENT.lock().unwrap_or_else(|e| e.into_inner()) = Some(rx);
+}
+
+/// 将队列中剩余的采集数据、命令结果与日志写入 outbox，返回写入条数
+pub fn persist_unsent(storage: &Storage) -> Result<usize> {
+    let Some(mut rx) = UNSENT.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return Ok(0);
+    };
+    let mut count = 0;
+    while let Ok(msg) = rx.try_recv() {
+        // 心跳、健康与状态报告下次连接时会重新生成，无需保留
+        if matches!(
+            msg.body,
+            Some(
+                agent_message::Body::Collect(_)
+                    | agent_message::Body::Result(_)
+                    | agent_message::Body::Logs(_)
+                    | agent_message::Body::Crash(_)
+            )
+        ) {
+            storage.spool_outbound(&msg.encode_to_vec())?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// 连接建立后先发出上次退出时暂存的消息。stream 取走后才从 outbox 删除，
+/// 连接中途断开时留在 outbox 中，下次连接重发
+async fn replay_spooled(tx: &mpsc::Sender<AgentMessage>, storage: &Storage) -> Result<()> {
+    let spooled = match storage.spooled_outbound() {
+        Ok(spooled) => spooled,
+        Err(e) => {
+            tracing::warn!(error = %format!("{e:#}"), "failed to load spooled messages");
+            return Ok(());
+        }
+    };
+    if spooled.is_empty() {
+        return Ok(());
+    }
+    let mut replayed = Vec::with_capacity(spooled.len());
+    for (id, body) in spooled {
+        match AgentMessage::decode(body.as_slice()) {
+            Ok(msg) if msg.body.is_some() => forward(tx, msg).await?,
+            _ => tracing::warn!(spool_id = id, "dropping undecodable spooled message"),
+        }
+        replayed.push(id);
+    }
+    // 等待 stream 取走缓冲中的全部消息
+    while tx.capacity() < tx.max_capacity() {
+        if tx.is_closed() {
+            return Err(anyhow!("stream closed while replaying spooled messages"));
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    for id in &replayed {
+        if let Err(e) = storage.delete_spooled(*id) {
+            tracing::warn!(spool_id = id, error = %format!("{e:#}"), "failed to delete spooled message");
+        }
+    }
+    tracing::info!(count = replayed.len(), "spooled messages replayed");
+    Ok(())
+}
+
+/// 一次连接结束的原因
+enum Disconnect {
+    Shutdown,
+    Reconfigure,
+}
+
+/// 正在执行的控制命令
+struct Job {
+    cmd_id: String,
+    handle: AbortHandle,
+}
+
+pub struct GrpcClient {
+    dispatcher: Dispatcher,
+    outbound: Outbound,
+    outbound_rx: mpsc::Receiver<AgentMessage>,
+    connections: watch::Sender<u64>,
+    jobs: Vec<Job>,
+}
+
+impl GrpcClient {
+    pub fn new(outbound: Outbound, outbound_rx: mpsc::Receiver<AgentMessage>) -> Self {
+        Self {
+            dispatcher: Dispatcher::new(),
+            outbound,
+            outbound_rx,
+            connections: watch::Sender::new(0),
+            jobs: Vec::new(),
+        }
+    }
+
+    /// 注册控制命令处理器，需在 run 之前调用
+    pub fn register(&mut self, cmd: &'static str, handler: Arc<dyn CommandHandler>) {
+        self.dispatcher.register(cmd, handler);
+    }
+
+    /// 每建立一次 stream 计数加一，用于等待重连成功
+    pub fn connections(&self) -> watch::Receiver<u64> {
+        self.connections.subscribe()
+    }
+
+    /// 运行直到 shutdown 置为 true；grpc/tls 配置变更时使用新配置重连。
+    /// 返回出站队列，其中为退出时仍未发出的消息
+    pub async fn run(
+        mut self,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> mpsc::Receiver<AgentMessage> {
+        let dispatcher = Arc::new(std::mem::take(&mut self.dispatcher));
+        crate::agent::maintenance::spawn_runner(
+            dispatcher.clone(),
+            self.outbound.clone(),
+            shutdown.clone(),
+        );
+        let mut cfg_rx = crate::config::subscribe();
+        let mut master_idx = 0usize;
+        let mut backoff = Backoff::new(&crate::config::global().grpc.reconnect);
+        loop {
+            let cfg = cfg_rx.borrow_and_update().clone();
+            let masters = &cfg.grpc.masters;
+            let master = masters[master_idx % masters.len()].clone();
+            match self
+                .serve(
+                    &cfg,
+                    &master,
+                    &dispatcher,
+                    &mut cfg_rx,
+                    &mut shutdown,
+                    &mut backoff,
+                )
+                .await
+            {
+                Ok(Disconnect::Shutdown) => return self.outbound_rx,
+                Ok(Disconnect::Reconfigure) => {
+                    tracing::info!("connection settings changed, reconnecting");
+                    backoff = Backoff::new(&crate::config::global().grpc.reconnect);
+                    master_idx = 0;
+                    continue;
+                }
+                Err(e) => {
+                    health::checker().set("grpc", health::Status::Degraded(format!("{e:#}")));
+                    state::try_transition(AgentState::Offline, "master connection lost");
+                    let delay = backoff.next_delay();
+                    tracing::warn!(master = %master, error = %format!("{e:#}"), retry_in_secs = delay.as_secs(), "master connection failed");
+                    if backoff.exhausted() {
+                        master_idx += 1;
+                        backoff.reset();
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = stopped(&mut shutdown) => {
+                            // 离线时退出：结果留在队列中，随后落盘
+                            self.finish_jobs(None, drain_timeout()).await;
+                            return self.outbound_rx;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn serve(
+        &mut self,
+        cfg: &Config,
+        master: &str,
+        dispatcher: &Arc<Dispatcher>,
+        cfg_rx: &mut watch::Receiver<Arc<Config>>,
+        shutdown: &mut watch::Receiver<bool>,
+        backoff: &mut Backoff,
+    ) -> Result<Disconnect> {
+        let channel = connect(&cfg.grpc, &cfg.tls, master).await?;
+        let max_recv = (cfg.grpc.max_receive_message_mb as usize) * 1024 * 1024;
+        let max_send = (cfg.grpc.max_send_message_mb as usize) * 1024 * 1024;
+        let mut client =
+            AgentClient::with_interceptor(channel, AuthInterceptor::new(&cfg.grpc.auth_token)?)
+                .max_decoding_message_size(max_recv)
+                .max_encoding_message_size(max_send);
+
+        let (tx, rx) = mpsc::channel::<AgentMessage>(64);
+        let mut inbound = client
+            .session(ReceiverStream::new(rx))
+            .await
+            .context("failed to open session stream")?
+            .into_inner();
+        tracing::info!(master = %master, "connected to master");
+        backoff.reset();
+        self.connections.send_modify(|n| *n += 1);
+        health::checker().set("grpc", health::Status::Healthy);
+        state::try_transition(AgentState::Online, "connected to master");
+
+        let capabilities = dispatcher.commands();
+        let mut heartbeat =
+            tokio::time::interval(Duration::from_secs(cfg.grpc.heartbeat_interval_secs));
+        heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        // 暂存的消息早于出站队列中的消息，先行发出
+        if let Some(storage) = crate::storage::try_global() {
+            tokio::select! {
+                result = replay_spooled(&tx, &storage) => result?,
+                _ = stopped(shutdown) => {}
+            }
+        }
+
+        loop {
+            tokio::select! {
+                _ = stopped(shutdown) => break,
+                changed = cfg_rx.changed() => {
+                    if changed.is_ok() {
+                        let new_cfg = cfg_rx.borrow().clone();
+                        if new_cfg.grpc != cfg.grpc || new_cfg.tls != cfg.tls {
+                            return Ok(Disconnect::Reconfigure);
+                        }
+                    }
+                }
+                msg = inbound.message() => match msg {
+                    Ok(Some(cmd)) => {
+                        tracing::debug!(cmd_id = %cmd.id, cmd = %cmd.cmd, "control command received");
+                        let cmd_id = cmd.id.clone();
+                        let dispatcher = dispatcher.clone();
+                        let outbound = self.outbound.clone();
+                        let handle = tokio::spawn(async move {
+                            let result = dispatcher.dispatch(cmd).await;
+                            let _ = outbound.send(agent_message::Body::Result(result)).await;
+                        });
+                        self.jobs.retain(|job| !job.handle.is_finished());
+                        self.jobs.push(Job { cmd_id, handle: handle.abort_handle() });
+                    }
+                    Ok(None) => return Err(anyhow!("stream closed by master")),
+                    Err(status) => return Err(anyhow!("stream error: {status}")),
+                },
+                _ = heartbeat.tick() => {
+                    let body = agent_message::Body::Heartbeat(Heartbeat {
+                        id: identity::agent_id(),
+                        ts: now_millis(),
+                        capabilities: capabilities.clone(),
+                        labels: crate::agent::labels::current_proto(),
+                        state: state::current().to_string(),
+                    });
+                    if tx.send(AgentMessage { body: Some(body) }).await.is_err() {
+                        return Err(anyhow!("stream closed while sending"));
+                    }
+                }
+                Some(out) = self.outbound_rx.recv() => forward(&tx, out).await?,
+            }
+        }
+
+        // 有序退出：不再读取新命令，等待在途命令，发出剩余消息后告知 master
+        self.finish_jobs(Some(&tx), drain_timeout()).await;
+        while let Ok(out) = self.outbound_rx.try_recv() {
+            forward(&tx, out).await?;
+        }
+        let going_away = agent_message::Body::GoingAway(GoingAway {
+            id: identity::agent_id(),
+            ts: now_millis(),
+            reason: "shutdown".to_string(),
+        });
+        forward(
+            &tx,
+            AgentMessage {
+                body: Some(going_away),
+            },
+        )
+        .await?;
+        drop(tx);
+        // 等待 master 结束响应流，确保已发送的消息写出
+        let _ = tokio::time::timeout(CLOSE_TIMEOUT, async {
+            while let Ok(Some(_)) = inbound.message().await {}
+        })
+        .await;
+        tracing::info!(master = %master, "stream closed");
+        Ok(Disconnect::Shutdown)
+    }
+
+    /// 等待在途命令完成，超过 timeout 后取消并回复 cancelled；
+    /// 连接可用时继续转发命令结果
+    async fn finish_jobs(&mut self, tx: Option<&mpsc::Sender<AgentMessage>>, timeout: Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            self.jobs.retain(|job| !job.handle.is_finished());
+            if self.jobs.is_empty() {
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => break,
+                Some(out) = self.outbound_rx.recv(), if tx.is_some() => {
+                    if let Some(tx) = tx
+                        && forward(tx, out).await.is_err()
+                    {
+                        break;
+                    }
+                }
+                _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+            }
+        }
+        for job in self.jobs.drain(..) {
+            if job.handle.is_finished() {
+                continue;
+            }
+            job.handle.abort();
+            tracing::warn!(cmd_id = %job.cmd_id, "command cancelled by shutdown");
+            let result = CmdResult {
+                id: job.cmd_id,
+                status: "cancelled".to_string(),
+                message: "agent shutting down".to_string(),
+                ts: now_millis(),
+                ..Default::default()
+            };
+            let _ = self.outbound.try_send(agent_message::Body::Result(result));
+        }
+    }
+}
+
+/// 退出时等待在途命令的时间
+fn drain_timeout() -> Duration {
+    Duration::from_secs(crate::config::global().basic.drain_timeout_secs)
+}
+
+/// 发送一条出站消息到 stream；采集批次缺少标签时附带 agent 标签
+async fn forward(tx: &mpsc::Sender<AgentMessage>, mut out: AgentMessage) -> Result<()> {
+    if let Some(agent_message::Body::Collect(data)) = out.body.as_mut()
+        && data.labels.is_empty()
+    {
+        data.labels = crate::agent::labels::current_proto();
+    }
+    tx.send(out)
+        .await
+        .map_err(|_| anyhow!("stream closed while sending"))
+}
+
+/// 等待 shutdown 信号
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|s| *s).await;
+}
+
+async fn connect(grpc: &GrpcConfig, tls: &TlsConfig, master: &str) -> Result<Channel> {
+    let scheme = if tls.enable { "https" } else { "http" };
+    let mut endpoint = Endpoint::from_shared(format!("{scheme}://{master}"))
+        .with_context(|| format!("invalid master address {master}"))?
+        .connect_timeout(Duration::from_secs(grpc.connect_timeout_secs))
+        .http2_keep_alive_interval(Duration::from_secs(grpc.keepalive.time_secs))
+        .keep_alive_timeout(Duration::from_secs(grpc.keepalive.timeout_secs))
+        .keep_alive_while_idle(grpc.keepalive.permit_without_calls);
+    if tls.enable {
+        endpoint = endpoint.tls_config(cert::client_tls_config(tls)?)?;
+    }
+    endpoint
+        .connect()
+        .await
+        .with_context(|| format!("failed to connect to {master}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::proto::LogBatch;
+
+    fn result(id: &str) -> agent_message::Body {
+        agent_message::Body::Result(CmdResult {
+            id: id.to_string(),
+            status: "ok".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn result_id(msg: &AgentMessage) -> &str {
+        match &msg.body {
+            Some(agent_message::Body::Result(result)) => &result.id,
+            other => panic!("unexpected message {other:?}"),
+        }
+    }
+
+    /// 登记一个在途命令，执行 delay 后经出站队列回复
+    fn spawn_job(client: &mut GrpcClient, cmd_id: &str, delay: Duration) {
+        let outbound = client.outbound.clone();
+        let body = result(cmd_id);
+        let handle = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = outbound.send(body).await;
+        });
+        client.jobs.push(Job {
+            cmd_id: cmd_id.to_string(),
+            handle: handle.abort_handle(),
+        });
+    }
+
+    #[tokio::test]
+    async fn jobs_past_the_drain_timeout_are_cancelled() {
+        let (outbound, rx) = outbound_channel();
+        let mut client = GrpcClient::new(outbound, rx);
+        spawn_job(&mut client, "fast", Duration::from_millis(10));
+        spawn_job(&mut client, "slow", Duration::from_secs(60));
+        let (tx, mut stream) = mpsc::channel(8);
+
+        let begin = std::time::Instant::now();
+        client
+            .finish_jobs(Some(&tx), Duration::from_millis(300))
+            .await;
+        assert!(begin.elapsed() < Duration::from_secs(5));
+        assert!(client.jobs.is_empty());
+
+        // 按时完成的结果照常转发到 stream
+        assert_eq!(result_id(&stream.try_recv().unwrap()), "fast");
+        assert!(stream.try_recv().is_err());
+        // 超时的命令被取消，cancelled 结果留在出站队列中
+        let cancelled = client.outbound_rx.try_recv().unwrap();
+        assert_eq!(result_id(&cancelled), "slow");
+        let Some(agent_message::Body::Result(cancelled)) = cancelled.body else {
+            unreachable!()
+        };
+        assert_eq!(cancelled.status, "cancelled");
+        assert!(client.outbound_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn unsent_messages_are_spooled_and_replayed_on_the_next_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+
+        let (outbound, rx) = outbound_channel();
+        outbound.try_send(result("r-1")).unwrap();
+        outbound
+            .try_send(agent_message::Body::Heartbeat(Heartbeat::default()))
+            .unwrap();
+        outbound
+            .try_send(agent_message::Body::Logs(LogBatch {
+                id: "l-1".to_string(),
+                ..Default::default()
+            }))
+            .unwrap();
+        park_unsent(rx);
+        // 心跳不落盘
+        assert_eq!(persist_unsent(&storage).unwrap(), 2);
+        assert_eq!(persist_unsent(&storage).unwrap(), 0);
+        assert_eq!(storage.spooled_outbound().unwrap().len(), 2);
+
+        let (tx, mut stream) = mpsc::channel(64);
+        let receiver = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(msg) = stream.recv().await {
+                received.push(msg);
+            }
+            received
+        });
+        replay_spooled(&tx, &storage).await.unwrap();
+        drop(tx);
+        let received = receiver.await.unwrap();
+
+        assert_eq!(received.len(), 2);
+        assert_eq!(result_id(&received[0]), "r-1");
+        assert!(matches!(
+            &received[1].body,
+            Some(agent_message::Body::Logs(logs)) if logs.id == "l-1"
+        ));
+        assert!(storage.spooled_outbound().unwrap().is_empty());
+    }
+}
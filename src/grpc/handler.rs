@@ -0,0 +1,157 @@
+//! ControlCmd dispatch: routes commands from the master to registered handlers.
+
+use crate::agent::state::{self, AgentState};
+use crate::grpc::proto::{CmdResult, ControlCmd};
+use crate::utils::time::now_millis;
+use anyhow::Result;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// 单个控制命令的处理器
+#[async_trait]
+pub trait CommandHandler: Send + Sync {
+    async fn handle(&self, cmd: &ControlCmd) -> Result<CmdResult>;
+}
+
+/// 构造命令结果，id 与 ts 由 Dispatcher 填充
+pub fn reply(status: &str, message: impl Into<String>) -> CmdResult {
+    CmdResult {
+        status: status.to_string(),
+        message: message.into(),
+        ..Default::default()
+    }
+}
+
+/// 正在执行的命令数
+static IN_FLIGHT: Lazy<watch::Sender<usize>> = Lazy::new(|| watch::Sender::new(0));
+
+/// 在途计数守卫，命令结束（含 panic）时递减
+struct InFlight;
+
+impl InFlight {
+    fn enter() -> Self {
+        IN_FLIGHT.send_modify(|n| *n += 1);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.send_modify(|n| *n = n.saturating_sub(1));
+    }
+}
+
+/// 等待所有在途命令执行完毕
+pub async fn wait_idle() {
+    let mut rx = IN_FLIGHT.subscribe();
+    let _ = rx.wait_for(|n| *n == 0).await;
+}
+
+/// 按 `ControlCmd.cmd` 分发到处理器
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: HashMap<&'static str, Arc<dyn CommandHandler>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, cmd: &'static str, handler: Arc<dyn CommandHandler>) {
+        self.handlers.insert(cmd, handler);
+    }
+
+    /// 已注册的命令名，作为心跳中的 capabilities 上报
+    pub fn commands(&self) -> Vec<String> {
+        let mut cmds: Vec<String> = self.handlers.keys().map(|k| k.to_string()).collect();
+        cmds.sort();
+        cmds
+    }
+
+    pub async fn dispatch(&self, cmd: ControlCmd) -> CmdResult {
+        self.dispatch_in(state::current(), cmd).await
+    }
+
+    /// 按命令到达时的 agent 状态分发
+    async fn dispatch_in(&self, state: AgentState, cmd: ControlCmd) -> CmdResult {
+        let _in_flight = InFlight::enter();
+        let mut result = match self.handlers.get(cmd.cmd.as_str()) {
+            // 更新与退出期间不再接受新命令
+            Some(_) if state == AgentState::Updating => reply("rejected", "agent is updating"),
+            Some(_) if state == AgentState::Draining => reply("rejected", "agent is shutting down"),
+            Some(handler) => match handler.handle(&cmd).await {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!(cmd_id = %cmd.id, cmd = %cmd.cmd, error = %format!("{e:#}"), "command failed");
+                    reply("failed", format!("{e:#}"))
+                }
+            },
+            None => {
+                tracing::warn!(cmd_id = %cmd.id, cmd = %cmd.cmd, "unknown command");
+                reply("rejected", format!("unknown command: {}", cmd.cmd))
+            }
+        };
+        result.id = cmd.id;
+        result.ts = now_millis();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 记录调用次数的处理器
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    #[async_trait]
+    impl CommandHandler for Counting {
+        async fn handle(&self, _cmd: &ControlCmd) -> Result<CmdResult> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(reply("ok", "done"))
+        }
+    }
+
+    fn dispatcher(handler: &Arc<Counting>) -> Dispatcher {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register("test.echo", handler.clone());
+        dispatcher
+    }
+
+    fn cmd(id: &str) -> ControlCmd {
+        ControlCmd {
+            id: id.to_string(),
+            cmd: "test.echo".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_commands_while_draining_or_updating() {
+        let handler = Arc::new(Counting::default());
+        let dispatcher = dispatcher(&handler);
+
+        let result = dispatcher
+            .dispatch_in(AgentState::Draining, cmd("c-1"))
+            .await;
+        assert_eq!(result.id, "c-1");
+        assert_eq!(result.status, "rejected");
+        assert_eq!(result.message, "agent is shutting down");
+
+        let result = dispatcher
+            .dispatch_in(AgentState::Updating, cmd("c-2"))
+            .await;
+        assert_eq!(result.status, "rejected");
+        assert_eq!(handler.0.load(Ordering::SeqCst), 0);
+
+        let result = dispatcher.dispatch_in(AgentState::Online, cmd("c-3")).await;
+        assert_eq!((result.id.as_str(), result.status.as_str()), ("c-3", "ok"));
+        assert_eq!(handler.0.load(Ordering::SeqCst), 1);
+    }
+}
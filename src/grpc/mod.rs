@@ -1,2 +1,57 @@
 mod auth;
+pub mod client;
+pub mod handler;
 mod reconnect;
+
+use crate::agent::service::{Context, Subsystem};
+use crate::agent::state::{self, AgentState};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+pub mod proto {
+    tonic::include_proto!("agent");
+}
+
+/// gRPC 子系统：在其他子系统注册完命令处理器后启动 master 连接
+#[derive(Default)]
+pub struct GrpcSubsystem {
+    shutdown: Option<watch::Sender<bool>>,
+    task: Option<JoinHandle<mpsc::Receiver<proto::AgentMessage>>>,
+}
+
+#[async_trait]
+impl Subsystem for GrpcSubsystem {
+    fn name(&self) -> &'static str {
+        "grpc"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        let client = ctx
+            .client
+            .take()
+            .ok_or_else(|| anyhow!("gRPC client already started"))?;
+        state::transition(AgentState::Connecting, "starting master connection")?;
+        let (tx, rx) = watch::channel(false);
+        self.task = Some(tokio::spawn(client.run(rx)));
+        self.shutdown = Some(tx);
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send_replace(true);
+        }
+        if let Some(task) = self.task.take() {
+            client::park_unsent(task.await?);
+        }
+        Ok(())
+    }
+
+    /// 需覆盖在途命令的 drain 超时以及发送剩余消息的时间
+    fn stop_timeout(&self) -> Duration {
+        Duration::from_secs(crate::config::global().basic.drain_timeout_secs + 10)
+    }
+}
@@ -1 +1,48 @@
+//! Exponential reconnect backoff driven by `ReconnectConfig`.
 
+use crate::config::schema::ReconnectConfig;
+use std::time::Duration;
+
+/// 重连退避：按乘数递增间隔，不超过 max_backoff_secs。
+/// 连续失败 max_attempts 次后切换到下一个 master 并重新计数。
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    max_attempts: u32,
+    attempts: u32,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(cfg: &ReconnectConfig) -> Self {
+        let initial = Duration::from_secs(cfg.initial_backoff_secs.max(1));
+        Self {
+            initial,
+            max: Duration::from_secs(cfg.max_backoff_secs.max(cfg.initial_backoff_secs)),
+            multiplier: cfg.backoff_multiplier.max(1.0),
+            max_attempts: cfg.max_attempts.max(1),
+            attempts: 0,
+            current: initial,
+        }
+    }
+
+    /// 连接成功后重置
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+        self.current = self.initial;
+    }
+
+    /// 记录一次失败并返回下一次重试前的等待时间
+    pub fn next_delay(&mut self) -> Duration {
+        self.attempts += 1;
+        let delay = self.current;
+        self.current = self.current.mul_f64(self.multiplier).min(self.max);
+        delay
+    }
+
+    /// 当前 master 的重试次数是否已用尽
+    pub fn exhausted(&self) -> bool {
+        self.attempts >= self.max_attempts
+    }
+}
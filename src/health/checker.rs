@@ -0,0 +1,65 @@
+//! Component health registry shared by all subsystems.
+
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Starting,
+    Healthy,
+    Degraded(String),
+    Failed(String),
+    Stopped,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Starting => write!(f, "starting"),
+            Status::Healthy => write!(f, "healthy"),
+            Status::Degraded(msg) => write!(f, "degraded: {msg}"),
+            Status::Failed(msg) => write!(f, "failed: {msg}"),
+            Status::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+/// 各组件的最新健康状态
+#[derive(Default)]
+pub struct HealthChecker {
+    components: RwLock<BTreeMap<String, Status>>,
+}
+
+static CHECKER: Lazy<HealthChecker> = Lazy::new(HealthChecker::default);
+
+pub fn checker() -> &'static HealthChecker {
+    &CHECKER
+}
+
+impl HealthChecker {
+    pub fn set(&self, component: &str, status: Status) {
+        let mut components = self.components.write().unwrap_or_else(|e| e.into_inner());
+        components.insert(component.to_string(), status);
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, Status> {
+        self.components
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// 汇总状态：任一组件失败为 failed，任一组件降级或未就绪为 degraded
+    pub fn overall(&self) -> &'static str {
+        let components = self.snapshot();
+        if components.values().any(|s| matches!(s, Status::Failed(_))) {
+            "failed"
+        } else if components.values().all(|s| *s == Status::Healthy) {
+            "healthy"
+        } else {
+            "degraded"
+        }
+    }
+}
@@ -0,0 +1,4 @@
+mod checker;
+pub mod reporter;
+
+pub use checker::{Status, checker};
@@ -0,0 +1,98 @@
+//! Periodic health report to the master.
+
+use crate::agent::identity;
+use crate::agent::service::{Context, Subsystem};
+use crate::agent::state::{self, AgentState};
+use crate::agent::supervisor::{ChildSpec, Restart, Strategy, Supervisor, SupervisorHandle};
+use crate::grpc::client::Outbound;
+use crate::grpc::proto::{HealthReport, agent_message};
+use crate::health::checker;
+use crate::utils::time::now_millis;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::sync::watch;
+
+#[derive(Default)]
+pub struct HealthReporter {
+    supervisor: Option<SupervisorHandle>,
+}
+
+/// 构造当前健康报告
+pub fn report() -> HealthReport {
+    let checker = checker();
+    HealthReport {
+        id: identity::agent_id(),
+        ts: now_millis(),
+        status: checker.overall().to_string(),
+        components: checker
+            .snapshot()
+            .into_iter()
+            .map(|(name, status)| (name, status.to_string()))
+            .collect(),
+    }
+}
+
+/// 根据组件健康状况在 online / degraded 之间切换
+fn sync_state() {
+    let healthy = checker().overall() == "healthy";
+    match state::current() {
+        AgentState::Online if !healthy => {
+            state::try_transition(AgentState::Degraded, "component unhealthy");
+        }
+        AgentState::Degraded if healthy => {
+            state::try_transition(AgentState::Online, "all components healthy");
+        }
+        _ => {}
+    }
+}
+
+/// 周期上报循环
+async fn run(outbound: Outbound, mut shutdown: watch::Receiver<bool>) -> Result<()> {
+    loop {
+        let interval = Duration::from_secs(crate::config::global().grpc.heartbeat_interval_secs);
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.changed() => return Ok(()),
+        }
+        sync_state();
+        // 离线时不排队健康报告，等待重新连上 master
+        tokio::select! {
+            _ = state::wait_for(|s| s.is_connected()) => {}
+            _ = shutdown.changed() => return Ok(()),
+        }
+        if outbound
+            .send(agent_message::Body::Health(report()))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait]
+impl Subsystem for HealthReporter {
+    fn name(&self) -> &'static str {
+        "health"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        let outbound = ctx.outbound.clone();
+        // 健康上报需要一直运行，任何退出都重新启动
+        let supervisor = Supervisor::new("health", Strategy::OneForOne).child(ChildSpec::new(
+            "reporter",
+            Restart::Permanent,
+            move |shutdown| run(outbound.clone(), shutdown),
+        ));
+        self.supervisor = Some(supervisor.spawn());
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.stop().await;
+        }
+        Ok(())
+    }
+}
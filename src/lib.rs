@@ -5,24 +5,25 @@ mod config;
 mod error;
 mod executor;
 mod grpc;
+mod health;
 mod plugin;
+mod security;
 mod storage;
 mod telemetry;
 mod utils;
 
-use crate::telemetry::logging::init_logging;
+pub use agent::{bundle, updater};
+pub use telemetry::{format, journald, redact, syslog};
+
 use anyhow::Result;
 use clap::Parser;
 
 pub fn run() -> Result<()> {
     let cli = cli::Cli::parse();
     match cli.command {
-        cli::Commands::Run(run_cmd) => {
-            run_cmd.execute();
-        }
+        cli::Commands::Run(run_cmd) => run_cmd.execute(),
+        cli::Commands::Id(id_cmd) => id_cmd.execute(),
+        cli::Commands::Update(update_cmd) => update_cmd.execute(),
+        cli::Commands::Log(log_cmd) => log_cmd.execute(),
     }
-    let cfg = config::global();
-    let _logger = init_logging(&cfg.telemetry);
-    tracing::info!("Warden CLI started");
-    Ok(())
 }
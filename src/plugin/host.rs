@@ -0,0 +1,111 @@
+//! Plugin host: loads and validates plugins listed in the manifest.
+//!
+//! Each plugin gets its entry from `basic.plugin_credentials`, still wrapped
+//! in [`Secret`]. The agent labels are written to `<plugin_dir>/labels.json` for the loaded
+//! plugins and rewritten whenever a config change alters them.
+
+use crate::agent::labels;
+use crate::agent::service::{Context, Subsystem};
+use crate::config::secret::Secret;
+use crate::plugin::{loader, validator};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// 已加载的插件
+#[derive(Debug, Clone)]
+pub struct LoadedPlugin {
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+    pub credential: Option<Secret<String>>, // basic.plugin_credentials 中的同名凭据
+}
+
+#[derive(Default)]
+pub struct PluginHost {
+    plugins: Vec<LoadedPlugin>,
+    shutdown: Option<watch::Sender<bool>>,
+    labels_task: Option<JoinHandle<()>>,
+}
+
+/// 写入标签文件，之后在配置变更导致标签变化时重写
+fn spawn_labels_writer(dir: PathBuf, mut shutdown: watch::Receiver<bool>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut cfg_rx = crate::config::subscribe();
+        let mut written = None;
+        loop {
+            cfg_rx.borrow_and_update();
+            let current = labels::current();
+            if written.as_ref() != Some(&current) {
+                match loader::save_labels(&dir, &current) {
+                    Ok(()) => written = Some(current),
+                    Err(e) => {
+                        tracing::warn!(error = %format!("{e:#}"), "failed to write plugin labels")
+                    }
+                }
+            }
+            tokio::select! {
+                _ = cfg_rx.changed() => {}
+                _ = shutdown.changed() => return,
+            }
+        }
+    })
+}
+
+#[async_trait]
+impl Subsystem for PluginHost {
+    fn name(&self) -> &'static str {
+        "plugins"
+    }
+
+    async fn start(&mut self, _ctx: &mut Context) -> Result<()> {
+        let cfg = crate::config::global();
+        let dir = Path::new(&cfg.basic.plugin_dir);
+        let manifest = loader::load_manifest(dir)?;
+        let credentials = &cfg.basic.plugin_credentials;
+        for entry in manifest.plugins {
+            // 单个插件无效不影响 agent 启动
+            match validator::validate_entry(dir, &entry) {
+                Ok(path) => {
+                    let credential = credentials.get(&entry.name).cloned();
+                    tracing::info!(plugin = %entry.name, version = %entry.version, has_credential = credential.is_some(), "plugin loaded");
+                    self.plugins.push(LoadedPlugin {
+                        name: entry.name,
+                        version: entry.version,
+                        path,
+                        credential,
+                    });
+                }
+                Err(e) => {
+                    tracing::warn!(plugin = %entry.name, error = %format!("{e:#}"), "plugin skipped")
+                }
+            }
+        }
+        for name in credentials.keys() {
+            if !self.plugins.iter().any(|p| &p.name == name) {
+                tracing::warn!(plugin = %name, "credential configured for a plugin that is not loaded");
+            }
+        }
+        if !self.plugins.is_empty() {
+            let (tx, rx) = watch::channel(false);
+            self.labels_task = Some(spawn_labels_writer(dir.to_path_buf(), rx));
+            self.shutdown = Some(tx);
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send_replace(true);
+        }
+        if let Some(task) = self.labels_task.take() {
+            task.await?;
+        }
+        for plugin in self.plugins.drain(..) {
+            tracing::debug!(plugin = %plugin.name, version = %plugin.version, path = %plugin.path.display(), has_credential = plugin.credential.is_some(), "plugin unloaded");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! Plugin installation from update bundles.
+//!
+//! Files are written into `plugin_dir` and merged into the manifest by plugin
+//! name. Replaced files are kept as `<file>.prev` and the previous manifest as
+//! `manifest.json.prev`, described by a [`Backup`] that is stored with the
+//! update record, so a failed install or a rolled back agent update restores
+//! the plugin set it replaced.
+
+use crate::agent::updater;
+use crate::plugin::loader::{self, MANIFEST_FILE, PluginEntry};
+use crate::plugin::validator;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 待安装的插件文件
+pub struct PluginFile<'a> {
+    pub name: String,
+    pub version: String,
+    pub entry: String, // 相对 plugin_dir 的入口文件
+    pub data: &'a [u8],
+}
+
+/// 安装前的插件状态，用于回滚
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub plugin_dir: PathBuf,
+    pub replaced: Vec<PathBuf>, // 被替换的文件，旧内容保存在 <file>.prev
+    pub created: Vec<PathBuf>,  // 新建的文件，回滚时删除
+    pub manifest_existed: bool, // 安装前清单是否存在，存在时保存在 manifest.json.prev
+    pub manifest_written: bool,
+}
+
+fn manifest_backup_path(plugin_dir: &Path) -> PathBuf {
+    plugin_dir.join(format!("{MANIFEST_FILE}.prev"))
+}
+
+/// 安装插件并更新清单；任一步失败时恢复已做的修改
+pub fn install(plugin_dir: &Path, plugins: &[PluginFile<'_>]) -> Result<Backup> {
+    let mut backup = Backup {
+        plugin_dir: plugin_dir.to_path_buf(),
+        ..Default::default()
+    };
+    match install_files(plugin_dir, plugins, &mut backup) {
+        Ok(()) => Ok(backup),
+        Err(e) => {
+            if let Err(restore_err) = restore(&backup) {
+                tracing::error!(error = %format!("{restore_err:#}"), "failed to restore plugins");
+            }
+            Err(e)
+        }
+    }
+}
+
+fn install_files(plugin_dir: &Path, plugins: &[PluginFile<'_>], backup: &mut Backup) -> Result<()> {
+    let entries: Vec<PluginEntry> = plugins
+        .iter()
+        .map(|p| PluginEntry {
+            name: p.name.clone(),
+            version: p.version.clone(),
+            entry: p.entry.clone(),
+        })
+        .collect();
+    for entry in &entries {
+        validator::check_entry_path(entry)?;
+    }
+    // 先读取清单，清单损坏时不改动任何文件
+    let mut manifest = loader::load_manifest(plugin_dir)?;
+
+    for (plugin, entry) in plugins.iter().zip(&entries) {
+        let path = plugin_dir.join(&entry.entry);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let staged = updater::stage(plugin.data, &path)?;
+        if path.exists() {
+            updater::install(&staged, &path)?;
+            backup.replaced.push(path);
+        } else {
+            fs::rename(&staged, &path)
+                .with_context(|| format!("failed to install {}", path.display()))?;
+            backup.created.push(path);
+        }
+    }
+
+    let manifest_path = plugin_dir.join(MANIFEST_FILE);
+    if manifest_path.exists() {
+        fs::copy(&manifest_path, manifest_backup_path(plugin_dir))
+            .with_context(|| format!("failed to back up {}", manifest_path.display()))?;
+        backup.manifest_existed = true;
+    }
+    for entry in entries {
+        match manifest.plugins.iter_mut().find(|p| p.name == entry.name) {
+            Some(existing) => *existing = entry,
+            None => manifest.plugins.push(entry),
+        }
+    }
+    backup.manifest_written = true;
+    loader::save_manifest(plugin_dir, &manifest)?;
+
+    for plugin in &manifest.plugins {
+        if plugins.iter().any(|p| p.name == plugin.name) {
+            validator::validate_entry(plugin_dir, plugin)?;
+        }
+    }
+    Ok(())
+}
+
+/// 恢复安装前的插件文件与清单
+pub fn restore(backup: &Backup) -> Result<()> {
+    for path in &backup.replaced {
+        updater::rollback(path)?;
+    }
+    for path in &backup.created {
+        if path.exists() {
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+    }
+    if backup.manifest_written {
+        let manifest_path = backup.plugin_dir.join(MANIFEST_FILE);
+        if backup.manifest_existed {
+            fs::rename(manifest_backup_path(&backup.plugin_dir), &manifest_path)
+                .with_context(|| format!("failed to restore {}", manifest_path.display()))?;
+        } else if manifest_path.exists() {
+            fs::remove_file(&manifest_path)
+                .with_context(|| format!("failed to remove {}", manifest_path.display()))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! Plugin manifest loading (`<plugin_dir>/manifest.json`).
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// 交给插件读取的 agent 标签
+pub const LABELS_FILE: &str = "labels.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginManifest {
+    #[serde(default)]
+    pub plugins: Vec<PluginEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginEntry {
+    pub name: String,
+    pub version: String,
+    pub entry: String, // 相对 plugin_dir 的入口文件
+}
+
+/// 读取插件清单；清单不存在或为空时视为没有插件
+pub fn load_manifest(plugin_dir: &Path) -> Result<PluginManifest> {
+    let path = plugin_dir.join(MANIFEST_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(PluginManifest::default()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    if content.trim().is_empty() {
+        return Ok(PluginManifest::default());
+    }
+    serde_json::from_str(&content).with_context(|| format!("invalid manifest {}", path.display()))
+}
+
+/// 先写临时文件再 rename 保存插件清单
+pub fn save_manifest(plugin_dir: &Path, manifest: &PluginManifest) -> Result<()> {
+    let path = plugin_dir.join(MANIFEST_FILE);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(manifest)?)
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// 先写临时文件再 rename 保存 agent 标签，插件读取时不会看到写了一半的文件
+pub fn save_labels(plugin_dir: &Path, labels: &BTreeMap<String, String>) -> Result<()> {
+    let path = plugin_dir.join(LABELS_FILE);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(labels)?)
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
@@ -1 +1,4 @@
+pub mod host;
+pub mod installer;
+mod loader;
 mod validator;
@@ -1 +1,39 @@
+//! Plugin entry validation.
 
+use crate::plugin::loader::PluginEntry;
+use anyhow::{Result, anyhow};
+use std::path::{Component, Path, PathBuf};
+
+/// 校验插件入口：必须是 plugin_dir 内的相对路径且文件存在
+pub fn validate_entry(plugin_dir: &Path, entry: &PluginEntry) -> Result<PathBuf> {
+    check_entry_path(entry)?;
+    let path = plugin_dir.join(&entry.entry);
+    if !path.is_file() {
+        return Err(anyhow!(
+            "plugin {} entry not found: {}",
+            entry.name,
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
+/// 校验插件名与入口路径，不检查文件是否存在
+pub fn check_entry_path(entry: &PluginEntry) -> Result<()> {
+    if entry.name.trim().is_empty() {
+        return Err(anyhow!("plugin name is empty"));
+    }
+    let rel = Path::new(&entry.entry);
+    if rel.is_absolute()
+        || rel
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(anyhow!(
+            "plugin {} entry escapes plugin_dir: {}",
+            entry.name,
+            entry.entry
+        ));
+    }
+    Ok(())
+}
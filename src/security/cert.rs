@@ -0,0 +1,27 @@
+//! Certificate loading for the master connection (CA bundle and client identity).
+
+use crate::config::schema::TlsConfig;
+use anyhow::{Context, Result};
+use std::fs;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+/// 根据 TlsConfig 构建 gRPC 客户端 TLS 配置
+pub fn client_tls_config(cfg: &TlsConfig) -> Result<ClientTlsConfig> {
+    let mut tls = ClientTlsConfig::new();
+    if !cfg.ca_file.is_empty() {
+        let ca = fs::read(&cfg.ca_file)
+            .with_context(|| format!("failed to read ca_file {}", cfg.ca_file))?;
+        tls = tls.ca_certificate(Certificate::from_pem(ca));
+    }
+    if !cfg.cert_file.is_empty() && !cfg.key_file.is_empty() {
+        let cert = fs::read(&cfg.cert_file)
+            .with_context(|| format!("failed to read cert_file {}", cfg.cert_file))?;
+        // 私钥路径属于敏感配置，错误信息中不输出
+        let key = fs::read(cfg.key_file.expose()).context("failed to read tls key_file")?;
+        tls = tls.identity(Identity::from_pem(cert, key));
+    }
+    if !cfg.server_name_override.is_empty() {
+        tls = tls.domain_name(cfg.server_name_override.clone());
+    }
+    Ok(tls)
+}
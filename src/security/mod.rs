@@ -0,0 +1,27 @@
+pub mod cert;
+
+use crate::agent::service::{Context, Subsystem};
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+
+/// 安全子系统：启动时校验 TLS 证书与私钥可读，避免连接阶段才发现配置错误
+pub struct SecuritySubsystem;
+
+#[async_trait]
+impl Subsystem for SecuritySubsystem {
+    fn name(&self) -> &'static str {
+        "security"
+    }
+
+    async fn start(&mut self, _ctx: &mut Context) -> Result<()> {
+        let cfg = crate::config::global();
+        if cfg.tls.enable {
+            cert::client_tls_config(&cfg.tls).context("invalid tls configuration")?;
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1 +1,57 @@
 mod sqlite;
+
+pub use sqlite::{EventRecord, Storage, UpdateRecord};
+
+use crate::agent::service::{Context, Subsystem};
+use anyhow::Result;
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use std::path::Path;
+use std::sync::Arc;
+
+static GLOBAL_STORAGE: OnceCell<Arc<Storage>> = OnceCell::new();
+
+/// 打开 SQLite 数据库并执行迁移，保存为全局实例
+pub fn init_global<P: AsRef<Path>>(path: P) -> Result<()> {
+    let storage = Arc::new(Storage::open(path)?);
+    GLOBAL_STORAGE
+        .set(storage)
+        .map_err(|_| anyhow::anyhow!("Global storage already initialized"))?;
+    Ok(())
+}
+
+pub fn global() -> Arc<Storage> {
+    GLOBAL_STORAGE
+        .get()
+        .expect("Global storage not initialized")
+        .clone()
+}
+
+/// 存储尚未初始化时返回 None（例如启动早期的状态迁移）
+pub fn try_global() -> Option<Arc<Storage>> {
+    GLOBAL_STORAGE.get().cloned()
+}
+
+/// 存储子系统：打开数据库，退出时保存未发出的消息（连接 master 后重发）
+pub struct StorageSubsystem;
+
+#[async_trait]
+impl Subsystem for StorageSubsystem {
+    fn name(&self) -> &'static str {
+        "storage"
+    }
+
+    async fn start(&mut self, _ctx: &mut Context) -> Result<()> {
+        init_global(&crate::config::global().basic.sqlite_path)?;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        // 其余子系统均已停止，出站队列中剩余的数据不会再被发送
+        let spooled = crate::grpc::client::persist_unsent(&global())?;
+        if spooled > 0 {
+            tracing::info!(count = spooled, "unsent messages persisted");
+        }
+        global().checkpoint()
+    }
+}
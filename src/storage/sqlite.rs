@@ -1 +1,369 @@
+//! SQLite storage: schema migrations, event log and persisted agent state.
 
+use anyhow::{Context, Result, anyhow};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+/// Embedded migrations, applied in order and tracked via `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../data/migrations/00001_init.sql"),
+    include_str!("../../data/migrations/00002_config_layers.sql"),
+    include_str!("../../data/migrations/00003_outbox.sql"),
+    include_str!("../../data/migrations/00004_deferred_commands.sql"),
+];
+
+/// 远程下发的配置层（合并后的完整补丁）
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    pub id: i64,
+    pub cmd_id: String,
+    pub layer: serde_json::Value,
+    pub state: String,
+}
+
+/// 维护窗口外被推迟执行的控制命令
+#[derive(Debug, Clone)]
+pub struct DeferredCommand {
+    pub id: i64,
+    pub cmd_id: String,
+    pub cmd: String,
+    pub payload: Vec<u8>,
+}
+
+/// `updates` 表中的一条升级记录
+#[derive(Debug, Clone)]
+pub struct UpdateRecord {
+    pub id: i64,
+    pub version: String,
+    pub meta: serde_json::Value,
+}
+
+/// `events` 表中的一条事件
+#[derive(Debug, Clone)]
+pub struct EventRecord {
+    pub id: i64,
+    pub payload: serde_json::Value,
+    pub created_at: String,
+}
+
+/// SQLite-backed agent storage, shared behind a mutex.
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open sqlite db {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Self::migrate(&conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn migrate(conn: &Connection) -> Result<()> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(sql)
+                .with_context(|| format!("migration {} failed", i + 1))?;
+            conn.pragma_update(None, "user_version", i + 1)?;
+        }
+        Ok(())
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 将 WAL 合并回主库，在退出前调用
+    pub fn checkpoint(&self) -> Result<()> {
+        self.conn()
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+    /// 记录事件到 events 表
+    pub fn record_event(&self, kind: &str, payload: &serde_json::Value) -> Result<()> {
+        self.conn().execute(
+            "INSERT INTO events (kind, payload) VALUES (?1, ?2)",
+            params![kind, payload.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// 连接正被占用时立即失败而不等待，供 panic hook 使用（panic 可能发生在持锁期间）
+    pub fn try_record_event(&self, kind: &str, payload: &serde_json::Value) -> Result<()> {
+        let conn = match self.conn.try_lock() {
+            Ok(conn) => conn,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(anyhow!("storage is busy")),
+        };
+        conn.execute(
+            "INSERT INTO events (kind, payload) VALUES (?1, ?2)",
+            params![kind, payload.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// 指定类型最近的一条事件
+    pub fn last_event(&self, kind: &str) -> Result<Option<EventRecord>> {
+        let row = self
+            .conn()
+            .query_row(
+                "SELECT id, payload, created_at FROM events
+                 WHERE kind = ?1 ORDER BY id DESC LIMIT 1",
+                params![kind],
+                |r| {
+                    Ok((
+                        r.get::<_, i64>(0)?,
+                        r.get::<_, Option<String>>(1)?,
+                        r.get::<_, String>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        row.map(|(id, payload, created_at)| {
+            let payload = match payload {
+                Some(payload) => serde_json::from_str(&payload).context("corrupt event payload")?,
+                None => serde_json::Value::Null,
+            };
+            Ok(EventRecord {
+                id,
+                payload,
+                created_at,
+            })
+        })
+        .transpose()
+    }
+
+    /// 当前生效的配置层（最近一条 pending/applied 记录）
+    pub fn active_config_layer(&self) -> Result<Option<ConfigLayer>> {
+        self.query_config_layer(
+            "SELECT id, cmd_id, layer, state FROM config_layers
+             WHERE state IN ('pending', 'applied') ORDER BY id DESC LIMIT 1",
+            params![],
+        )
+    }
+
+    /// 指定记录之前最近一次确认生效的配置层，用于回滚
+    pub fn previous_applied_config_layer(&self, before_id: i64) -> Result<Option<ConfigLayer>> {
+        self.query_config_layer(
+            "SELECT id, cmd_id, layer, state FROM config_layers
+             WHERE state = 'applied' AND id < ?1 ORDER BY id DESC LIMIT 1",
+            params![before_id],
+        )
+    }
+
+    pub fn insert_config_layer(
+        &self,
+        cmd_id: &str,
+        layer: &serde_json::Value,
+        state: &str,
+    ) -> Result<i64> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO config_layers (cmd_id, layer, state) VALUES (?1, ?2, ?3)",
+            params![cmd_id, layer.to_string(), state],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn set_config_layer_state(&self, id: i64, state: &str) -> Result<()> {
+        self.conn().execute(
+            "UPDATE config_layers SET state = ?1 WHERE id = ?2",
+            params![state, id],
+        )?;
+        Ok(())
+    }
+
+    fn query_config_layer(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Option<ConfigLayer>> {
+        let row = self
+            .conn()
+            .query_row(sql, params, |r| {
+                Ok((
+                    r.get::<_, i64>(0)?,
+                    r.get::<_, Option<String>>(1)?,
+                    r.get::<_, String>(2)?,
+                    r.get::<_, String>(3)?,
+                ))
+            })
+            .optional()?;
+        row.map(|(id, cmd_id, layer, state)| {
+            Ok(ConfigLayer {
+                id,
+                cmd_id: cmd_id.unwrap_or_default(),
+                layer: serde_json::from_str(&layer).context("corrupt config layer")?,
+                state,
+            })
+        })
+        .transpose()
+    }
+
+    pub fn insert_update(
+        &self,
+        name: &str,
+        version: &str,
+        state: &str,
+        meta: &serde_json::Value,
+    ) -> Result<i64> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO updates (name, version, state, meta) VALUES (?1, ?2, ?3, ?4)",
+            params![name, version, state, meta.to_string()],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn set_update(&self, id: i64, state: &str, meta: &serde_json::Value) -> Result<()> {
+        self.conn().execute(
+            "UPDATE updates SET state = ?1, meta = ?2 WHERE id = ?3",
+            params![state, meta.to_string(), id],
+        )?;
+        Ok(())
+    }
+
+    /// 指定组件最近一条仍处于 pending 的升级记录
+    pub fn pending_update(&self, name: &str) -> Result<Option<UpdateRecord>> {
+        let row = self
+            .conn()
+            .query_row(
+                "SELECT id, version, meta FROM updates
+                 WHERE name = ?1 AND state = 'pending' ORDER BY id DESC LIMIT 1",
+                params![name],
+                |r| {
+                    Ok((
+                        r.get::<_, i64>(0)?,
+                        r.get::<_, Option<String>>(1)?,
+                        r.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        row.map(|(id, version, meta)| {
+            let meta = match meta {
+                Some(meta) => serde_json::from_str(&meta).context("corrupt update meta")?,
+                None => serde_json::Value::Object(Default::default()),
+            };
+            Ok(UpdateRecord {
+                id,
+                version: version.unwrap_or_default(),
+                meta,
+            })
+        })
+        .transpose()
+    }
+
+    /// 保存退出前未能发出的出站消息
+    pub fn spool_outbound(&self, body: &[u8]) -> Result<()> {
+        self.conn()
+            .execute("INSERT INTO outbox (body) VALUES (?1)", params![body])?;
+        Ok(())
+    }
+
+    /// 按写入顺序返回暂存的出站消息
+    pub fn spooled_outbound(&self) -> Result<Vec<(i64, Vec<u8>)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT id, body FROM outbox ORDER BY id")?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    pub fn delete_spooled(&self, id: i64) -> Result<()> {
+        self.conn()
+            .execute("DELETE FROM outbox WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    pub fn defer_command(&self, cmd_id: &str, cmd: &str, payload: &[u8]) -> Result<()> {
+        self.conn().execute(
+            "INSERT INTO deferred_commands (cmd_id, cmd, payload) VALUES (?1, ?2, ?3)",
+            params![cmd_id, cmd, payload],
+        )?;
+        Ok(())
+    }
+
+    /// 按入队顺序返回推迟的命令
+    pub fn deferred_commands(&self) -> Result<Vec<DeferredCommand>> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("SELECT id, cmd_id, cmd, payload FROM deferred_commands ORDER BY id")?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok(DeferredCommand {
+                    id: r.get(0)?,
+                    cmd_id: r.get(1)?,
+                    cmd: r.get(2)?,
+                    payload: r.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    pub fn delete_deferred(&self, id: i64) -> Result<()> {
+        self.conn()
+            .execute("DELETE FROM deferred_commands WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn try_record_event_fails_fast_while_the_connection_is_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+        storage
+            .try_record_event("test", &json!({ "n": 1 }))
+            .unwrap();
+
+        let held = storage.conn();
+        let err = storage
+            .try_record_event("test", &json!({ "n": 2 }))
+            .unwrap_err();
+        assert!(err.to_string().contains("busy"), "{err}");
+        drop(held);
+
+        let last = storage.last_event("test").unwrap().unwrap();
+        assert_eq!(last.payload, json!({ "n": 1 }));
+    }
+
+    #[test]
+    fn try_record_event_writes_through_a_poisoned_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path().join("agent.db")).unwrap();
+        // 持锁期间 panic，与 panic hook 面对的情形相同
+        std::thread::scope(|s| {
+            let _ = s
+                .spawn(|| {
+                    let _held = storage.conn.lock().unwrap();
+                    panic!("poison the connection lock");
+                })
+                .join();
+        });
+        assert!(storage.conn.is_poisoned());
+        storage
+            .try_record_event("test", &json!({ "n": 3 }))
+            .unwrap();
+        let last = storage.last_event("test").unwrap().unwrap();
+        assert_eq!(last.payload, json!({ "n": 3 }));
+    }
+}
@@ -0,0 +1,699 @@
+//! Event formatting for the stdout/file sinks.
+//!
+//! [`EventFormat`] replaces the stock `fmt` formatters so that every field
+//! value passes through the global [`Redactor`] before it is written. It
+//! encodes records as:
+//!
+//! - `json`: `timestamp`, `level`, `message` and the event fields flattened,
+//!   then `target`, `filename`, `line_number`, the current `span` and `spans`
+//! - `plain`: `TIMESTAMP LEVEL span: target: file:line: message key=value`
+//! - `logfmt`: `ts=... level=info msg="..." key=value target=... file=... line=...`
+//! - `ecs`: Elastic Common Schema (`@timestamp`, `log.level`, `log.logger`, ...)
+//! - `otel`: the OpenTelemetry log data model (`Timestamp`, `SeverityText`,
+//!   `SeverityNumber`, `Body`, `Attributes`, `Resource`)
+//!
+//! The timestamp, level and message field names can be overridden for every
+//! encoding but `plain`. Event and span fields named like one of the record's
+//! own fields (`level`, `target`, `@timestamp`, ...) are written with a
+//! `fields.` prefix instead of replacing it. Timestamps are RFC 3339 with nanoseconds in the
+//! configured timezone.
+//!
+//! Span fields are formatted (and redacted) once by [`SpanFields`] when the
+//! span is created and stored in the span extensions.
+
+pub use crate::config::schema::LogEncodingConfig;
+use crate::telemetry::redact::{self, Redactor};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt::{self, Debug};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// 写入 ECS 记录的 ecs.version
+const ECS_VERSION: &str = "8.11.0";
+
+/// ECS/OTel 记录中的服务名
+const SERVICE_NAME: &str = "warden";
+
+/// 与记录自身字段同名的事件/span 字段加上的前缀
+const FIELD_PREFIX: &str = "fields.";
+
+/// 输出编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Plain,
+    Logfmt,
+    Ecs,
+    Otel,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Ok(Encoding::Json),
+            "plain" => Ok(Encoding::Plain),
+            "logfmt" => Ok(Encoding::Logfmt),
+            "ecs" => Ok(Encoding::Ecs),
+            "otel" => Ok(Encoding::Otel),
+            other => Err(anyhow!(
+                "invalid log format: {other}, expected json, plain, logfmt, ecs or otel"
+            )),
+        }
+    }
+
+    /// span 字段是否以 JSON 存放；只有 plain 直接存放文本
+    pub fn json_spans(self) -> bool {
+        self != Encoding::Plain
+    }
+
+    /// 默认的时间、级别、消息字段名
+    fn default_names(self) -> [&'static str; 3] {
+        match self {
+            Encoding::Json | Encoding::Plain => ["timestamp", "level", "message"],
+            Encoding::Logfmt => ["ts", "level", "msg"],
+            Encoding::Ecs => ["@timestamp", "log.level", "message"],
+            Encoding::Otel => ["Timestamp", "SeverityText", "Body"],
+        }
+    }
+}
+
+/// 时间戳使用的时区
+#[derive(Debug, Clone, Copy)]
+pub enum TimeZone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+impl TimeZone {
+    /// "UTC"、"local"、固定偏移如 "+08:00"，或 IANA 名称如 "Asia/Shanghai"
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "" | "UTC" | "utc" | "Z" => Ok(TimeZone::Utc),
+            "local" | "Local" => Ok(TimeZone::Local),
+            _ if name.starts_with(['+', '-']) => name
+                .parse::<FixedOffset>()
+                .map(TimeZone::Fixed)
+                .map_err(|_| anyhow!("invalid log timezone offset: {name}")),
+            _ => name
+                .parse::<chrono_tz::Tz>()
+                .map(TimeZone::Named)
+                .map_err(|_| anyhow!("invalid log timezone: {name}")),
+        }
+    }
+
+    /// RFC 3339，纳秒精度；UTC 以 "Z" 结尾
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        match self {
+            TimeZone::Utc => time.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            TimeZone::Local => time
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Nanos, true),
+            TimeZone::Fixed(offset) => time
+                .with_timezone(offset)
+                .to_rfc3339_opts(SecondsFormat::Nanos, true),
+            TimeZone::Named(tz) => time
+                .with_timezone(tz)
+                .to_rfc3339_opts(SecondsFormat::Nanos, true),
+        }
+    }
+}
+
+/// 一个字段的取值
+enum Recorded {
+    Str(String),   // 字符串，plain 格式下加引号
+    Debug(String), // Debug 输出，原样写出
+    Json(Value),   // 数字与布尔值
+}
+
+impl Recorded {
+    fn into_json(self) -> Value {
+        match self {
+            Recorded::Str(s) | Recorded::Debug(s) => Value::String(s),
+            Recorded::Json(v) => v,
+        }
+    }
+
+    fn write_plain(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            Recorded::Str(s) => write!(out, "{s:?}"),
+            Recorded::Debug(s) => out.write_str(s),
+            Recorded::Json(v) => write!(out, "{v}"),
+        }
+    }
+}
+
+/// 按记录顺序收集字段并脱敏
+struct Collector<'r> {
+    redactor: &'r Redactor,
+    fields: Vec<(&'static str, Recorded)>,
+}
+
+impl<'r> Collector<'r> {
+    fn new(redactor: &'r Redactor) -> Self {
+        Self {
+            redactor,
+            fields: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, field: &Field, value: Recorded) {
+        let name = field.name();
+        // log 兼容字段由 target/filename 等表示
+        if name.starts_with("log.") {
+            return;
+        }
+        let name = name.strip_prefix("r#").unwrap_or(name);
+        let value = if self.redactor.is_denied(name) {
+            Recorded::Str(self.redactor.replacement().to_string())
+        } else {
+            value
+        };
+        self.fields.push((name, value));
+    }
+
+    /// 取出 message 字段
+    fn take_message(&mut self) -> Option<String> {
+        let i = self
+            .fields
+            .iter()
+            .position(|(name, _)| *name == "message")?;
+        match self.fields.remove(i).1 {
+            Recorded::Str(s) | Recorded::Debug(s) => Some(s),
+            Recorded::Json(v) => Some(v.to_string()),
+        }
+    }
+
+    fn into_pairs(self) -> Vec<(String, Value)> {
+        self.fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.into_json()))
+            .collect()
+    }
+
+    /// plain 格式：message 在前且不带字段名
+    fn write_plain(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let mut first = true;
+        if let Some((_, message)) = self.fields.iter().find(|(name, _)| *name == "message") {
+            match message {
+                Recorded::Str(s) | Recorded::Debug(s) => out.write_str(s)?,
+                Recorded::Json(v) => write!(out, "{v}")?,
+            }
+            first = false;
+        }
+        for (name, value) in self.fields.iter().filter(|(name, _)| *name != "message") {
+            if !first {
+                out.write_char(' ')?;
+            }
+            first = false;
+            write!(out, "{name}=")?;
+            value.write_plain(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl Visit for Collector<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let value = self.redactor.redact(value).into_owned();
+        // message 按 Debug 记录，plain 格式下不加引号
+        if field.name() == "message" {
+            self.push(field, Recorded::Debug(value));
+        } else {
+            self.push(field, Recorded::Str(value));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let value = format!("{value:?}");
+        let value = self.redactor.redact(&value).into_owned();
+        self.push(field, Recorded::Debug(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Recorded::Json(value.into()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Recorded::Json(value.into()));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, Recorded::Json(value.into()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Recorded::Json(value.into()));
+    }
+}
+
+/// 按给定顺序写出 JSON 对象（serde_json 的 Map 会按键排序）
+fn write_object<'a>(
+    out: &mut impl fmt::Write,
+    pairs: impl IntoIterator<Item = (&'a str, &'a Value)>,
+) -> fmt::Result {
+    out.write_char('{')?;
+    for (i, (key, value)) in pairs.into_iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        write!(out, "{}:{value}", Value::from(key))?;
+    }
+    out.write_char('}')
+}
+
+/// 有序的 JSON 对象，同名键后写入的覆盖先写入的
+#[derive(Default)]
+struct Object(Vec<(String, Value)>);
+
+impl Object {
+    fn put(&mut self, key: &str, value: Value) {
+        match self.0.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.0.push((key.to_string(), value)),
+        }
+    }
+
+    /// 只在键不存在时写入
+    fn put_absent(&mut self, key: &str, value: Value) {
+        if !self.0.iter().any(|(k, _)| k == key) {
+            self.0.push((key.to_string(), value));
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Object(self.0.into_iter().collect())
+    }
+
+    fn write(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        write_object(out, self.0.iter().map(|(k, v)| (k.as_str(), v)))
+    }
+}
+
+/// 事件/span 字段写入记录时的键，与 `reserved` 中的字段同名时加前缀
+fn field_key(name: &str, reserved: &[&str]) -> String {
+    if reserved.contains(&name) {
+        format!("{FIELD_PREFIX}{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// logfmt 的值：含空白、'='、'"' 或为空时加引号并转义
+fn write_logfmt_value(out: &mut impl fmt::Write, value: &Value) -> fmt::Result {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let needs_quotes = text.is_empty()
+        || text
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '=' | '"'));
+    if !needs_quotes {
+        return out.write_str(&text);
+    }
+    out.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// logfmt 的键不能含空白、'=' 与 '"'
+fn write_logfmt_pair(out: &mut impl fmt::Write, key: &str, value: &Value) -> fmt::Result {
+    out.write_char(' ')?;
+    for c in key.chars() {
+        if c.is_whitespace() || matches!(c, '=' | '"') {
+            out.write_char('_')?;
+        } else {
+            out.write_char(c)?;
+        }
+    }
+    out.write_char('=')?;
+    write_logfmt_value(out, value)
+}
+
+/// 格式化 span 字段：plain 以外的编码存为 JSON 对象字符串
+///
+/// 字段按类型存放在 span 扩展中，两种存放方式用不同的类型，
+/// 以便不同编码的 sink 同时存在
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpanFields<const JSON: bool>;
+
+impl<'w, const JSON: bool> FormatFields<'w> for SpanFields<JSON> {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut collector = Collector::new(redact::global());
+        fields.record(&mut collector);
+        if JSON {
+            let pairs = collector.into_pairs();
+            write_object(&mut writer, pairs.iter().map(|(k, v)| (k.as_str(), v)))
+        } else {
+            collector.write_plain(&mut writer)
+        }
+    }
+
+    fn add_fields(
+        &self,
+        current: &'w mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut collector = Collector::new(redact::global());
+        fields.record(&mut collector);
+        if collector.fields.is_empty() {
+            return Ok(());
+        }
+        if JSON {
+            // 已有字段是 JSON 对象，合并后重新序列化
+            let mut map: Map<String, Value> =
+                serde_json::from_str(&current.fields).unwrap_or_default();
+            map.extend(collector.into_pairs());
+            current.fields = Value::Object(map).to_string();
+            Ok(())
+        } else {
+            if !current.fields.is_empty() {
+                current.fields.push(' ');
+            }
+            collector.write_plain(&mut current.fields)
+        }
+    }
+}
+
+/// OTel 日志数据模型的 SeverityNumber
+fn severity_number(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE => 1,
+        Level::DEBUG => 5,
+        Level::INFO => 9,
+        Level::WARN => 13,
+        Level::ERROR => 17,
+    }
+}
+
+/// stdout/文件 sink 的事件格式
+#[derive(Debug, Clone)]
+pub struct EventFormat {
+    encoding: Encoding,
+    timestamp_field: String,
+    level_field: String,
+    message_field: String,
+    timezone: TimeZone,
+    hostname: Option<String>,
+}
+
+impl EventFormat {
+    pub fn new(encoding: Encoding, cfg: &LogEncodingConfig) -> Result<Self> {
+        let [timestamp, level, message] = encoding.default_names();
+        let name = |configured: &str, default: &str| {
+            if configured.trim().is_empty() {
+                default.to_string()
+            } else {
+                configured.trim().to_string()
+            }
+        };
+        Ok(Self {
+            encoding,
+            timestamp_field: name(&cfg.timestamp_field, timestamp),
+            level_field: name(&cfg.level_field, level),
+            message_field: name(&cfg.message_field, message),
+            timezone: TimeZone::parse(&cfg.timezone)?,
+            hostname: crate::utils::host::facts().hostname.clone(),
+        })
+    }
+
+    fn write_plain<S, const JSON: bool>(
+        &self,
+        ctx: &FmtContext<'_, S, SpanFields<JSON>>,
+        writer: &mut Writer<'_>,
+        event: &Event<'_>,
+        timestamp: &str,
+        collector: &Collector<'_>,
+    ) -> fmt::Result
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let meta = event.metadata();
+        write!(writer, "{timestamp} {:>5} ", meta.level())?;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, "{}:", span.name())?;
+            }
+            writer.write_char(' ')?;
+        }
+        write!(writer, "{}:", meta.target())?;
+        if let Some(file) = meta.file() {
+            write!(writer, " {file}:")?;
+        }
+        if let Some(line) = meta.line() {
+            write!(writer, "{line}:")?;
+        }
+        writer.write_char(' ')?;
+        collector.write_plain(writer)?;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let ext = span.extensions();
+                if let Some(fields) = ext.get::<FormattedFields<SpanFields<JSON>>>()
+                    && !fields.is_empty()
+                {
+                    write!(writer, " {}", fields.fields)?;
+                }
+            }
+        }
+        writeln!(writer)
+    }
+}
+
+/// 事件所在的 span（从根到叶）及其字段
+fn span_list<S, const JSON: bool>(
+    ctx: &FmtContext<'_, S, SpanFields<JSON>>,
+) -> Vec<(&'static str, Map<String, Value>)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some(scope) = ctx.event_scope() else {
+        return Vec::new();
+    };
+    scope
+        .from_root()
+        .map(|span| {
+            let ext = span.extensions();
+            let fields = ext
+                .get::<FormattedFields<SpanFields<JSON>>>()
+                .and_then(|f| serde_json::from_str(&f.fields).ok())
+                .unwrap_or_default();
+            (span.name(), fields)
+        })
+        .collect()
+}
+
+impl<S, const JSON: bool> FormatEvent<S, SpanFields<JSON>> for EventFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, SpanFields<JSON>>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let meta = event.metadata();
+        let timestamp = self.timezone.format(Utc::now());
+        let mut collector = Collector::new(redact::global());
+        event.record(&mut collector);
+
+        if self.encoding == Encoding::Plain {
+            return self.write_plain(ctx, &mut writer, event, &timestamp, &collector);
+        }
+
+        let message = collector.take_message();
+        let fields = collector.into_pairs();
+        let spans = span_list(ctx);
+        let mut record = Object::default();
+        record.put(&self.timestamp_field, Value::String(timestamp));
+        let core_fields = [
+            self.timestamp_field.as_str(),
+            self.level_field.as_str(),
+            self.message_field.as_str(),
+        ];
+        match self.encoding {
+            Encoding::Json => {
+                let reserved = [
+                    &core_fields[..],
+                    &["target", "filename", "line_number", "span", "spans"],
+                ]
+                .concat();
+                record.put(&self.level_field, Value::from(meta.level().as_str()));
+                if let Some(message) = message {
+                    record.put(&self.message_field, Value::String(message));
+                }
+                for (name, value) in fields {
+                    record.put(&field_key(&name, &reserved), value);
+                }
+                record.put("target", Value::from(meta.target()));
+                if let Some(file) = meta.file() {
+                    record.put("filename", Value::from(file));
+                }
+                if let Some(line) = meta.line() {
+                    record.put("line_number", Value::from(line));
+                }
+                if !spans.is_empty() {
+                    let spans: Vec<Value> = spans
+                        .into_iter()
+                        .map(|(name, fields)| {
+                            // 与 span 名同名的 span 字段加前缀
+                            let mut span: Map<String, Value> = fields
+                                .into_iter()
+                                .map(|(key, value)| (field_key(&key, &["name"]), value))
+                                .collect();
+                            span.insert("name".to_string(), Value::from(name));
+                            Value::Object(span)
+                        })
+                        .collect();
+                    record.put("span", spans[spans.len() - 1].clone());
+                    record.put("spans", Value::Array(spans));
+                }
+            }
+            Encoding::Logfmt => {
+                let reserved = [&core_fields[..], &["target", "file", "line", "span"]].concat();
+                let level = meta.level().as_str().to_ascii_lowercase();
+                record.put(&self.level_field, Value::String(level));
+                if let Some(message) = message {
+                    record.put(&self.message_field, Value::String(message));
+                }
+                for (name, value) in fields {
+                    record.put(&field_key(&name, &reserved), value);
+                }
+                record.put("target", Value::from(meta.target()));
+                if let Some(file) = meta.file() {
+                    record.put("file", Value::from(file));
+                }
+                if let Some(line) = meta.line() {
+                    record.put("line", Value::from(line));
+                }
+                if !spans.is_empty() {
+                    let span_names: Vec<&str> = spans.iter().map(|(name, _)| *name).collect();
+                    record.put("span", Value::String(span_names.join(":")));
+                    // 事件字段与内层 span 的字段优先
+                    for (_, fields) in spans.into_iter().rev() {
+                        for (name, value) in fields {
+                            record.put_absent(&field_key(&name, &reserved), value);
+                        }
+                    }
+                }
+                let mut pairs = record.0.iter();
+                if let Some((key, value)) = pairs.next() {
+                    write!(writer, "{key}=")?;
+                    write_logfmt_value(&mut writer, value)?;
+                }
+                for (key, value) in pairs {
+                    write_logfmt_pair(&mut writer, key, value)?;
+                }
+                return writeln!(writer);
+            }
+            Encoding::Ecs => {
+                let reserved = [
+                    &core_fields[..],
+                    &[
+                        "ecs.version",
+                        "log.logger",
+                        "log.origin.file.name",
+                        "log.origin.file.line",
+                        "process.pid",
+                        "host.hostname",
+                        "service.name",
+                        "span.name",
+                    ],
+                ]
+                .concat();
+                let level = meta.level().as_str().to_ascii_lowercase();
+                record.put(&self.level_field, Value::String(level));
+                if let Some(message) = message {
+                    record.put(&self.message_field, Value::String(message));
+                }
+                record.put("ecs.version", Value::from(ECS_VERSION));
+                record.put("log.logger", Value::from(meta.target()));
+                if let Some(file) = meta.file() {
+                    record.put("log.origin.file.name", Value::from(file));
+                }
+                if let Some(line) = meta.line() {
+                    record.put("log.origin.file.line", Value::from(line));
+                }
+                record.put("process.pid", Value::from(std::process::id()));
+                if let Some(host) = &self.hostname {
+                    record.put("host.hostname", Value::from(host.as_str()));
+                }
+                record.put("service.name", Value::from(SERVICE_NAME));
+                if let Some((name, _)) = spans.last() {
+                    record.put("span.name", Value::from(*name));
+                }
+                // 事件字段覆盖同名的 span 字段
+                for (_, fields) in spans {
+                    for (name, value) in fields {
+                        record.put(&field_key(&name, &reserved), value);
+                    }
+                }
+                for (name, value) in fields {
+                    record.put(&field_key(&name, &reserved), value);
+                }
+            }
+            Encoding::Otel => {
+                record.put(&self.level_field, Value::from(meta.level().as_str()));
+                record.put("SeverityNumber", Value::from(severity_number(meta.level())));
+                record.put(
+                    &self.message_field,
+                    Value::String(message.unwrap_or_default()),
+                );
+                let reserved = [
+                    "code.namespace",
+                    "code.filepath",
+                    "code.lineno",
+                    "span.name",
+                ];
+                let mut attributes = Object::default();
+                for (name, value) in fields {
+                    attributes.put(&field_key(&name, &reserved), value);
+                }
+                // 事件字段与内层 span 的字段优先
+                for (_, fields) in spans.iter().rev() {
+                    for (name, value) in fields {
+                        attributes.put_absent(&field_key(name, &reserved), value.clone());
+                    }
+                }
+                attributes.put("code.namespace", Value::from(meta.target()));
+                if let Some(file) = meta.file() {
+                    attributes.put("code.filepath", Value::from(file));
+                }
+                if let Some(line) = meta.line() {
+                    attributes.put("code.lineno", Value::from(line));
+                }
+                if let Some((name, _)) = spans.last() {
+                    attributes.put("span.name", Value::from(*name));
+                }
+                record.put("Attributes", attributes.into_value());
+                let mut resource = Object::default();
+                resource.put("service.name", Value::from(SERVICE_NAME));
+                if let Some(host) = &self.hostname {
+                    resource.put("host.name", Value::from(host.as_str()));
+                }
+                resource.put("process.pid", Value::from(std::process::id()));
+                record.put("Resource", resource.into_value());
+            }
+            Encoding::Plain => unreachable!("handled above"),
+        }
+        record.write(&mut writer)?;
+        writeln!(writer)
+    }
+}
@@ -0,0 +1,427 @@
+//! Log forwarding to the master.
+//!
+//! [`layer`] returns a tracing layer that copies records at or above
+//! `telemetry.log_forward.min_level` into a bounded in-memory buffer, subject
+//! to a per-second rate limit. The [`LogForwarder`] subsystem ships the buffer
+//! as `LogBatch` messages (with agent id and labels) through the outbound
+//! queue while the agent is connected; while offline records stay buffered,
+//! the oldest being dropped once the buffer is full. Dropped records are
+//! counted in the next batch.
+//!
+//! The global log filter still applies first: records below
+//! `telemetry.log_level` never reach the forwarder.
+
+use crate::agent::service::{Context, Subsystem};
+use crate::agent::state;
+use crate::config::schema::LogForwardConfig;
+use crate::grpc::client::Outbound;
+use crate::grpc::proto::{LogBatch, LogRecord, agent_message};
+use crate::telemetry::redact;
+use crate::utils::time::now_millis;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+
+/// 转发器自身的日志不再转发，避免循环
+const SELF_TARGET: &str = module_path!();
+
+/// 转发层与转发子系统共享的缓冲区，启用转发时由 init_logging 创建
+static BUFFER: OnceCell<Arc<ForwardBuffer>> = OnceCell::new();
+
+/// 令牌桶限速，容量为一秒的配额
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    fn new(per_sec: u32) -> Self {
+        Self {
+            rate: per_sec as f64,
+            tokens: per_sec as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// 待转发的日志记录
+struct ForwardBuffer {
+    records: Mutex<VecDeque<LogRecord>>,
+    capacity: usize,
+    batch_size: usize,
+    limiter: Option<Mutex<RateLimiter>>, // rate_limit_per_sec 为 0 时不限速
+    dropped: AtomicU64,
+    ready: Notify, // 攒够一批时唤醒转发任务
+}
+
+impl ForwardBuffer {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<LogRecord>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, record: LogRecord) {
+        if let Some(limiter) = &self.limiter
+            && !limiter.lock().unwrap_or_else(|e| e.into_inner()).allow()
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut records = self.lock();
+        if records.len() >= self.capacity {
+            records.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        records.push_back(record);
+        if records.len() >= self.batch_size {
+            self.ready.notify_one();
+        }
+    }
+
+    fn take_batch(&self) -> Vec<LogRecord> {
+        let mut records = self.lock();
+        let n = records.len().min(self.batch_size);
+        records.drain(..n).collect()
+    }
+
+    /// 发送失败的批次放回队首，超出容量时丢弃最旧的记录
+    fn requeue(&self, batch: Vec<LogRecord>) {
+        let mut records = self.lock();
+        for record in batch.into_iter().rev() {
+            records.push_front(record);
+        }
+        while records.len() > self.capacity {
+            records.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 转发日志的 tracing 层
+pub struct ForwardLayer {
+    min_level: Level,
+    buffer: Arc<ForwardBuffer>,
+}
+
+/// 按配置创建转发层；只能创建一次
+pub fn layer(cfg: &LogForwardConfig) -> Result<ForwardLayer> {
+    let min_level = parse_level(&cfg.min_level)?;
+    let buffer = Arc::new(ForwardBuffer {
+        records: Mutex::new(VecDeque::new()),
+        capacity: cfg.buffer_size.max(1),
+        batch_size: cfg.batch_size.max(1),
+        limiter: (cfg.rate_limit_per_sec > 0)
+            .then(|| Mutex::new(RateLimiter::new(cfg.rate_limit_per_sec))),
+        dropped: AtomicU64::new(0),
+        ready: Notify::new(),
+    });
+    BUFFER
+        .set(buffer.clone())
+        .map_err(|_| anyhow!("log forwarding already initialized"))?;
+    Ok(ForwardLayer { min_level, buffer })
+}
+
+pub fn parse_level(level: &str) -> Result<Level> {
+    Level::from_str(level).map_err(|_| anyhow!("invalid log_forward.min_level: {level}"))
+}
+
+/// 收集事件的 message 与其余字段，发送前脱敏
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: HashMap<String, String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let redactor = redact::global();
+        if field.name() == "message" {
+            self.message = redactor.redact(value).into_owned();
+        } else {
+            self.fields.insert(
+                field.name().to_string(),
+                redactor.field(field.name(), value).into_owned(),
+            );
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ForwardLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let meta = event.metadata();
+        // Level 越详细越大：TRACE > DEBUG > ... > ERROR
+        if *meta.level() > self.min_level || meta.target().starts_with(SELF_TARGET) {
+            return;
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        self.buffer.push(LogRecord {
+            ts: now_millis(),
+            level: meta.level().to_string(),
+            target: meta.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+/// 把缓冲的日志发往 master 的子系统；未启用转发时不做任何事
+#[derive(Default)]
+pub struct LogForwarder {
+    shutdown: Option<watch::Sender<bool>>,
+    task: Option<JoinHandle<()>>,
+}
+
+#[async_trait]
+impl Subsystem for LogForwarder {
+    fn name(&self) -> &'static str {
+        "log_forward"
+    }
+
+    async fn start(&mut self, ctx: &mut Context) -> Result<()> {
+        let Some(buffer) = BUFFER.get().cloned() else {
+            return Ok(());
+        };
+        let interval = Duration::from_secs(
+            crate::config::global()
+                .telemetry
+                .log_forward
+                .flush_interval_secs
+                .max(1),
+        );
+        let (tx, rx) = watch::channel(false);
+        self.task = Some(tokio::spawn(run(
+            buffer,
+            ctx.outbound.clone(),
+            interval,
+            rx,
+        )));
+        self.shutdown = Some(tx);
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send_replace(true);
+        }
+        if let Some(task) = self.task.take() {
+            task.await?;
+        }
+        Ok(())
+    }
+}
+
+async fn run(
+    buffer: Arc<ForwardBuffer>,
+    outbound: Outbound,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = buffer.ready.notified() => {}
+            _ = shutdown.changed() => break,
+        }
+        // 离线时保留在缓冲区，重新连接后发送
+        if state::current().is_connected() {
+            flush(&buffer, &outbound);
+        }
+    }
+    // 退出时剩余记录进入出站队列，未发出的部分随出站队列持久化
+    flush(&buffer, &outbound);
+}
+
+fn flush(buffer: &ForwardBuffer, outbound: &Outbound) {
+    loop {
+        let records = buffer.take_batch();
+        if records.is_empty() {
+            return;
+        }
+        let batch = LogBatch {
+            id: crate::agent::identity::agent_id(),
+            ts: now_millis(),
+            labels: crate::agent::labels::current_proto(),
+            records,
+            dropped: buffer.dropped.swap(0, Ordering::Relaxed),
+        };
+        // 出站队列满时不阻塞，留待下次发送
+        if let Err(e) = outbound.try_send(agent_message::Body::Logs(batch.clone())) {
+            buffer.dropped.fetch_add(batch.dropped, Ordering::Relaxed);
+            buffer.requeue(batch.records);
+            tracing::debug!(error = %format!("{e:#}"), "log batch not sent");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(capacity: usize, batch_size: usize, rate: u32) -> ForwardBuffer {
+        ForwardBuffer {
+            records: Mutex::new(VecDeque::new()),
+            capacity,
+            batch_size,
+            limiter: (rate > 0).then(|| Mutex::new(RateLimiter::new(rate))),
+            dropped: AtomicU64::new(0),
+            ready: Notify::new(),
+        }
+    }
+
+    fn record(message: &str) -> LogRecord {
+        LogRecord {
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn messages(records: &[LogRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.message.as_str()).collect()
+    }
+
+    #[test]
+    fn rate_limiter_allows_one_second_of_burst_then_refills() {
+        let mut limiter = RateLimiter::new(3);
+        assert_eq!((0..5).filter(|_| limiter.allow()).count(), 3);
+        // 经过 1/3 秒补充一个令牌
+        limiter.last -= Duration::from_millis(340);
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+        // 长时间空闲后最多积累一秒的配额
+        limiter.last -= Duration::from_secs(10);
+        assert_eq!((0..5).filter(|_| limiter.allow()).count(), 3);
+    }
+
+    #[test]
+    fn push_drops_the_oldest_record_when_full() {
+        let buffer = buffer(2, 10, 0);
+        for message in ["a", "b", "c"] {
+            buffer.push(record(message));
+        }
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(messages(&buffer.take_batch()), ["b", "c"]);
+    }
+
+    #[test]
+    fn push_counts_rate_limited_records_as_dropped() {
+        let buffer = buffer(10, 10, 2);
+        for message in ["a", "b", "c"] {
+            buffer.push(record(message));
+        }
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(messages(&buffer.take_batch()), ["a", "b"]);
+    }
+
+    #[test]
+    fn batches_are_taken_in_order_and_requeued_in_front() {
+        let buffer = buffer(4, 2, 0);
+        for message in ["a", "b", "c"] {
+            buffer.push(record(message));
+        }
+        let batch = buffer.take_batch();
+        assert_eq!(messages(&batch), ["a", "b"]);
+        buffer.push(record("d"));
+        buffer.push(record("e"));
+        // 放回后超出容量，丢弃最旧的记录
+        buffer.requeue(batch);
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(messages(&buffer.take_batch()), ["b", "c"]);
+        assert_eq!(messages(&buffer.take_batch()), ["d", "e"]);
+        assert!(buffer.take_batch().is_empty());
+    }
+
+    #[test]
+    fn dropped_count_moves_to_the_sent_batch_or_back() {
+        crate::config::init_for_tests();
+        let buffer = buffer(10, 2, 0);
+        buffer.dropped.store(5, Ordering::Relaxed);
+        for message in ["a", "b", "c"] {
+            buffer.push(record(message));
+        }
+        let (outbound, mut rx) = crate::grpc::client::outbound_channel();
+        flush(&buffer, &outbound);
+        let mut batches = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            match msg.body {
+                Some(agent_message::Body::Logs(batch)) => batches.push(batch),
+                other => panic!("unexpected message {other:?}"),
+            }
+        }
+        assert_eq!(batches.len(), 2);
+        assert_eq!((batches[0].dropped, batches[1].dropped), (5, 0));
+        assert_eq!(messages(&batches[1].records), ["c"]);
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 0);
+
+        // 发送失败时批次与丢弃计数都留给下一次
+        buffer.dropped.store(2, Ordering::Relaxed);
+        buffer.push(record("d"));
+        drop(rx);
+        flush(&buffer, &outbound);
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(messages(&buffer.take_batch()), ["d"]);
+    }
+
+    #[test]
+    fn forwarded_records_are_redacted() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        redact::init_for_tests();
+        let buffer = Arc::new(buffer(10, 10, 0));
+        let layer = ForwardLayer {
+            min_level: Level::WARN,
+            buffer: buffer.clone(),
+        };
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            // 本模块自身的日志不转发，换一个 target
+            tracing::warn!(
+                target: "agent",
+                password = "hunter2",
+                credentials = ?("admin", "s3cr3t"),
+                cmd_id = "c1",
+                "retrying with Authorization: Bearer eyJhbGciOi.x"
+            );
+            tracing::info!(target: "agent", password = "hunter2", "below min_level");
+        });
+        let batch = buffer.take_batch();
+        assert_eq!(batch.len(), 1);
+        let record = &batch[0];
+        assert_eq!(
+            record.message,
+            "retrying with Authorization: Bearer [REDACTED]"
+        );
+        assert_eq!(record.fields["password"], "[REDACTED]");
+        assert_eq!(record.fields["credentials"], "[REDACTED]");
+        assert_eq!(record.fields["cmd_id"], "c1");
+    }
+}
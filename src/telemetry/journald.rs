@@ -0,0 +1,144 @@
+//! journald output over the native journal protocol.
+//!
+//! Each record is one datagram to the journal socket
+//! (`/run/systemd/journal/socket`) made of `FIELD=value` lines; values with
+//! newlines use the binary form (`FIELD\n`, little-endian u64 length, value).
+//! Besides `MESSAGE`, `PRIORITY` and `SYSLOG_IDENTIFIER`, the record carries
+//! `TARGET`, `CODE_FILE`, `CODE_LINE` and every event field with its name
+//! upper-cased (`cmd_id` becomes `CMD_ID`). The socket is non-blocking: records
+//! are dropped rather than stalling the caller when the journal is unavailable
+//! or too slow, and records too large for a datagram are dropped. The journal
+//! socket only exists on unix; elsewhere the layer cannot be created.
+
+use anyhow::Result;
+use std::path::Path;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Layer;
+#[cfg(unix)]
+use {
+    crate::telemetry::redact,
+    crate::telemetry::syslog::severity,
+    anyhow::Context as _,
+    std::fmt::Debug,
+    std::os::unix::net::UnixDatagram,
+    std::path::PathBuf,
+    tracing::Event,
+    tracing::field::{Field, Visit},
+    tracing_subscriber::layer::Context,
+};
+
+/// 发往 journald 的 tracing 层
+#[cfg(unix)]
+pub struct JournaldLayer {
+    socket: UnixDatagram,
+    path: PathBuf,
+    identifier: String,
+}
+
+/// 非 unix 平台没有 journald，无法创建
+#[cfg(not(unix))]
+pub struct JournaldLayer;
+
+#[cfg(not(unix))]
+impl JournaldLayer {
+    pub fn new(_path: &Path, _identifier: &str) -> Result<Self> {
+        anyhow::bail!("journald is only supported on unix")
+    }
+}
+
+#[cfg(not(unix))]
+impl<S: Subscriber> Layer<S> for JournaldLayer {}
+
+#[cfg(unix)]
+impl JournaldLayer {
+    pub fn new(path: &Path, identifier: &str) -> Result<Self> {
+        let socket = UnixDatagram::unbound().context("failed to create journald socket")?;
+        socket
+            .set_nonblocking(true)
+            .context("failed to configure journald socket")?;
+        Ok(Self {
+            socket,
+            path: path.to_path_buf(),
+            identifier: identifier.to_string(),
+        })
+    }
+}
+
+/// journald 字段名只允许大写字母、数字和下划线，且不能以下划线或数字开头
+pub fn field_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' => c.to_ascii_uppercase(),
+            'A'..='Z' | '0'..='9' => c,
+            _ => '_',
+        })
+        .take(64)
+        .collect();
+    if !out.starts_with(|c: char| c.is_ascii_uppercase()) {
+        out.insert_str(0, "F_");
+        out.truncate(64);
+    }
+    out
+}
+
+/// 追加一个字段，含换行的值使用二进制格式
+#[cfg(unix)]
+fn put_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+#[cfg(unix)]
+struct FieldVisitor<'a> {
+    buf: &'a mut Vec<u8>,
+}
+
+#[cfg(unix)]
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let redactor = redact::global();
+        match field.name() {
+            "message" => put_field(self.buf, "MESSAGE", &redactor.redact(value)),
+            name => put_field(self.buf, &field_name(name), &redactor.field(name, value)),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+#[cfg(unix)]
+impl JournaldLayer {
+    fn encode(&self, event: &Event<'_>) -> Vec<u8> {
+        let meta = event.metadata();
+        let mut buf = Vec::with_capacity(256);
+        put_field(&mut buf, "PRIORITY", &severity(meta.level()).to_string());
+        put_field(&mut buf, "SYSLOG_IDENTIFIER", &self.identifier);
+        put_field(&mut buf, "TARGET", meta.target());
+        if let Some(file) = meta.file() {
+            put_field(&mut buf, "CODE_FILE", file);
+        }
+        if let Some(line) = meta.line() {
+            put_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+        event.record(&mut FieldVisitor { buf: &mut buf });
+        buf
+    }
+}
+
+#[cfg(unix)]
+impl<S: Subscriber> Layer<S> for JournaldLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // 不能通过 tracing 报告发送失败，否则会再次进入本层
+        let _ = self.socket.send_to(&self.encode(event), &self.path);
+    }
+}
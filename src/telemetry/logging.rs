@@ -1,70 +1,425 @@
 //! Logging module with async file rotation and multi-output support.
+//!
+//! The file writer rotates by size, by period (daily/hourly) or on whichever
+//! comes first. Size-only rotation keeps `X.1`..`X.N`; time-based policies
+//! name rotated files after the period they cover (`X.2026-10-18`,
+//! `X.2026-10-18T09`, with `.N` for extra size rotations in a period).
+//! Rotated files beyond `max_files` are removed unless they are younger than
+//! `max_age_days`, so an age-based retention is never cut short by the count;
+//! `max_files: 0` keeps any number of files and leaves cleanup to the age.
+//!
+//! Stdout, stderr and file output goes through sinks: the named `log_sinks`, or a
+//! single `default` sink built from `log_output`/`log_format`/`log_file`.
+//! Each sink has its own format, level and target filter (applied after the
+//! global filter), and each file sink its own queue, writer thread and rotation.
 
 use std::{
+    collections::VecDeque,
     fs::{self, File, OpenOptions},
     io::{self, Write},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, mpsc},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use once_cell::sync::OnceCell;
+use tracing::{Level, Metadata};
 use tracing_subscriber::{
     Layer, // For .boxed()
     Registry,
     filter::EnvFilter,
-    fmt::{self, format::FmtSpan},
+    fmt,
     layer::SubscriberExt,
     reload,
     util::SubscriberInitExt,
 };
 
-use crate::config::schema::TelemetryConfig;
+use crate::config::schema::{LogRotationConfig, LogSinkConfig, TelemetryConfig};
+use crate::telemetry::format::{Encoding, EventFormat, SpanFields, TimeZone};
+use crate::telemetry::forward;
+use crate::telemetry::journald::JournaldLayer;
+use crate::telemetry::redact::{self, Redactor};
+use crate::telemetry::syslog::{self, SyslogLayer};
 
 /// Global logger handle for background thread management
 static LOGGER_HANDLE: OnceCell<LoggerHandle> = OnceCell::new();
 
-// Macro for base fmt::layer construction (no format/writer)
-macro_rules! base_fmt_layer {
-    () => {
-        fmt::layer()
-            .with_ansi(false)
-            .with_file(true)
-            .with_line_number(true)
-            .with_target(true)
-            .with_level(true)
-            .with_thread_ids(false)
-            .with_thread_names(false)
-            .with_span_events(FmtSpan::NONE)
-    };
-}
-
 /// Async file write commands
 enum Cmd {
-    Write(Vec<u8>),
+    /// A formatted line; `error` marks ERROR records, which drop-oldest keeps
+    Write {
+        buf: Vec<u8>,
+        error: bool,
+    },
     Flush,
+    /// Flush and acknowledge, used for synchronous flushes
+    Sync(mpsc::Sender<()>),
     Shutdown,
 }
 
-/// Rotating file writer with size-based rotation and retention
+/// What happens to a new line when the file queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    Block,
+    DropNewest,
+    DropOldest,
+}
+
+impl Overflow {
+    fn from_config(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "block" => Ok(Overflow::Block),
+            "drop_newest" => Ok(Overflow::DropNewest),
+            "drop_oldest" => Ok(Overflow::DropOldest),
+            other => Err(anyhow!("invalid log queue overflow policy: {}", other)),
+        }
+    }
+}
+
+/// Counters of the file queue, reported as agent metrics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueStats {
+    pub queued: usize,       // Lines waiting to be written
+    pub queued_bytes: usize, // Total size of those lines
+    pub capacity: usize,     // Maximum number of queued lines
+    pub dropped: u64,        // Lines dropped since start
+    pub dropped_errors: u64, // ERROR lines among them
+}
+
+struct QueueState {
+    items: VecDeque<Cmd>,
+    lines: usize, // Write commands in `items`; control commands don't count
+    bytes: usize, // Total size of their buffers
+    closed: bool,
+}
+
+impl QueueState {
+    fn take_line(&mut self, index: usize) -> Option<Cmd> {
+        let cmd = self.items.remove(index)?;
+        if let Cmd::Write { buf, .. } = &cmd {
+            self.lines -= 1;
+            self.bytes -= buf.len();
+        }
+        Some(cmd)
+    }
+}
+
+/// Bounded queue between log producers and the file writer thread. It is full
+/// at `capacity` lines or `max_bytes` bytes, whichever comes first; a single
+/// line larger than `max_bytes` is still accepted into an empty queue.
+struct LogQueue {
+    state: Mutex<QueueState>,
+    readable: Condvar,
+    writable: Condvar,
+    capacity: usize,
+    max_bytes: Option<usize>,
+    overflow: Overflow,
+    dropped: AtomicU64,
+    dropped_errors: AtomicU64,
+}
+
+impl LogQueue {
+    fn new(capacity: usize, max_bytes: Option<usize>, overflow: Overflow) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                lines: 0,
+                bytes: 0,
+                closed: false,
+            }),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+            capacity,
+            max_bytes,
+            overflow,
+            dropped: AtomicU64::new(0),
+            dropped_errors: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn count_drop(&self, error: bool) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        if error {
+            self.dropped_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn is_full(&self, state: &QueueState, len: usize) -> bool {
+        state.lines >= self.capacity
+            || (state.lines > 0 && self.max_bytes.is_some_and(|max| state.bytes + len > max))
+    }
+
+    fn push_line(&self, buf: Vec<u8>, error: bool) {
+        let mut state = self.lock();
+        while !state.closed && self.is_full(&state, buf.len()) {
+            match self.overflow {
+                Overflow::Block => {
+                    state = self.writable.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                Overflow::DropNewest => {
+                    self.count_drop(error);
+                    return;
+                }
+                Overflow::DropOldest => {
+                    let oldest = state
+                        .items
+                        .iter()
+                        .position(|c| matches!(c, Cmd::Write { error: false, .. }));
+                    match oldest {
+                        Some(i) => {
+                            state.take_line(i);
+                            self.count_drop(false);
+                        }
+                        // Only errors queued: keep them and drop the new line
+                        None => {
+                            self.count_drop(error);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+        if state.closed {
+            return;
+        }
+        state.lines += 1;
+        state.bytes += buf.len();
+        state.items.push_back(Cmd::Write { buf, error });
+        self.readable.notify_one();
+    }
+
+    /// Queue a control command; never dropped, returns false once closed
+    fn push(&self, cmd: Cmd) -> bool {
+        let mut state = self.lock();
+        if state.closed {
+            return false;
+        }
+        state.items.push_back(cmd);
+        self.readable.notify_one();
+        true
+    }
+
+    fn pop(&self) -> Cmd {
+        let mut state = self.lock();
+        loop {
+            if let Some(cmd) = state.take_line(0) {
+                if matches!(cmd, Cmd::Write { .. }) {
+                    // A freed byte budget may admit more than one waiting line
+                    self.writable.notify_all();
+                }
+                return cmd;
+            }
+            state = self.readable.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Stop accepting lines and release producers blocked on a full queue
+    fn close(&self) {
+        self.lock().closed = true;
+        self.writable.notify_all();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    fn stats(&self) -> QueueStats {
+        let state = self.lock();
+        QueueStats {
+            queued: state.lines,
+            queued_bytes: state.bytes,
+            capacity: self.capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            dropped_errors: self.dropped_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Periodically log how many lines were dropped since the last report
+fn spawn_drop_reporter(sink: String, queue: Arc<LogQueue>, interval: Duration) {
+    let spawned = thread::Builder::new()
+        .name("log-drop-report".into())
+        .spawn(move || {
+            let mut reported = QueueStats::default();
+            loop {
+                thread::sleep(interval);
+                if queue.is_closed() {
+                    return;
+                }
+                let stats = queue.stats();
+                let dropped = stats.dropped - reported.dropped;
+                if dropped > 0 {
+                    tracing::warn!(
+                        sink = %sink,
+                        dropped,
+                        dropped_errors = stats.dropped_errors - reported.dropped_errors,
+                        dropped_total = stats.dropped,
+                        policy = ?queue.overflow,
+                        "log lines dropped, file queue full"
+                    );
+                }
+                reported = stats;
+            }
+        });
+    if let Err(e) = spawned {
+        eprintln!("[logging] failed to spawn drop reporter: {e}");
+    }
+}
+
+/// Compression applied to rotated files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_config(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            other => Err(anyhow!("invalid log compression: {}", other)),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// Extensions of compressed rotated files
+const COMPRESSED_EXTENSIONS: [&str; 2] = [".gz", ".zst"];
+
+/// Name of the file writer threads; the panic hook must not wait on them
+pub(crate) const WRITER_THREAD: &str = "log-rotate-writer";
+
+/// Period of time-based rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    Daily,
+    Hourly,
+}
+
+impl Interval {
+    fn from_config(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "daily" => Ok(Interval::Daily),
+            "hourly" => Ok(Interval::Hourly),
+            other => Err(anyhow!("invalid log rotation interval: {}", other)),
+        }
+    }
+
+    /// Stamp of the period containing `t`, used in rotated file names
+    fn stamp(self, t: DateTime<Local>) -> String {
+        match self {
+            Interval::Daily => t.format("%Y-%m-%d").to_string(),
+            Interval::Hourly => t.format("%Y-%m-%dT%H").to_string(),
+        }
+    }
+}
+
+/// Rotation and retention settings of the file writer
+#[derive(Debug, Clone)]
+struct Rotation {
+    max_size: Option<u64>,         // Rotate when the file would exceed this size
+    interval: Option<Interval>,    // Rotate when the period changes; names rotated files by date
+    keep: Option<usize>,           // Number of rotated files to keep, None for no limit
+    max_age: Option<Duration>,     // Remove rotated files older than this, keep younger ones
+    compress: Option<Compression>, // Compress rotated files
+    on_startup: bool,              // Rotate a non-empty file when the writer starts
+}
+
+impl Rotation {
+    fn from_config(cfg: &LogRotationConfig) -> Result<Self> {
+        let (by_size, by_time) = match cfg.policy.to_ascii_lowercase().as_str() {
+            "size" => (true, false),
+            "time" => (false, true),
+            "size_or_time" => (true, true),
+            other => return Err(anyhow!("invalid log rotation policy: {}", other)),
+        };
+        let interval = Interval::from_config(&cfg.interval)?;
+        let compression = Compression::from_config(&cfg.compression)?;
+        Ok(Self {
+            max_size: by_size.then(|| cfg.max_size_mb as u64 * 1024 * 1024),
+            interval: by_time.then_some(interval),
+            keep: (cfg.max_files > 0).then(|| cfg.max_files as usize - 1),
+            max_age: (cfg.max_age_days > 0)
+                .then(|| Duration::from_secs(cfg.max_age_days as u64 * 24 * 3600)),
+            compress: cfg.compress.then_some(compression),
+            on_startup: cfg.rotate_on_startup,
+        })
+    }
+}
+
+/// Position of a rotated file: `X.N` or `X.<stamp>[.N]`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum RotatedKey {
+    Index(usize),
+    Stamp(String, usize),
+}
+
+/// A rotated file found next to the active file
+struct RotatedFile {
+    path: PathBuf,
+    key: RotatedKey,
+    compressed: bool,
+}
+
+/// Parse the part of a rotated file name after `X.`
+fn parse_rotated(rest: &str) -> Option<(RotatedKey, bool)> {
+    let (rest, compressed) = match COMPRESSED_EXTENSIONS
+        .iter()
+        .find_map(|ext| rest.strip_suffix(ext))
+    {
+        Some(rest) => (rest, true),
+        None => (rest, false),
+    };
+    if let Ok(index) = rest.parse::<usize>() {
+        return Some((RotatedKey::Index(index), compressed));
+    }
+    let (stamp, seq) = match rest.rsplit_once('.') {
+        Some((stamp, seq)) => (stamp, seq.parse::<usize>().ok()?),
+        None => (rest, 0),
+    };
+    let date_ok = stamp
+        .get(..10)
+        .is_some_and(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").is_ok());
+    let hour_ok = match stamp.get(10..) {
+        Some("") => true,
+        Some(hour) => hour
+            .strip_prefix('T')
+            .is_some_and(|h| h.len() == 2 && h.parse::<u8>().is_ok_and(|h| h < 24)),
+        None => false,
+    };
+    (date_ok && hour_ok).then(|| (RotatedKey::Stamp(stamp.to_string(), seq), compressed))
+}
+
+/// Rotating file writer with size/time-based rotation and retention
 struct RotatingFileWorker {
     base_path: PathBuf,
     file: Option<File>,
     current_size: u64,
-    max_size: u64,
-    keep: usize,    // Number of rotated files to keep
-    compress: bool, // Compress rotated files (gzip)
+    rotation: Rotation,
+    period: Option<String>, // Stamp of the period the active file belongs to
+    compressing: Option<thread::JoinHandle<()>>, // Background compression of rotated files
 }
 
 impl RotatingFileWorker {
-    fn new<P: AsRef<Path>>(
-        base: P,
-        max_size: u64,
-        keep: usize,
-        compress: bool,
-    ) -> io::Result<Self> {
+    fn new<P: AsRef<Path>>(base: P, rotation: Rotation) -> io::Result<Self> {
         let base_path = base.as_ref().to_path_buf();
         if let Some(dir) = base_path.parent() {
             fs::create_dir_all(dir)?;
@@ -73,11 +428,30 @@ impl RotatingFileWorker {
             base_path,
             file: None,
             current_size: 0,
-            max_size,
-            keep,
-            compress,
+            rotation,
+            period: None,
+            compressing: None,
         };
         worker.open_new_file()?;
+        // Contents of an existing file belong to the period it was last written in
+        if let Some(interval) = worker.rotation.interval {
+            let modified = worker
+                .file
+                .as_ref()
+                .and_then(|f| f.metadata().ok())
+                .filter(|m| m.len() > 0)
+                .and_then(|m| m.modified().ok())
+                .map(DateTime::<Local>::from)
+                .unwrap_or_else(Local::now);
+            worker.period = Some(interval.stamp(modified));
+        }
+        if worker.current_size > 0 && (worker.rotation.on_startup || worker.period_changed()) {
+            worker.rotate()?;
+        } else {
+            // Clean up after a previous run that exited before retention or compression finished
+            worker.apply_retention();
+            worker.compress_rotated();
+        }
         Ok(worker)
     }
 
@@ -85,7 +459,6 @@ impl RotatingFileWorker {
         let f = OpenOptions::new()
             .create(true)
             .append(true)
-            .write(true)
             .open(&self.base_path)?;
         let size = f.metadata().map(|m| m.len()).unwrap_or(0);
         self.file = Some(f);
@@ -93,51 +466,240 @@ impl RotatingFileWorker {
         Ok(())
     }
 
+    fn period_changed(&self) -> bool {
+        match (self.rotation.interval, &self.period) {
+            (Some(interval), Some(period)) => interval.stamp(Local::now()) != *period,
+            _ => false,
+        }
+    }
+
     fn rotate(&mut self) -> io::Result<()> {
         self.file.take();
-        if self.keep > 0 {
-            for i in (1..=self.keep).rev() {
-                let src = self.suffixed(i);
-                let dst = self.suffixed(i + 1);
-                if src.exists() {
-                    if i == self.keep && dst.exists() {
-                        let _ = fs::remove_file(&dst);
+        // Renaming files while they are being compressed would lose them
+        self.wait_compression();
+        if self.rotation.keep != Some(0) || self.rotation.max_age.is_some() {
+            if self.base_path.exists() {
+                let target = match &self.period {
+                    Some(period) => self.stamped_target(period),
+                    None => {
+                        self.shift_numbered();
+                        self.suffixed(1, "")
                     }
-                    let _ = fs::rename(&src, &dst);
-                }
+                };
+                let _ = fs::rename(&self.base_path, target);
             }
-            if self.base_path.exists() {
-                let dst = self.suffixed(1);
-                if dst.exists() {
-                    let _ = fs::remove_file(&dst);
-                }
-                let _ = fs::rename(&self.base_path, &dst);
-                if self.compress {
-                    let _ = Self::compress_file(&dst);
+            self.apply_retention();
+            self.compress_rotated();
+        } else if self.base_path.exists() {
+            let _ = fs::remove_file(&self.base_path);
+        }
+        if let Some(interval) = self.rotation.interval {
+            self.period = Some(interval.stamp(Local::now()));
+        }
+        self.open_new_file()
+    }
+
+    /// Shift `X.i[.gz|.zst]` to `X.i+1`; retention then drops the ones beyond `keep`
+    fn shift_numbered(&self) {
+        let last = self
+            .rotated_files()
+            .into_iter()
+            .filter_map(|f| match f.key {
+                RotatedKey::Index(n) => Some(n),
+                RotatedKey::Stamp(..) => None,
+            })
+            .max()
+            .unwrap_or(0);
+        for i in (1..=last).rev() {
+            for ext in std::iter::once("").chain(COMPRESSED_EXTENSIONS) {
+                let src = self.suffixed(i, ext);
+                if src.exists() {
+                    let _ = fs::rename(&src, self.suffixed(i + 1, ext));
                 }
             }
-        } else {
-            if self.base_path.exists() {
-                let _ = fs::remove_file(&self.base_path);
-            }
         }
-        self.open_new_file()
+    }
+
+    /// `X.<stamp>`, or `X.<stamp>.N` after the newest file of the period for extra size rotations
+    fn stamped_target(&self, period: &str) -> PathBuf {
+        let last = self
+            .rotated_files()
+            .into_iter()
+            .filter_map(|f| match f.key {
+                RotatedKey::Stamp(stamp, seq) if stamp == period => Some(seq),
+                _ => None,
+            })
+            .max();
+        match last {
+            Some(seq) => self.with_suffix(&format!("{period}.{}", seq + 1)),
+            None => self.with_suffix(period),
+        }
     }
 
     #[inline]
-    fn suffixed(&self, n: usize) -> PathBuf {
+    fn suffixed(&self, n: usize, ext: &str) -> PathBuf {
+        self.with_suffix(&format!("{n}{ext}"))
+    }
+
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
         let mut p = self.base_path.clone();
         let name = p
             .file_name()
             .and_then(|s| s.to_str())
-            .map(|s| format!("{}.{}", s, n))
-            .unwrap_or_else(|| format!(".{}", n));
+            .map(|s| format!("{s}.{suffix}"))
+            .unwrap_or_else(|| format!(".{suffix}"));
         p.set_file_name(name);
         p
     }
 
+    /// Rotated files of either naming scheme next to the active file
+    fn rotated_files(&self) -> Vec<RotatedFile> {
+        let (Some(dir), Some(base)) = (
+            self.base_path.parent(),
+            self.base_path.file_name().and_then(|s| s.to_str()),
+        ) else {
+            return Vec::new();
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let rest = name.to_str()?.strip_prefix(base)?.strip_prefix('.')?;
+                let (key, compressed) = parse_rotated(rest)?;
+                Some(RotatedFile {
+                    path: entry.path(),
+                    key,
+                    compressed,
+                })
+            })
+            .collect()
+    }
+
+    /// Remove rotated files older than `max_age`, and those beyond `keep`
+    /// unless they are younger than `max_age`
+    fn apply_retention(&self) {
+        let keep = self.rotation.keep.unwrap_or(usize::MAX);
+        let mut stamped = Vec::new();
+        for file in self.rotated_files() {
+            let age = fs::metadata(&file.path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.elapsed().ok());
+            let (expired, young) = match (self.rotation.max_age, age) {
+                (Some(max_age), Some(age)) => (age > max_age, age <= max_age),
+                _ => (false, false),
+            };
+            match file.key {
+                _ if expired => {
+                    let _ = fs::remove_file(&file.path);
+                }
+                RotatedKey::Index(n) if n > keep && !young => {
+                    let _ = fs::remove_file(&file.path);
+                }
+                RotatedKey::Index(_) => {}
+                RotatedKey::Stamp(..) => stamped.push((file, young)),
+            }
+        }
+        // Newest first; a compressed and an uncompressed copy of the same file count once
+        stamped.sort_by(|(a, _), (b, _)| b.key.cmp(&a.key).then(b.compressed.cmp(&a.compressed)));
+        stamped.dedup_by(|(a, _), (b, _)| {
+            let duplicate = a.key == b.key;
+            if duplicate {
+                let _ = fs::remove_file(&a.path);
+            }
+            duplicate
+        });
+        for (file, young) in stamped.into_iter().skip(keep) {
+            if !young {
+                let _ = fs::remove_file(&file.path);
+            }
+        }
+    }
+
+    /// Compress uncompressed rotated files on a separate thread so writes are not blocked
+    fn compress_rotated(&mut self) {
+        let Some(compression) = self.rotation.compress else {
+            return;
+        };
+        let pending: Vec<(PathBuf, PathBuf)> = self
+            .rotated_files()
+            .into_iter()
+            .filter(|f| !f.compressed)
+            .map(|f| {
+                let mut dst = f.path.clone().into_os_string();
+                dst.push(format!(".{}", compression.extension()));
+                (f.path, PathBuf::from(dst))
+            })
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+        let spawned = thread::Builder::new()
+            .name("log-compress".into())
+            .spawn(move || {
+                for (src, dst) in pending {
+                    if let Err(e) = Self::compress_file(&src, &dst, compression) {
+                        eprintln!("[logging] failed to compress {}: {e}", src.display());
+                    }
+                }
+            });
+        match spawned {
+            Ok(handle) => self.compressing = Some(handle),
+            Err(e) => eprintln!("[logging] failed to spawn compression thread: {e}"),
+        }
+    }
+
+    fn wait_compression(&mut self) {
+        if let Some(handle) = self.compressing.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Compress `src` into `dst` via a temporary file, then remove `src`
+    fn compress_file(src: &Path, dst: &Path, compression: Compression) -> io::Result<()> {
+        let mut tmp = dst.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut input = File::open(src)?;
+        let output = File::create(&tmp)?;
+        let result = match compression {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                io::copy(&mut input, &mut encoder)
+                    .and_then(|_| encoder.finish())
+                    .and_then(|f| f.sync_all())
+            }
+            Compression::Zstd => {
+                zstd::stream::write::Encoder::new(output, 0).and_then(|mut encoder| {
+                    io::copy(&mut input, &mut encoder)
+                        .and_then(|_| encoder.finish())
+                        .and_then(|f| f.sync_all())
+                })
+            }
+        };
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        fs::rename(&tmp, dst)?;
+        fs::remove_file(src)
+    }
+
     fn write(&mut self, buf: &[u8]) -> io::Result<()> {
-        if self.current_size + (buf.len() as u64) > self.max_size {
+        let over_size = self
+            .rotation
+            .max_size
+            .is_some_and(|max| self.current_size > 0 && self.current_size + buf.len() as u64 > max);
+        if over_size || self.period_changed() {
             self.rotate()?;
         }
         if let Some(f) = self.file.as_mut() {
@@ -153,24 +715,21 @@ impl RotatingFileWorker {
         }
         Ok(())
     }
-
-    /// Placeholder for gzip compression (can be enhanced with flate2)
-    #[allow(unused)]
-    fn compress_file(_path: &Path) -> io::Result<()> {
-        Ok(())
-    }
 }
 
-/// MultiWriter: unified stdout/file/both output
+/// MultiWriter: unified stdout/stderr/file output
 #[derive(Clone)]
 struct MultiWriter {
     to_stdout: bool,
-    file_tx: Option<mpsc::Sender<Cmd>>,
+    to_stderr: bool,
+    queue: Option<Arc<LogQueue>>,
 }
 
 struct MultiWriterHandle {
     to_stdout: bool,
-    file_tx: Option<mpsc::Sender<Cmd>>,
+    to_stderr: bool,
+    queue: Option<Arc<LogQueue>>,
+    error: bool,
 }
 
 impl Write for MultiWriterHandle {
@@ -178,8 +737,11 @@ impl Write for MultiWriterHandle {
         if self.to_stdout {
             let _ = std::io::stdout().write_all(buf);
         }
-        if let Some(tx) = &self.file_tx {
-            let _ = tx.send(Cmd::Write(buf.to_vec()));
+        if self.to_stderr {
+            let _ = std::io::stderr().write_all(buf);
+        }
+        if let Some(queue) = &self.queue {
+            queue.push_line(buf.to_vec(), self.error);
         }
         Ok(buf.len())
     }
@@ -187,8 +749,8 @@ impl Write for MultiWriterHandle {
         if self.to_stdout {
             let _ = std::io::stdout().flush();
         }
-        if let Some(tx) = &self.file_tx {
-            let _ = tx.send(Cmd::Flush);
+        if let Some(queue) = &self.queue {
+            queue.push(Cmd::Flush);
         }
         Ok(())
     }
@@ -199,142 +761,587 @@ impl<'a> fmt::MakeWriter<'a> for MultiWriter {
     fn make_writer(&'a self) -> Self::Writer {
         MultiWriterHandle {
             to_stdout: self.to_stdout,
-            file_tx: self.file_tx.clone(),
+            to_stderr: self.to_stderr,
+            queue: self.queue.clone(),
+            error: false,
         }
     }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        MultiWriterHandle {
+            error: *meta.level() == Level::ERROR,
+            ..self.make_writer()
+        }
+    }
+}
+
+/// Log filter currently applied
+#[derive(Debug, Clone)]
+pub struct FilterState {
+    pub directives: String,                // EnvFilter directives in effect
+    pub default: String,                   // Directives from config, restored on reset
+    pub expires_at: Option<DateTime<Utc>>, // When a temporary filter reverts to the default
+    deadline: Option<Instant>,             // Monotonic deadline watched by the TTL timer
+}
+
+/// Reload handle of the filter layer and the state it was given
+struct FilterControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    state: Mutex<FilterState>,
+    changed: Condvar,       // Wakes the TTL timer when the filter changes
+    timer: std::sync::Once, // The TTL timer thread is started on first use
+}
+
+impl FilterControl {
+    fn new(handle: reload::Handle<EnvFilter, Registry>, default: String) -> Self {
+        Self {
+            handle,
+            state: Mutex::new(FilterState {
+                directives: default.clone(),
+                default,
+                expires_at: None,
+                deadline: None,
+            }),
+            changed: Condvar::new(),
+            timer: std::sync::Once::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FilterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn apply(self: &Arc<Self>, directives: &str, ttl: Option<Duration>) -> Result<FilterState> {
+        let mut state = self.lock();
+        self.reload(&mut state, directives, ttl)?;
+        if ttl.is_some() {
+            self.timer.call_once(|| {
+                let control = Arc::clone(self);
+                let spawned = thread::Builder::new()
+                    .name("log-filter-ttl".into())
+                    .spawn(move || control.run_timer());
+                if let Err(e) = spawned {
+                    eprintln!("[logging] failed to spawn log filter timer: {e}");
+                }
+            });
+        }
+        self.changed.notify_all();
+        Ok(state.clone())
+    }
+
+    /// Reload the filter layer; the caller holds the state lock so a concurrent
+    /// change and the TTL timer cannot interleave
+    fn reload(
+        &self,
+        state: &mut FilterState,
+        directives: &str,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| anyhow!("invalid log filter {directives:?}: {e}"))?;
+        self.handle
+            .reload(filter)
+            .map_err(|e| anyhow!("failed to reload log filter: {e}"))?;
+        state.directives = directives.to_string();
+        state.deadline = ttl.map(|ttl| Instant::now() + ttl);
+        state.expires_at = ttl.and_then(|ttl| {
+            chrono::Duration::from_std(ttl)
+                .ok()
+                .map(|ttl| Utc::now() + ttl)
+        });
+        Ok(())
+    }
+
+    /// Single timer for all temporary filters: revert to the default once the
+    /// current deadline passes, re-checking whenever the filter changes
+    fn run_timer(&self) {
+        let mut state = self.lock();
+        loop {
+            let Some(deadline) = state.deadline else {
+                state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue;
+            };
+            let now = Instant::now();
+            if now < deadline {
+                state = self
+                    .changed
+                    .wait_timeout(state, deadline - now)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+                continue;
+            }
+            let default = state.default.clone();
+            match self.reload(&mut state, &default, None) {
+                Ok(()) => tracing::info!(filter = %default, "temporary log filter expired"),
+                Err(e) => {
+                    eprintln!("[logging] failed to revert log filter: {e:#}");
+                    state.deadline = None;
+                }
+            }
+        }
+    }
+}
+
+/// A sink's log file, written by a background thread through a bounded queue
+struct FileSink {
+    name: String,
+    queue: Arc<LogQueue>,
+    writer: Mutex<Option<thread::JoinHandle<()>>>,
 }
 
 /// Logger handle for graceful shutdown and dynamic level
 pub struct LoggerHandle {
-    _bg: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
-    tx: mpsc::Sender<Cmd>,
+    files: Vec<FileSink>,
+    filter: Arc<FilterControl>,
+}
+
+impl LoggerHandle {
+    /// Replace the log filter with EnvFilter directives (e.g. `info,warden::grpc=trace`);
+    /// with a TTL the config default is restored once it elapses
+    pub fn set_filter(&self, directives: &str, ttl: Option<Duration>) -> Result<FilterState> {
+        self.filter.apply(directives, ttl)
+    }
+
+    /// Restore the log filter from config
+    pub fn reset_filter(&self) -> Result<FilterState> {
+        let default = self.filter().default;
+        self.filter.apply(&default, None)
+    }
+
+    pub fn filter(&self) -> FilterState {
+        self.filter.lock().clone()
+    }
+
+    /// Flush stdout and wait (bounded) until queued file writes reach disk
+    pub fn flush(&self, timeout: Duration) {
+        let _ = std::io::stdout().flush();
+        let (ack_tx, ack_rx) = mpsc::channel();
+        let pending = self
+            .files
+            .iter()
+            .filter(|f| f.queue.push(Cmd::Sync(ack_tx.clone())))
+            .count();
+        let deadline = std::time::Instant::now() + timeout;
+        for _ in 0..pending {
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if ack_rx.recv_timeout(left).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// File queue counters summed over all sinks; None when no sink writes a file
+    pub fn queue_stats(&self) -> Option<QueueStats> {
+        if self.files.is_empty() {
+            return None;
+        }
+        Some(self.files.iter().fold(QueueStats::default(), |sum, f| {
+            let stats = f.queue.stats();
+            QueueStats {
+                queued: sum.queued + stats.queued,
+                queued_bytes: sum.queued_bytes + stats.queued_bytes,
+                capacity: sum.capacity + stats.capacity,
+                dropped: sum.dropped + stats.dropped,
+                dropped_errors: sum.dropped_errors + stats.dropped_errors,
+            }
+        }))
+    }
+
+    /// File queue counters per sink name
+    pub fn sink_queue_stats(&self) -> Vec<(String, QueueStats)> {
+        self.files
+            .iter()
+            .map(|f| (f.name.clone(), f.queue.stats()))
+            .collect()
+    }
 }
 
 impl Drop for LoggerHandle {
     fn drop(&mut self) {
-        let _ = self.tx.send(Cmd::Shutdown);
-        if let Ok(mut g) = self._bg.lock() {
-            if let Some(h) = g.take() {
+        for file in &self.files {
+            file.queue.push(Cmd::Shutdown);
+        }
+        for file in &self.files {
+            if let Ok(mut g) = file.writer.lock()
+                && let Some(h) = g.take()
+            {
                 let _ = h.join();
             }
         }
     }
 }
 
+/// Outputs selected by `log_output`, a comma-separated list such as
+/// `file,journald`; `both` stands for `stdout,file`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outputs {
+    pub stdout: bool,
+    pub stderr: bool,
+    pub file: bool,
+    pub syslog: bool,
+    pub journald: bool,
+}
+
+impl Outputs {
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut outputs = Outputs::default();
+        for name in value.split(',').map(|n| n.trim().to_ascii_lowercase()) {
+            match name.as_str() {
+                "stdout" => outputs.stdout = true,
+                "stderr" => outputs.stderr = true,
+                "file" => outputs.file = true,
+                "both" => {
+                    outputs.stdout = true;
+                    outputs.file = true;
+                }
+                "syslog" => outputs.syslog = true,
+                "journald" if cfg!(unix) => outputs.journald = true,
+                "journald" => return Err(anyhow!("log_output journald is only supported on unix")),
+                other => return Err(anyhow!("invalid log_output: {}", other)),
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+/// Check a rotation config, including values only used by some policies
+fn validate_rotation(cfg: &LogRotationConfig) -> Result<()> {
+    let rotation = Rotation::from_config(cfg)?;
+    if rotation.max_size.is_some() && cfg.max_size_mb == 0 {
+        return Err(anyhow!("max_size_mb must be > 0"));
+    }
+    // Without either limit rotated files would pile up forever
+    if cfg.max_files == 0 && cfg.max_age_days == 0 {
+        return Err(anyhow!("max_files must be > 0 unless max_age_days is set"));
+    }
+    Ok(())
+}
+
+/// Validate named sinks: unique names and files, stdout/stderr/file outputs only
+pub fn validate_sinks(sinks: &[LogSinkConfig]) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    let mut files = std::collections::HashSet::new();
+    for sink in sinks {
+        let name = sink.name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("log sink name must not be empty"));
+        }
+        if !names.insert(name) {
+            return Err(anyhow!("duplicate log sink name: {name}"));
+        }
+        let outputs = Outputs::parse(&sink.output).map_err(|e| anyhow!("log sink {name}: {e}"))?;
+        if outputs.syslog || outputs.journald {
+            return Err(anyhow!(
+                "log sink {name}: output must be stdout, stderr and/or file, syslog and journald are set by log_output"
+            ));
+        }
+        Encoding::parse(&sink.format).map_err(|e| anyhow!("log sink {name}: {e}"))?;
+        SinkFilter::from_config(sink).map_err(|e| anyhow!("log sink {name}: {e}"))?;
+        if outputs.file {
+            if sink.file.trim().is_empty() {
+                return Err(anyhow!(
+                    "log sink {name}: file required when output includes file"
+                ));
+            }
+            // Two writers rotating the same file would corrupt it
+            if !files.insert(sink.file.trim()) {
+                return Err(anyhow!(
+                    "log sink {name}: file {} is used by another sink",
+                    sink.file
+                ));
+            }
+            validate_rotation(&sink.rotation).map_err(|e| anyhow!("log sink {name}: {e}"))?;
+        }
+    }
+    Ok(())
+}
+
 /// Validate TelemetryConfig logging fields
 fn validate_config(cfg: &TelemetryConfig) -> Result<()> {
     match cfg.log_level.to_ascii_lowercase().as_str() {
         "error" | "warn" | "info" | "debug" | "trace" => {}
         other => return Err(anyhow!("invalid log_level: {}", other)),
     }
-    match cfg.log_format.to_ascii_lowercase().as_str() {
-        "json" | "plain" => {}
-        other => return Err(anyhow!("invalid log_format: {}", other)),
+    Encoding::parse(&cfg.log_format)?;
+    TimeZone::parse(&cfg.log_encoding.timezone)?;
+    let outputs = Outputs::parse(&cfg.log_output)?;
+    if outputs.file && cfg.log_sinks.is_empty() && cfg.log_file.trim().is_empty() {
+        return Err(anyhow!("log_file required when output includes file"));
     }
-    match cfg.log_output.to_ascii_lowercase().as_str() {
-        "stdout" | "file" | "both" => {}
-        other => return Err(anyhow!("invalid log_output: {}", other)),
+    if outputs.syslog {
+        syslog::Address::parse(&cfg.log_syslog.address)?;
+        syslog::parse_facility(&cfg.log_syslog.facility)?;
+        syslog::parse_sd_id(&cfg.log_syslog.sd_id)?;
     }
-    if (cfg.log_output == "file" || cfg.log_output == "both") && cfg.log_file.trim().is_empty() {
-        return Err(anyhow!("log_file required when output=file/both"));
+    Overflow::from_config(&cfg.log_queue.overflow)?;
+    if cfg.log_queue.capacity == 0 {
+        return Err(anyhow!("log_queue.capacity must be > 0"));
     }
-    if cfg.log_rotation.max_size_mb == 0 {
-        return Err(anyhow!("max_size_mb must be > 0"));
+    Redactor::from_config(&cfg.log_redaction)?;
+    validate_rotation(&cfg.log_rotation)?;
+    validate_sinks(&cfg.log_sinks)?;
+    Ok(())
+}
+
+/// Sinks to build: `log_sinks`, or one sink named `default` made of the
+/// stdout/stderr/file part of `log_output` with `log_format`, `log_file` and `log_rotation`
+fn sink_configs(cfg: &TelemetryConfig) -> Result<Vec<LogSinkConfig>> {
+    if !cfg.log_sinks.is_empty() {
+        return Ok(cfg.log_sinks.clone());
     }
-    if cfg.log_rotation.max_files == 0 {
-        return Err(anyhow!("max_files must be > 0"));
+    let outputs = Outputs::parse(&cfg.log_output)?;
+    let output: Vec<&str> = [
+        (outputs.stdout, "stdout"),
+        (outputs.stderr, "stderr"),
+        (outputs.file, "file"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect();
+    if output.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(vec![LogSinkConfig {
+        name: "default".to_string(),
+        output: output.join(","),
+        format: cfg.log_format.clone(),
+        file: cfg.log_file.clone(),
+        rotation: cfg.log_rotation.clone(),
+        ..LogSinkConfig::default()
+    }])
+}
+
+/// Start the background writer of a sink's log file
+fn spawn_file_writer(
+    base: PathBuf,
+    rotation: Rotation,
+    rx: Arc<LogQueue>,
+) -> Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(WRITER_THREAD.into())
+        .spawn(move || {
+            let mut worker = match RotatingFileWorker::new(&base, rotation) {
+                Ok(w) => w,
+                Err(e) => {
+                    eprintln!(
+                        "[logging] failed to init file writer for {}: {e}",
+                        base.display()
+                    );
+                    // Nothing will drain the queue, don't let producers block on it
+                    rx.close();
+                    return;
+                }
+            };
+            loop {
+                match rx.pop() {
+                    Cmd::Write { buf, .. } => {
+                        if let Err(e) = worker.write(&buf) {
+                            eprintln!("[logging] write error: {e}");
+                            thread::sleep(Duration::from_millis(5));
+                        }
+                    }
+                    Cmd::Flush => {
+                        let _ = worker.flush();
+                    }
+                    Cmd::Sync(ack) => {
+                        let _ = worker.flush();
+                        let _ = ack.send(());
+                    }
+                    Cmd::Shutdown => {
+                        rx.close();
+                        let _ = worker.flush();
+                        worker.wait_compression();
+                        break;
+                    }
+                }
+            }
+        })
+        .map_err(|e| anyhow!("failed to spawn log writer thread: {e}"))
+}
+
+/// Level and target filter of one sink, applied after the global filter
+struct SinkFilter {
+    level: Level,
+    targets: Vec<String>,
+    exclude_targets: Vec<String>,
+}
+
+impl SinkFilter {
+    fn from_config(cfg: &LogSinkConfig) -> Result<Self> {
+        let level = cfg
+            .level
+            .parse::<Level>()
+            .map_err(|_| anyhow!("invalid level: {}", cfg.level))?;
+        Ok(Self {
+            level,
+            targets: cfg.targets.clone(),
+            exclude_targets: cfg.exclude_targets.clone(),
+        })
+    }
+
+    /// `prefix` matches the target itself and its submodules
+    fn target_matches(target: &str, prefix: &str) -> bool {
+        target
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+
+    fn accepts(&self, meta: &Metadata<'_>) -> bool {
+        // Spans pass so events keep their span context in every sink
+        if meta.is_span() {
+            return true;
+        }
+        let target = meta.target();
+        *meta.level() <= self.level
+            && (self.targets.is_empty()
+                || self.targets.iter().any(|t| Self::target_matches(target, t)))
+            && !self
+                .exclude_targets
+                .iter()
+                .any(|t| Self::target_matches(target, t))
+    }
+}
+
+impl<S> tracing_subscriber::layer::Filter<S> for SinkFilter {
+    fn enabled(
+        &self,
+        meta: &Metadata<'_>,
+        _cx: &tracing_subscriber::layer::Context<'_, S>,
+    ) -> bool {
+        self.accepts(meta)
     }
-    Ok(())
 }
 
 /// Initialize global logger (call early in main)
-/// - Supports JSON/plain format
-/// - Output: stdout/file/both
+/// - Supports JSON/plain/logfmt/ECS/OTel formats, with secrets redacted
+/// - Output: any combination of stdout/file/syslog/journald, or named sinks
+///   each with their own output, format, filters and rotation
 /// - Async file write with rotation
 pub fn init_logging(cfg: &TelemetryConfig) -> Result<LoggerHandle> {
     validate_config(cfg)?;
+    // Every output reads the global rules, install them before building layers
+    if redact::set_global(Redactor::from_config(&cfg.log_redaction)?).is_err() {
+        eprintln!("[logging] redaction rules already set, keeping the first ones");
+    }
     let default_level = cfg.log_level.to_ascii_lowercase();
     let filter =
         EnvFilter::try_new(default_level.clone()).unwrap_or_else(|_| EnvFilter::new("info"));
-    let (filter_layer, _filter_handle) = reload::Layer::new(filter);
+    let (filter_layer, filter_handle) = reload::Layer::new(filter);
+    let filter = Arc::new(FilterControl::new(filter_handle, default_level));
 
-    let keep = if cfg.log_rotation.max_files > 0 {
-        (cfg.log_rotation.max_files - 1) as usize
-    } else {
-        0
-    };
-    let max_size_bytes = (cfg.log_rotation.max_size_mb as u64) * 1024 * 1024;
-    let (file_tx, bg_handle_opt) = if cfg.log_output == "file" || cfg.log_output == "both" {
-        let (tx, rx) = mpsc::channel::<Cmd>();
-        let base = PathBuf::from(cfg.log_file.clone());
-        let compress = cfg.log_rotation.compress;
-        let bg = thread::Builder::new()
-            .name("log-rotate-writer".into())
-            .spawn(move || {
-                let mut worker =
-                    match RotatingFileWorker::new(&base, max_size_bytes, keep, compress) {
-                        Ok(w) => w,
-                        Err(e) => {
-                            eprintln!("[logging] failed to init file writer: {e}");
-                            return;
-                        }
-                    };
-                while let Ok(cmd) = rx.recv() {
-                    match cmd {
-                        Cmd::Write(buf) => {
-                            if let Err(e) = worker.write(&buf) {
-                                eprintln!("[logging] write error: {e}");
-                                thread::sleep(Duration::from_millis(5));
-                            }
-                        }
-                        Cmd::Flush => {
-                            let _ = worker.flush();
-                        }
-                        Cmd::Shutdown => {
-                            let _ = worker.flush();
-                            break;
-                        }
-                    }
-                }
-            })
-            .expect("spawn log writer thread");
-        (Some(tx), Some(bg))
+    let outputs = Outputs::parse(&cfg.log_output)?;
+    let overflow = Overflow::from_config(&cfg.log_queue.overflow)?;
+    let mut files = Vec::new();
+    let mut sink_layers = Vec::new();
+    for sink in sink_configs(cfg)? {
+        let sink_outputs = Outputs::parse(&sink.output)?;
+        let queue = if sink_outputs.file {
+            let max_bytes = (cfg.log_queue.max_size_mb > 0)
+                .then(|| cfg.log_queue.max_size_mb as usize * 1024 * 1024);
+            let queue = Arc::new(LogQueue::new(cfg.log_queue.capacity, max_bytes, overflow));
+            let writer = spawn_file_writer(
+                PathBuf::from(&sink.file),
+                Rotation::from_config(&sink.rotation)?,
+                queue.clone(),
+            )?;
+            if cfg.log_queue.report_interval_secs > 0 {
+                spawn_drop_reporter(
+                    sink.name.clone(),
+                    queue.clone(),
+                    Duration::from_secs(cfg.log_queue.report_interval_secs),
+                );
+            }
+            files.push(FileSink {
+                name: sink.name.clone(),
+                queue: queue.clone(),
+                writer: Mutex::new(Some(writer)),
+            });
+            Some(queue)
+        } else {
+            None
+        };
+        let writer = MultiWriter {
+            to_stdout: sink_outputs.stdout,
+            to_stderr: sink_outputs.stderr,
+            queue,
+        };
+        let sink_filter = SinkFilter::from_config(&sink)?;
+        let encoding = Encoding::parse(&sink.format)?;
+        let format = EventFormat::new(encoding, &cfg.log_encoding)?;
+        // Redaction happens in the formatter, before lines reach any writer
+        let layer = if encoding.json_spans() {
+            fmt::layer()
+                .fmt_fields(SpanFields::<true>)
+                .event_format(format)
+                .with_writer(writer)
+                .with_filter(sink_filter)
+                .boxed()
+        } else {
+            fmt::layer()
+                .fmt_fields(SpanFields::<false>)
+                .event_format(format)
+                .with_writer(writer)
+                .with_filter(sink_filter)
+                .boxed()
+        };
+        sink_layers.push(layer);
+    }
+
+    let forward_layer = if cfg.log_forward.enabled {
+        Some(forward::layer(&cfg.log_forward)?)
     } else {
-        (None, None)
+        None
     };
 
-    let multi_writer = MultiWriter {
-        to_stdout: cfg.log_output == "stdout" || cfg.log_output == "both",
-        file_tx: file_tx.clone(),
+    let syslog_layer = if outputs.syslog {
+        Some(SyslogLayer::new(
+            &cfg.log_syslog.address,
+            &cfg.log_syslog.facility,
+            &cfg.log_syslog.app_name,
+            &cfg.log_syslog.sd_id,
+        )?)
+    } else {
+        None
     };
-
-    let fmt_layer = if cfg.log_format == "json" {
-        base_fmt_layer!()
-            .json()
-            .flatten_event(true)
-            .with_current_span(true)
-            .with_span_list(true)
-            .with_writer(multi_writer.clone())
-            .boxed()
+    let journald_layer = if outputs.journald {
+        Some(JournaldLayer::new(
+            Path::new(&cfg.log_journald.socket),
+            &cfg.log_journald.identifier,
+        )?)
     } else {
-        base_fmt_layer!()
-            .compact()
-            .with_writer(multi_writer.clone())
-            .boxed()
+        None
     };
 
     Registry::default()
         .with(filter_layer)
-        .with(fmt_layer)
+        .with(sink_layers)
+        .with(syslog_layer)
+        .with(journald_layer)
+        .with(forward_layer)
         .try_init()
         .ok();
 
-    let bg = Arc::new(Mutex::new(bg_handle_opt));
-    let tx = file_tx.unwrap_or_else(|| {
-        let (tx, _rx) = mpsc::channel();
-        tx
-    });
-    Ok(LoggerHandle { _bg: bg, tx })
+    Ok(LoggerHandle { files, filter })
+}
+
+/// Logging config for the `run --supervised` parent: stderr plus syslog and
+/// journald when configured. Log files and forwarding belong to the worker,
+/// so two processes never write and rotate the same files.
+pub fn supervisor_config(cfg: &TelemetryConfig) -> Result<TelemetryConfig> {
+    let outputs = Outputs::parse(&cfg.log_output)?;
+    let output: Vec<&str> = [
+        (true, "stderr"),
+        (outputs.syslog, "syslog"),
+        (outputs.journald, "journald"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect();
+    let mut supervisor = cfg.clone();
+    supervisor.log_output = output.join(",");
+    supervisor.log_sinks.clear();
+    supervisor.log_forward.enabled = false;
+    Ok(supervisor)
 }
 
 /// Initialize global logger and save handle (error if already set)
@@ -345,3 +1352,606 @@ pub fn init_global_logging(cfg: &TelemetryConfig) -> Result<&'static LoggerHandl
         .map_err(|_| anyhow!("Logger already initialized"))?;
     Ok(LOGGER_HANDLE.get().expect("logger set"))
 }
+
+/// File queue counters of the global logger
+pub fn queue_stats() -> Option<QueueStats> {
+    LOGGER_HANDLE.get().and_then(|h| h.queue_stats())
+}
+
+/// File queue counters of the global logger per sink
+pub fn sink_queue_stats() -> Vec<(String, QueueStats)> {
+    LOGGER_HANDLE
+        .get()
+        .map(|h| h.sink_queue_stats())
+        .unwrap_or_default()
+}
+
+/// Global logger handle, if initialized
+pub fn global() -> Option<&'static LoggerHandle> {
+    LOGGER_HANDLE.get()
+}
+
+/// Flush the global logger, if initialized (e.g. before exit or exec)
+pub fn flush_global() {
+    if let Some(handle) = LOGGER_HANDLE.get() {
+        handle.flush(Duration::from_secs(2));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn rotation(keep: usize, compress: Option<Compression>) -> Rotation {
+        Rotation {
+            max_size: Some(15),
+            interval: None,
+            keep: Some(keep),
+            max_age: None,
+            compress,
+            on_startup: false,
+        }
+    }
+
+    fn dated(keep: usize, max_size: Option<u64>) -> Rotation {
+        Rotation {
+            max_size,
+            interval: Some(Interval::Daily),
+            keep: Some(keep),
+            max_age: None,
+            compress: None,
+            on_startup: false,
+        }
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn set_age(path: &Path, age: Duration) {
+        let mtime = std::time::SystemTime::now() - age;
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    fn read_gzip(path: &Path) -> String {
+        let mut out = String::new();
+        flate2::read::GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut out)
+            .unwrap();
+        out
+    }
+
+    fn read_zstd(path: &Path) -> String {
+        String::from_utf8(zstd::decode_all(File::open(path).unwrap()).unwrap()).unwrap()
+    }
+
+    /// Write `lines` of 10 bytes each; every write after the first rotates
+    fn write_lines(worker: &mut RotatingFileWorker, lines: &[&str]) {
+        for line in lines {
+            worker.write(format!("{line:<9}\n").as_bytes()).unwrap();
+            worker.wait_compression();
+        }
+        worker.flush().unwrap();
+    }
+
+    #[test]
+    fn numbered_rotation_shifts_compressed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("agent.log");
+        let mut worker =
+            RotatingFileWorker::new(&base, rotation(2, Some(Compression::Gzip))).unwrap();
+        write_lines(&mut worker, &["one", "two", "three", "four"]);
+
+        assert_eq!(fs::read_to_string(&base).unwrap().trim_end(), "four");
+        assert_eq!(read_gzip(&worker.suffixed(1, ".gz")).trim_end(), "three");
+        assert_eq!(read_gzip(&worker.suffixed(2, ".gz")).trim_end(), "two");
+        assert!(!worker.suffixed(3, ".gz").exists());
+        assert!(!worker.suffixed(1, "").exists());
+    }
+
+    #[test]
+    fn numbered_rotation_compresses_with_zstd() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("agent.log");
+        let mut worker =
+            RotatingFileWorker::new(&base, rotation(3, Some(Compression::Zstd))).unwrap();
+        write_lines(&mut worker, &["one", "two"]);
+        assert_eq!(read_zstd(&worker.suffixed(1, ".zst")).trim_end(), "one");
+    }
+
+    #[test]
+    fn shifting_moves_every_extension_and_retention_drops_the_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let worker =
+            RotatingFileWorker::new(dir.path().join("agent.log"), rotation(3, None)).unwrap();
+        for (n, ext) in [(1, ""), (2, ".zst"), (3, ".gz")] {
+            fs::write(worker.suffixed(n, ext), format!("{n}{ext}")).unwrap();
+        }
+        worker.shift_numbered();
+        worker.apply_retention();
+        assert_eq!(fs::read_to_string(worker.suffixed(2, "")).unwrap(), "1");
+        assert_eq!(
+            fs::read_to_string(worker.suffixed(3, ".zst")).unwrap(),
+            "2.zst"
+        );
+        assert!(!worker.suffixed(1, "").exists());
+        assert!(!worker.suffixed(3, ".gz").exists());
+        assert!(!worker.suffixed(4, ".gz").exists());
+    }
+
+    #[test]
+    fn compress_file_replaces_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("agent.log.1");
+        let dst = dir.path().join("agent.log.1.gz");
+        fs::write(&src, "rotated\n").unwrap();
+        RotatingFileWorker::compress_file(&src, &dst, Compression::Gzip).unwrap();
+        assert_eq!(read_gzip(&dst), "rotated\n");
+        assert!(!src.exists());
+        assert!(!dir.path().join("agent.log.1.gz.tmp").exists());
+
+        // A missing source leaves neither the target nor a temporary file behind
+        let missing = dir.path().join("agent.log.2");
+        let dst = dir.path().join("agent.log.2.zst");
+        assert!(RotatingFileWorker::compress_file(&missing, &dst, Compression::Zstd).is_err());
+        assert!(!dst.exists());
+        assert!(!dir.path().join("agent.log.2.zst.tmp").exists());
+    }
+
+    #[test]
+    fn parses_rotated_file_names() {
+        let stamp = |s: &str, seq| RotatedKey::Stamp(s.to_string(), seq);
+        assert_eq!(parse_rotated("1"), Some((RotatedKey::Index(1), false)));
+        assert_eq!(parse_rotated("12.gz"), Some((RotatedKey::Index(12), true)));
+        assert_eq!(
+            parse_rotated("2026-10-18"),
+            Some((stamp("2026-10-18", 0), false))
+        );
+        assert_eq!(
+            parse_rotated("2026-10-18.3"),
+            Some((stamp("2026-10-18", 3), false))
+        );
+        assert_eq!(
+            parse_rotated("2026-10-18T09.2.zst"),
+            Some((stamp("2026-10-18T09", 2), true))
+        );
+        for name in [
+            "",
+            "gz",
+            "1.gz.tmp",
+            "2026-13-01",
+            "2026-10-18T24",
+            "2026-10-18T9",
+            "2026-10-18.x",
+            "2026-1-18",
+        ] {
+            assert_eq!(parse_rotated(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn period_change_rotates_to_dated_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut worker =
+            RotatingFileWorker::new(dir.path().join("agent.log"), dated(5, None)).unwrap();
+        for line in ["first", "second"] {
+            worker.period = Some("2026-10-17".to_string());
+            worker.write(format!("{line}\n").as_bytes()).unwrap();
+        }
+        worker.flush().unwrap();
+        assert_eq!(
+            names(dir.path()),
+            [
+                "agent.log",
+                "agent.log.2026-10-17",
+                "agent.log.2026-10-17.1"
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("agent.log.2026-10-17.1")).unwrap(),
+            "first\n"
+        );
+        assert_eq!(worker.period, Some(Interval::Daily.stamp(Local::now())));
+    }
+
+    #[test]
+    fn size_rotation_within_a_period_adds_a_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut worker =
+            RotatingFileWorker::new(dir.path().join("agent.log"), dated(5, Some(15))).unwrap();
+        write_lines(&mut worker, &["one", "two", "three"]);
+        let today = Interval::Daily.stamp(Local::now());
+        assert_eq!(
+            names(dir.path()),
+            [
+                "agent.log".to_string(),
+                format!("agent.log.{today}"),
+                format!("agent.log.{today}.1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn retention_keeps_the_newest_files_and_prefers_compressed_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let worker = RotatingFileWorker::new(dir.path().join("agent.log"), dated(2, None)).unwrap();
+        for suffix in [
+            "2026-10-10",
+            "2026-10-11.gz",
+            "2026-10-12",
+            "2026-10-12.gz",
+            "2026-10-12.1",
+            "1",
+            "3.gz",
+            "unrelated",
+        ] {
+            fs::write(worker.with_suffix(suffix), suffix).unwrap();
+        }
+        worker.apply_retention();
+        assert_eq!(
+            names(dir.path()),
+            [
+                "agent.log",
+                "agent.log.1",
+                "agent.log.2026-10-12.1",
+                "agent.log.2026-10-12.gz",
+                "agent.log.unrelated",
+            ]
+        );
+    }
+
+    #[test]
+    fn retention_removes_files_older_than_max_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rotation = dated(5, None);
+        rotation.max_age = Some(Duration::from_secs(24 * 3600));
+        let worker = RotatingFileWorker::new(dir.path().join("agent.log"), rotation).unwrap();
+        for suffix in ["2026-10-16", "2026-10-17", "1"] {
+            fs::write(worker.with_suffix(suffix), suffix).unwrap();
+        }
+        set_age(
+            &worker.with_suffix("2026-10-16"),
+            Duration::from_secs(2 * 24 * 3600),
+        );
+        set_age(&worker.with_suffix("1"), Duration::from_secs(2 * 24 * 3600));
+        worker.apply_retention();
+        assert_eq!(names(dir.path()), ["agent.log", "agent.log.2026-10-17"]);
+    }
+
+    #[test]
+    fn retention_never_prunes_files_younger_than_max_age_by_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rotation = dated(1, None);
+        rotation.max_age = Some(Duration::from_secs(3 * 24 * 3600));
+        let worker = RotatingFileWorker::new(dir.path().join("agent.log"), rotation).unwrap();
+        for suffix in ["2026-10-14", "2026-10-16", "2026-10-17", "1", "2"] {
+            fs::write(worker.with_suffix(suffix), suffix).unwrap();
+        }
+        set_age(
+            &worker.with_suffix("2026-10-14"),
+            Duration::from_secs(4 * 24 * 3600),
+        );
+        worker.apply_retention();
+        assert_eq!(
+            names(dir.path()),
+            [
+                "agent.log",
+                "agent.log.1",
+                "agent.log.2",
+                "agent.log.2026-10-16",
+                "agent.log.2026-10-17"
+            ]
+        );
+    }
+
+    #[test]
+    fn unlimited_file_count_keeps_every_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("agent.log");
+        let mut unlimited = rotation(0, None);
+        unlimited.keep = None;
+        unlimited.max_age = Some(Duration::from_secs(24 * 3600));
+        let mut worker = RotatingFileWorker::new(&base, unlimited).unwrap();
+        write_lines(&mut worker, &["one", "two", "three", "four"]);
+        assert_eq!(
+            names(dir.path()),
+            ["agent.log", "agent.log.1", "agent.log.2", "agent.log.3"]
+        );
+        assert_eq!(
+            fs::read_to_string(worker.suffixed(3, ""))
+                .unwrap()
+                .trim_end(),
+            "one"
+        );
+    }
+
+    #[test]
+    fn max_files_may_be_zero_only_with_max_age() {
+        let cfg = |max_files, max_age_days| LogRotationConfig {
+            max_files,
+            max_age_days,
+            ..LogRotationConfig::default()
+        };
+        assert!(validate_rotation(&cfg(0, 0)).is_err());
+        assert!(validate_rotation(&cfg(0, 90)).is_ok());
+        assert!(validate_rotation(&cfg(7, 0)).is_ok());
+        assert_eq!(Rotation::from_config(&cfg(0, 90)).unwrap().keep, None);
+        assert_eq!(Rotation::from_config(&cfg(7, 0)).unwrap().keep, Some(6));
+    }
+
+    fn sink(name: &str, output: &str, file: &str) -> LogSinkConfig {
+        LogSinkConfig {
+            name: name.to_string(),
+            output: output.to_string(),
+            file: file.to_string(),
+            ..LogSinkConfig::default()
+        }
+    }
+
+    #[test]
+    fn validate_sinks_rejects_conflicting_or_incomplete_sinks() {
+        let ok = [
+            sink("general", "stdout", ""),
+            sink("audit", "file", "audit.log"),
+        ];
+        validate_sinks(&ok).unwrap();
+
+        let error = |sinks: &[LogSinkConfig]| format!("{:#}", validate_sinks(sinks).unwrap_err());
+        assert!(error(&[sink(" ", "stdout", "")]).contains("must not be empty"));
+        assert!(error(&[sink("a", "stdout", ""), sink("a", "stderr", "")]).contains("duplicate"));
+        assert!(error(&[sink("a", "stdout,syslog", "")]).contains("syslog and journald"));
+        assert!(error(&[sink("a", "file", "")]).contains("file required"));
+        assert!(
+            error(&[sink("a", "file", "x.log"), sink("b", "file", "x.log")])
+                .contains("used by another sink")
+        );
+        let mut bad_level = sink("a", "stdout", "");
+        bad_level.level = "loud".to_string();
+        assert!(error(&[bad_level]).contains("invalid level"));
+        let mut bad_format = sink("a", "stdout", "");
+        bad_format.format = "xml".to_string();
+        assert!(error(&[bad_format]).contains("invalid log format"));
+        let mut unbounded = sink("a", "file", "x.log");
+        unbounded.rotation.max_files = 0;
+        assert!(error(&[unbounded]).contains("max_files"));
+    }
+
+    /// Records the targets of the events a filtered layer receives
+    #[derive(Clone, Default)]
+    struct Targets(Arc<Mutex<Vec<String>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Targets {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let target = event.metadata().target().to_string();
+            self.0.lock().unwrap().push(target);
+        }
+    }
+
+    fn routed(level: &str, targets: &[&str], exclude_targets: &[&str]) -> Vec<String> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let cfg = LogSinkConfig {
+            level: level.to_string(),
+            targets: targets.iter().map(|t| t.to_string()).collect(),
+            exclude_targets: exclude_targets.iter().map(|t| t.to_string()).collect(),
+            ..LogSinkConfig::default()
+        };
+        let received = Targets::default();
+        let filter = SinkFilter::from_config(&cfg).unwrap();
+        let subscriber = tracing_subscriber::registry().with(received.clone().with_filter(filter));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "audit", "login");
+            tracing::info!(target: "audit::admin", "grant");
+            tracing::info!(target: "auditor", "scan");
+            tracing::debug!(target: "audit", "detail");
+            tracing::warn!(target: "agent::grpc", "reconnect");
+        });
+        received.0.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn sink_filter_routes_by_level_and_target_prefix() {
+        assert_eq!(
+            routed("info", &[], &[]),
+            ["audit", "audit::admin", "auditor", "agent::grpc"]
+        );
+        assert_eq!(routed("info", &["audit"], &[]), ["audit", "audit::admin"]);
+        assert_eq!(
+            routed("debug", &["audit"], &["audit::admin"]),
+            ["audit", "audit"]
+        );
+        assert_eq!(routed("warn", &[], &["audit"]), ["agent::grpc"]);
+    }
+
+    #[test]
+    fn startup_rotation_moves_existing_contents_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("agent.log");
+        fs::write(&base, "previous run\n").unwrap();
+        drop(RotatingFileWorker::new(&base, rotation(3, None)).unwrap());
+        assert_eq!(fs::read_to_string(&base).unwrap(), "previous run\n");
+
+        let mut on_startup = rotation(3, None);
+        on_startup.on_startup = true;
+        let worker = RotatingFileWorker::new(&base, on_startup).unwrap();
+        assert_eq!(worker.current_size, 0);
+        assert_eq!(
+            fs::read_to_string(worker.suffixed(1, "")).unwrap(),
+            "previous run\n"
+        );
+    }
+
+    #[test]
+    fn startup_rotates_a_file_left_from_an_earlier_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("agent.log");
+        fs::write(&base, "yesterday\n").unwrap();
+        let age = Duration::from_secs(2 * 24 * 3600);
+        set_age(&base, age);
+        let stamp = Interval::Daily.stamp(Local::now() - age);
+        let worker = RotatingFileWorker::new(&base, dated(3, None)).unwrap();
+        assert_eq!(worker.current_size, 0);
+        assert_eq!(
+            fs::read_to_string(worker.with_suffix(&stamp)).unwrap(),
+            "yesterday\n"
+        );
+    }
+
+    fn wait_for_filter(control: &FilterControl, directives: &str) -> FilterState {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let state = control.lock().clone();
+            if state.directives == directives || Instant::now() > deadline {
+                return state;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn temporary_filters_revert_unless_replaced() {
+        let (_layer, handle) = reload::Layer::<EnvFilter, Registry>::new(EnvFilter::new("info"));
+        let control = Arc::new(FilterControl::new(handle, "info".to_string()));
+
+        let state = control
+            .apply("debug", Some(Duration::from_millis(30)))
+            .unwrap();
+        assert_eq!(state.directives, "debug");
+        assert!(state.expires_at.is_some());
+        let state = wait_for_filter(&control, "info");
+        assert_eq!(state.directives, "info");
+        assert!(state.expires_at.is_none());
+
+        // A permanent change cancels the pending expiry
+        control
+            .apply("debug", Some(Duration::from_millis(30)))
+            .unwrap();
+        control.apply("trace", None).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(control.lock().directives, "trace");
+
+        // A newer TTL replaces the older deadline
+        control
+            .apply("debug", Some(Duration::from_millis(30)))
+            .unwrap();
+        control
+            .apply("warn", Some(Duration::from_millis(300)))
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(control.lock().directives, "warn");
+        assert_eq!(wait_for_filter(&control, "info").directives, "info");
+
+        assert!(control.apply("warden=nonsense", None).is_err());
+        assert_eq!(control.lock().directives, "info");
+    }
+
+    /// Pop the queued lines, as text
+    fn drain(queue: &LogQueue) -> Vec<String> {
+        let mut lines = Vec::new();
+        while queue.lock().lines > 0 {
+            if let Cmd::Write { buf, .. } = queue.pop() {
+                lines.push(String::from_utf8(buf).unwrap());
+            }
+        }
+        lines
+    }
+
+    fn push(queue: &LogQueue, line: &str) {
+        queue.push_line(line.as_bytes().to_vec(), line.starts_with("ERROR"));
+    }
+
+    #[test]
+    fn drop_newest_discards_lines_once_full() {
+        let queue = LogQueue::new(2, None, Overflow::DropNewest);
+        for line in ["a", "b", "c", "ERROR d"] {
+            push(&queue, line);
+        }
+        let stats = queue.stats();
+        assert_eq!((stats.dropped, stats.dropped_errors), (2, 1));
+        assert_eq!(drain(&queue), ["a", "b"]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_errors() {
+        let queue = LogQueue::new(2, None, Overflow::DropOldest);
+        for line in ["ERROR 1", "a", "b", "ERROR 2"] {
+            push(&queue, line);
+        }
+        assert_eq!(queue.stats().dropped, 2);
+        // Only errors queued: the new line is dropped instead
+        push(&queue, "c");
+        push(&queue, "ERROR 3");
+        let stats = queue.stats();
+        assert_eq!((stats.dropped, stats.dropped_errors), (4, 1));
+        assert_eq!(drain(&queue), ["ERROR 1", "ERROR 2"]);
+    }
+
+    #[test]
+    fn block_waits_for_the_writer_and_close_releases_producers() {
+        let queue = Arc::new(LogQueue::new(1, None, Overflow::Block));
+        push(&queue, "a");
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || push(&queue, "b"))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!producer.is_finished());
+        assert!(matches!(queue.pop(), Cmd::Write { buf, .. } if buf == b"a"));
+        producer.join().unwrap();
+        assert_eq!(drain(&queue), ["b"]);
+
+        push(&queue, "c");
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || push(&queue, "d"))
+        };
+        thread::sleep(Duration::from_millis(50));
+        queue.close();
+        producer.join().unwrap();
+        assert_eq!(queue.stats().dropped, 0);
+        assert_eq!(drain(&queue), ["c"]);
+    }
+
+    #[test]
+    fn byte_budget_bounds_the_queue() {
+        let queue = LogQueue::new(100, Some(10), Overflow::DropOldest);
+        push(&queue, "123456");
+        push(&queue, "abcdef");
+        let stats = queue.stats();
+        assert_eq!((stats.queued, stats.queued_bytes, stats.dropped), (1, 6, 1));
+        assert_eq!(drain(&queue), ["abcdef"]);
+
+        // An oversized line still fits into an empty queue
+        push(&queue, "an oversized line");
+        assert_eq!(queue.stats().queued_bytes, 17);
+        assert_eq!(drain(&queue), ["an oversized line"]);
+        assert_eq!(queue.stats().queued_bytes, 0);
+    }
+
+    #[test]
+    fn control_commands_do_not_use_capacity() {
+        let queue = LogQueue::new(1, None, Overflow::DropNewest);
+        assert!(queue.push(Cmd::Flush));
+        push(&queue, "a");
+        assert_eq!(queue.stats().dropped, 0);
+        assert!(matches!(queue.pop(), Cmd::Flush));
+        assert_eq!(drain(&queue), ["a"]);
+        queue.close();
+        assert!(!queue.push(Cmd::Flush));
+    }
+}
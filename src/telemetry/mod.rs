@@ -1,3 +1,8 @@
+pub mod format;
+pub mod forward;
+pub mod journald;
 pub mod logging;
 mod metrics;
+pub mod redact;
+pub mod syslog;
 mod tracing;
@@ -0,0 +1,119 @@
+//! Redaction of secrets in log records.
+//!
+//! A [`Redactor`] replaces the whole value of deny-listed fields (matched
+//! case-insensitively by name, or by a `_`/`.` separated suffix, so `token`
+//! also covers `auth_token`) and every match of the configured regexes in
+//! messages and other string values. A pattern with a capture group only
+//! replaces the first group, which keeps context such as `password=`.
+//!
+//! The global redactor is installed by `init_logging` and applied by every
+//! output: the stdout/file formatter, syslog, journald and log forwarding.
+
+pub use crate::config::schema::RedactionConfig;
+use anyhow::{Result, anyhow};
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use std::borrow::Cow;
+
+static GLOBAL: OnceCell<Redactor> = OnceCell::new();
+
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    fields: Vec<String>,
+    patterns: Vec<Regex>,
+    replacement: String,
+}
+
+impl Redactor {
+    pub fn new(fields: &[String], patterns: &[String], replacement: &str) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| anyhow!("invalid redaction pattern {p:?}: {e}")))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            fields: fields.iter().map(|f| f.to_ascii_lowercase()).collect(),
+            patterns,
+            replacement: replacement.to_string(),
+        })
+    }
+
+    /// 未启用时返回不做任何替换的实例
+    pub fn from_config(cfg: &RedactionConfig) -> Result<Self> {
+        if !cfg.enabled {
+            return Ok(Self::default());
+        }
+        Self::new(&cfg.fields, &cfg.patterns, &cfg.replacement)
+    }
+
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    /// 字段名是否在拒绝列表中
+    pub fn is_denied(&self, field: &str) -> bool {
+        let field = field.to_ascii_lowercase();
+        self.fields.iter().any(|denied| {
+            field == *denied
+                || field
+                    .strip_suffix(denied.as_str())
+                    .is_some_and(|prefix| prefix.ends_with(['_', '.']))
+        })
+    }
+
+    /// 对文本应用所有正则规则
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if !pattern.is_match(&text) {
+                continue;
+            }
+            let replaced = pattern.replace_all(&text, |caps: &regex::Captures<'_>| {
+                let whole = caps.get(0).map(|m| m.as_str()).unwrap_or_default();
+                match caps.get(1) {
+                    Some(group) => {
+                        let start = group.start() - caps.get(0).map_or(0, |m| m.start());
+                        format!(
+                            "{}{}{}",
+                            &whole[..start],
+                            self.replacement,
+                            &whole[start + group.len()..]
+                        )
+                    }
+                    None => self.replacement.clone(),
+                }
+            });
+            text = Cow::Owned(replaced.into_owned());
+        }
+        text
+    }
+
+    /// 字段值：拒绝列表中的字段整体替换，其余应用正则规则
+    pub fn field<'a>(&self, name: &str, value: &'a str) -> Cow<'a, str> {
+        if self.is_denied(name) {
+            Cow::Owned(self.replacement.clone())
+        } else {
+            self.redact(value)
+        }
+    }
+}
+
+/// 安装全局规则；只能设置一次
+pub fn set_global(redactor: Redactor) -> Result<()> {
+    GLOBAL
+        .set(redactor)
+        .map_err(|_| anyhow!("log redaction already initialized"))
+}
+
+/// 测试中以默认配置安装全局规则，可重复调用
+#[cfg(test)]
+pub(crate) fn init_for_tests() {
+    let _ = GLOBAL.get_or_try_init(|| Redactor::from_config(&RedactionConfig::default()));
+}
+
+/// 全局规则，未初始化时不做替换
+pub fn global() -> &'static Redactor {
+    static NONE: OnceCell<Redactor> = OnceCell::new();
+    GLOBAL
+        .get()
+        .unwrap_or_else(|| NONE.get_or_init(Redactor::default))
+}
@@ -0,0 +1,333 @@
+//! Syslog output (RFC 5424).
+//!
+//! Records are formatted as `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID - [SD] MSG`
+//! with event fields and the target as structured data under a configurable
+//! SD-ID (`name@<private enterprise number>`), and sent to a Unix datagram
+//! socket (`unix:///dev/log`, unix only), UDP (`udp://host:514`) or TCP
+//! (`tcp://host:601`, octet-counted framing per RFC 6587). Sending happens on
+//! a background thread behind a bounded queue; records are dropped when it is
+//! full. After a send failure the sender reconnects and retries the record once.
+
+use crate::telemetry::redact;
+use anyhow::{Result, anyhow};
+use chrono::{SecondsFormat, Utc};
+use std::fmt::{Debug, Write as _};
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// 发送队列容量
+const QUEUE_CAPACITY: usize = 1024;
+
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// syslog 服务地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Unix(PathBuf),
+    Udp(String),
+    Tcp(String),
+}
+
+impl Address {
+    pub fn parse(address: &str) -> Result<Self> {
+        if let Some(path) = address.strip_prefix("unix://") {
+            if !cfg!(unix) {
+                return Err(anyhow!(
+                    "unix:// syslog addresses are only supported on unix"
+                ));
+            }
+            Ok(Address::Unix(PathBuf::from(path)))
+        } else if let Some(addr) = address.strip_prefix("udp://") {
+            Ok(Address::Udp(addr.to_string()))
+        } else if let Some(addr) = address.strip_prefix("tcp://") {
+            Ok(Address::Tcp(addr.to_string()))
+        } else {
+            Err(anyhow!(
+                "invalid syslog address {address:?}, expected unix://, udp:// or tcp://"
+            ))
+        }
+    }
+}
+
+/// facility 名称对应的编号
+pub fn parse_facility(name: &str) -> Result<u8> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        other => return Err(anyhow!("invalid syslog facility: {other}")),
+    };
+    Ok(code)
+}
+
+/// 校验 SD-ID：未在 IANA 注册的 SD-ID 须为 name@<企业号>，
+/// 最长 32 个可见 ASCII 字符，不含 '='、' '、']'、'"'
+pub fn parse_sd_id(id: &str) -> Result<()> {
+    let valid_chars = id
+        .chars()
+        .all(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'));
+    let valid_form = match id.split_once('@') {
+        Some((name, pen)) => {
+            !name.is_empty()
+                && !pen.is_empty()
+                && pen.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && !pen.starts_with('.')
+                && !pen.ends_with('.')
+        }
+        None => false,
+    };
+    if id.len() > 32 || !valid_chars || !valid_form {
+        return Err(anyhow!(
+            "invalid syslog sd_id {id:?}, expected name@<private enterprise number>"
+        ));
+    }
+    Ok(())
+}
+
+/// tracing 级别对应的 syslog severity
+pub fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// 发往 syslog 的 tracing 层
+pub struct SyslogLayer {
+    facility: u8,
+    hostname: String,
+    app_name: String,
+    sd_id: String,
+    tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl SyslogLayer {
+    pub fn new(address: &str, facility: &str, app_name: &str, sd_id: &str) -> Result<Self> {
+        let address = Address::parse(address)?;
+        let facility = parse_facility(facility)?;
+        parse_sd_id(sd_id)?;
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        thread::Builder::new()
+            .name("log-syslog".into())
+            .spawn(move || send_loop(address, rx))
+            .map_err(|e| anyhow!("failed to spawn syslog sender: {e}"))?;
+        Ok(Self {
+            facility,
+            hostname: crate::utils::host::facts()
+                .hostname
+                .clone()
+                .map(|h| header_field(&h, 255))
+                .unwrap_or_else(|| "-".to_string()),
+            app_name: header_field(app_name, 48),
+            sd_id: sd_id.to_string(),
+            tx,
+        })
+    }
+}
+
+/// 头部字段只允许可见 ASCII，空值用 "-"
+fn header_field(value: &str, max: usize) -> String {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max)
+        .collect();
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+/// PARAM-NAME 不能含 '='、' '、']'、'"'，最长 32 字符
+fn param_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect()
+}
+
+/// PARAM-VALUE 中的 '"'、'\\'、']' 需要转义
+fn escape_param_value(value: &str, out: &mut String) {
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let redactor = redact::global();
+        if field.name() == "message" {
+            self.message = redactor.redact(value).into_owned();
+        } else {
+            self.fields.push((
+                field.name().to_string(),
+                redactor.field(field.name(), value).into_owned(),
+            ));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+impl SyslogLayer {
+    fn format(&self, event: &Event<'_>) -> Vec<u8> {
+        let meta = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let pri = self.facility * 8 + severity(meta.level());
+        let mut line = format!(
+            "<{pri}>1 {} {} {} {} - [{} target=\"",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.hostname,
+            self.app_name,
+            std::process::id(),
+            self.sd_id,
+        );
+        escape_param_value(meta.target(), &mut line);
+        line.push('"');
+        for (name, value) in &visitor.fields {
+            let name = param_name(name);
+            if name.is_empty() {
+                continue;
+            }
+            let _ = write!(line, " {name}=\"");
+            escape_param_value(value, &mut line);
+            line.push('"');
+        }
+        line.push_str("] ");
+        line.push_str(&visitor.message);
+        line.into_bytes()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SyslogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // 队列满或发送线程退出时丢弃
+        let _ = self.tx.try_send(self.format(event));
+    }
+}
+
+enum Transport {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+fn resolve(addr: &str) -> std::io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::other(format!("cannot resolve {addr}")))
+}
+
+fn connect(address: &Address) -> std::io::Result<Transport> {
+    match address {
+        #[cfg(unix)]
+        Address::Unix(path) => {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(path)?;
+            Ok(Transport::Unix(socket))
+        }
+        // 解析时已拒绝
+        #[cfg(not(unix))]
+        Address::Unix(_) => Err(std::io::ErrorKind::Unsupported.into()),
+        Address::Udp(addr) => {
+            let addr = resolve(addr)?;
+            let local = if addr.is_ipv6() {
+                "[::]:0"
+            } else {
+                "0.0.0.0:0"
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(addr)?;
+            Ok(Transport::Udp(socket))
+        }
+        Address::Tcp(addr) => Ok(Transport::Tcp(TcpStream::connect_timeout(
+            &resolve(addr)?,
+            TCP_CONNECT_TIMEOUT,
+        )?)),
+    }
+}
+
+fn send(transport: &mut Transport, msg: &[u8]) -> std::io::Result<()> {
+    match transport {
+        #[cfg(unix)]
+        Transport::Unix(socket) => socket.send(msg).map(|_| ()),
+        Transport::Udp(socket) => socket.send(msg).map(|_| ()),
+        Transport::Tcp(stream) => {
+            stream.write_all(format!("{} ", msg.len()).as_bytes())?;
+            stream.write_all(msg)
+        }
+    }
+}
+
+fn send_loop(address: Address, rx: mpsc::Receiver<Vec<u8>>) {
+    let mut transport = None;
+    let mut reported = false;
+    while let Ok(msg) = rx.recv() {
+        // 连接断开后重连一次再发送
+        let mut result = Err(std::io::Error::other("not connected"));
+        for _ in 0..2 {
+            if transport.is_none() {
+                transport = connect(&address).ok();
+            }
+            let Some(current) = transport.as_mut() else {
+                continue;
+            };
+            result = send(current, &msg);
+            if result.is_ok() {
+                break;
+            }
+            transport = None;
+        }
+        match result {
+            Ok(()) => reported = false,
+            // 不能通过 tracing 报告，否则会再次进入本层
+            Err(e) if !reported => {
+                eprintln!("[logging] syslog send to {address:?} failed: {e}");
+                reported = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
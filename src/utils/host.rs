@@ -0,0 +1,46 @@
+//! Host facts used for config templating and agent labels.
+
+use once_cell::sync::Lazy;
+use std::fs;
+
+const MACHINE_ID_PATHS: &[&str] = &["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// 主机信息，进程内只采集一次
+#[derive(Debug, Clone, Default)]
+pub struct HostFacts {
+    pub hostname: Option<String>,
+    pub ip: Option<String>,
+    pub machine_id: Option<String>,
+    pub os: String,
+    pub arch: String,
+}
+
+static FACTS: Lazy<HostFacts> = Lazy::new(|| HostFacts {
+    hostname: super::net::hostname(),
+    ip: super::net::primary_ip().map(|ip| ip.to_string()),
+    machine_id: machine_id(),
+    os: std::env::consts::OS.to_string(),
+    arch: std::env::consts::ARCH.to_string(),
+});
+
+pub fn facts() -> &'static HostFacts {
+    &FACTS
+}
+
+/// 读取系统 machine-id（systemd / dbus）
+pub fn machine_id() -> Option<String> {
+    MACHINE_ID_PATHS.iter().find_map(|p| {
+        fs::read_to_string(p)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    })
+}
+
+/// 读取 DMI 系统 UUID；虚拟机克隆后通常由虚拟化平台重新分配，需 root 权限
+pub fn product_uuid() -> Option<String> {
+    fs::read_to_string("/sys/class/dmi/id/product_uuid")
+        .ok()
+        .map(|s| s.trim().to_ascii_lowercase())
+        .filter(|s| !s.is_empty())
+}
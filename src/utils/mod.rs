@@ -1,4 +1,5 @@
 mod compression;
 mod fs;
+pub mod host;
 mod net;
-mod time;
+pub mod time;
@@ -1 +1,9 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 
+/// 当前 Unix 时间戳，单位毫秒
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
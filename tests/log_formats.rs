@@ -0,0 +1,174 @@
+use chrono::{TimeZone as _, Utc};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::layer::SubscriberExt;
+use warden::format::{Encoding, EventFormat, LogEncodingConfig, SpanFields, TimeZone};
+
+/// 收集格式化结果的 writer
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn render(encoding: Encoding, cfg: &LogEncodingConfig) -> String {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let layer = tracing_subscriber::fmt::layer()
+        .fmt_fields(SpanFields::<true>)
+        .event_format(EventFormat::new(encoding, cfg).unwrap())
+        .with_writer(move || writer.clone());
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", id = 7);
+        let _entered = span.enter();
+        tracing::warn!(user = "bob", note = "say \"hi\"", "login failed");
+    });
+    let out = buffer.0.lock().unwrap().clone();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn logfmt_quotes_values_that_need_it() {
+    let line = render(Encoding::Logfmt, &LogEncodingConfig::default());
+    assert!(line.starts_with("ts="), "{line}");
+    assert!(
+        line.contains(
+            r#" level=warn msg="login failed" user=bob note="say \"hi\"" target=log_formats "#
+        ),
+        "{line}"
+    );
+    assert!(line.contains(" span=req id=7"), "{line}");
+}
+
+#[test]
+fn ecs_and_otel_use_their_field_names() {
+    let ecs: serde_json::Value =
+        serde_json::from_str(&render(Encoding::Ecs, &LogEncodingConfig::default())).unwrap();
+    assert_eq!(ecs["log.level"], "warn");
+    assert_eq!(ecs["message"], "login failed");
+    assert_eq!(ecs["log.logger"], "log_formats");
+    assert_eq!(ecs["id"], 7);
+    assert!(ecs["@timestamp"].as_str().unwrap().ends_with('Z'));
+
+    let otel: serde_json::Value =
+        serde_json::from_str(&render(Encoding::Otel, &LogEncodingConfig::default())).unwrap();
+    assert_eq!(otel["SeverityText"], "WARN");
+    assert_eq!(otel["SeverityNumber"], 13);
+    assert_eq!(otel["Body"], "login failed");
+    assert_eq!(otel["Attributes"]["user"], "bob");
+    assert_eq!(otel["Attributes"]["code.namespace"], "log_formats");
+    assert_eq!(otel["Resource"]["service.name"], "warden");
+}
+
+#[test]
+fn field_names_and_timezone_are_configurable() {
+    let cfg = LogEncodingConfig {
+        timezone: "+08:00".to_string(),
+        timestamp_field: "time".to_string(),
+        level_field: "severity".to_string(),
+        message_field: "msg".to_string(),
+    };
+    let json: serde_json::Value = serde_json::from_str(&render(Encoding::Json, &cfg)).unwrap();
+    assert_eq!(json["severity"], "WARN");
+    assert_eq!(json["msg"], "login failed");
+    assert!(json["time"].as_str().unwrap().ends_with("+08:00"));
+}
+
+#[test]
+fn timestamps_are_rfc3339_with_nanoseconds() {
+    let time = Utc.timestamp_opt(1_760_000_000, 123_456_789).unwrap();
+    assert_eq!(
+        TimeZone::parse("UTC").unwrap().format(time),
+        "2025-10-09T08:53:20.123456789Z"
+    );
+    assert_eq!(
+        TimeZone::parse("Asia/Shanghai").unwrap().format(time),
+        "2025-10-09T16:53:20.123456789+08:00"
+    );
+    assert_eq!(
+        TimeZone::parse("-05:30").unwrap().format(time),
+        "2025-10-09T03:23:20.123456789-05:30"
+    );
+}
+
+#[test]
+fn rejects_unknown_encoding_and_timezone() {
+    assert!(Encoding::parse("xml").is_err());
+    assert!(TimeZone::parse("Mars/Olympus").is_err());
+    assert!(TimeZone::parse("+25:00").is_err());
+}
+
+fn render_colliding(encoding: Encoding) -> String {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let layer = tracing_subscriber::fmt::layer()
+        .fmt_fields(SpanFields::<true>)
+        .event_format(EventFormat::new(encoding, &LogEncodingConfig::default()).unwrap())
+        .with_writer(move || writer.clone());
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", line = 1, name = "inner", "span.name" = "outer");
+        let _entered = span.enter();
+        tracing::warn!(
+            level = "custom",
+            timestamp = 0,
+            target = "elsewhere",
+            "@timestamp" = "never",
+            "code.lineno" = 0,
+            user = "bob",
+            "login failed"
+        );
+    });
+    let out = buffer.0.lock().unwrap().clone();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn colliding_field_names_do_not_replace_core_fields() {
+    let json: serde_json::Value = serde_json::from_str(&render_colliding(Encoding::Json)).unwrap();
+    assert_eq!(json["level"], "WARN");
+    assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(json["target"], "log_formats");
+    assert_eq!(json["fields.level"], "custom");
+    assert_eq!(json["fields.timestamp"], 0);
+    assert_eq!(json["fields.target"], "elsewhere");
+    assert_eq!(json["@timestamp"], "never");
+    assert_eq!(json["user"], "bob");
+    assert_eq!(json["span"]["name"], "req");
+    assert_eq!(json["span"]["fields.name"], "inner");
+
+    let line = render_colliding(Encoding::Logfmt);
+    assert!(line.contains(" level=warn "), "{line}");
+    assert!(line.contains(" fields.level=custom "), "{line}");
+    assert!(line.contains(" target=log_formats "), "{line}");
+    assert!(line.contains(" fields.target=elsewhere "), "{line}");
+    assert!(line.contains(" fields.line=1"), "{line}");
+    assert!(!line.contains(" level=custom"), "{line}");
+
+    let ecs: serde_json::Value = serde_json::from_str(&render_colliding(Encoding::Ecs)).unwrap();
+    assert!(ecs["@timestamp"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(ecs["fields.@timestamp"], "never");
+    assert_eq!(ecs["log.level"], "warn");
+    assert_eq!(ecs["level"], "custom");
+    assert_eq!(ecs["log.logger"], "log_formats");
+    assert_eq!(ecs["span.name"], "req");
+    assert_eq!(ecs["fields.span.name"], "outer");
+
+    let otel: serde_json::Value = serde_json::from_str(&render_colliding(Encoding::Otel)).unwrap();
+    let attributes = &otel["Attributes"];
+    assert_eq!(attributes["code.namespace"], "log_formats");
+    assert!(attributes["code.lineno"].as_u64().unwrap() > 0);
+    assert_eq!(attributes["fields.code.lineno"], 0);
+    assert_eq!(attributes["span.name"], "req");
+    assert_eq!(attributes["fields.span.name"], "outer");
+    assert_eq!(attributes["name"], "inner");
+}
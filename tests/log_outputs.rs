@@ -0,0 +1,177 @@
+use std::io::Read;
+use std::net::{TcpListener, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tracing_subscriber::layer::SubscriberExt;
+use warden::syslog::SyslogLayer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn emit<L>(layer: L)
+where
+    L: tracing_subscriber::Layer<tracing_subscriber::Registry> + Send + Sync + 'static,
+{
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(cmd_id = "c1", path = "a]b\"c", "disk almost full");
+    });
+}
+
+/// 校验 RFC 5424 消息：daemon(3) * 8 + warning(4) = 28
+fn assert_syslog_message(msg: &str) {
+    assert!(msg.starts_with("<28>1 "), "{msg}");
+    let fields: Vec<&str> = msg.splitn(7, ' ').collect();
+    assert_eq!(fields[3], "warden-test");
+    assert_eq!(fields[4], std::process::id().to_string());
+    assert_eq!(fields[5], "-");
+    assert!(
+        fields[6].starts_with("[warden@32473.1 target=\"log_outputs\" cmd_id=\"c1\""),
+        "{msg}"
+    );
+    assert!(msg.contains(r#"path="a\]b\"c""#), "{msg}");
+    assert!(msg.ends_with("] disk almost full"), "{msg}");
+}
+
+#[cfg(unix)]
+#[test]
+fn syslog_over_unix_datagram() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log.sock");
+    let server = UnixDatagram::bind(&path).unwrap();
+    server.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let address = format!("unix://{}", path.display());
+    emit(SyslogLayer::new(&address, "daemon", "warden-test", "warden@32473.1").unwrap());
+    let mut buf = [0u8; 4096];
+    let n = server.recv(&mut buf).unwrap();
+    assert_syslog_message(std::str::from_utf8(&buf[..n]).unwrap());
+}
+
+#[test]
+fn syslog_over_udp() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let address = format!("udp://{}", server.local_addr().unwrap());
+    emit(SyslogLayer::new(&address, "daemon", "warden-test", "warden@32473.1").unwrap());
+    let mut buf = [0u8; 4096];
+    let n = server.recv(&mut buf).unwrap();
+    assert_syslog_message(std::str::from_utf8(&buf[..n]).unwrap());
+}
+
+#[test]
+fn syslog_over_tcp_uses_octet_counting() {
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("tcp://{}", server.local_addr().unwrap());
+    emit(SyslogLayer::new(&address, "daemon", "warden-test", "warden@32473.1").unwrap());
+
+    let (mut stream, _) = server.accept().unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    // 读到一个完整的帧为止："<长度> <消息>"
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed early");
+        received.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&received).to_string();
+        if let Some((len, rest)) = text.split_once(' ')
+            && rest.len() >= len.parse::<usize>().unwrap()
+        {
+            assert_eq!(rest.len(), len.parse::<usize>().unwrap());
+            assert_syslog_message(rest);
+            break;
+        }
+    }
+}
+
+#[test]
+fn rejects_unknown_syslog_address_and_facility() {
+    let layer = |address, facility, sd_id| SyslogLayer::new(address, facility, "warden", sd_id);
+    assert!(layer("http://localhost", "daemon", "fields@32473").is_err());
+    assert!(layer("udp://127.0.0.1:514", "nope", "fields@32473").is_err());
+}
+
+#[test]
+fn rejects_sd_ids_without_an_enterprise_number() {
+    for sd_id in ["fields@32473", "warden@32473.1"] {
+        assert!(warden::syslog::parse_sd_id(sd_id).is_ok(), "{sd_id}");
+    }
+    for sd_id in [
+        "fields",
+        "fields@",
+        "@32473",
+        "fields@example",
+        "fields@32473.",
+        "my fields@32473",
+        "fields=x@32473",
+        "a_very_long_structured_data_id@32473",
+    ] {
+        assert!(warden::syslog::parse_sd_id(sd_id).is_err(), "{sd_id}");
+    }
+}
+
+/// 解析 journald 原生协议的数据报
+#[cfg(unix)]
+fn parse_journal(mut data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let line_end = data.iter().position(|&b| b == b'\n').unwrap();
+        let line = &data[..line_end];
+        if let Some(eq) = line.iter().position(|&b| b == b'=') {
+            fields.push((
+                String::from_utf8(line[..eq].to_vec()).unwrap(),
+                line[eq + 1..].to_vec(),
+            ));
+            data = &data[line_end + 1..];
+        } else {
+            let name = String::from_utf8(line.to_vec()).unwrap();
+            let rest = &data[line_end + 1..];
+            let len = u64::from_le_bytes(rest[..8].try_into().unwrap()) as usize;
+            fields.push((name, rest[8..8 + len].to_vec()));
+            assert_eq!(rest[8 + len], b'\n');
+            data = &rest[9 + len..];
+        }
+    }
+    fields
+}
+
+#[cfg(unix)]
+#[test]
+fn journald_maps_fields_and_encodes_multiline_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("journal.sock");
+    let server = UnixDatagram::bind(&path).unwrap();
+    server.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let layer = warden::journald::JournaldLayer::new(&path, "warden-test").unwrap();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!(cmd_id = "c1", attempts = 3, "first line\nsecond line");
+    });
+    let mut buf = [0u8; 4096];
+    let n = server.recv(&mut buf).unwrap();
+    let fields = parse_journal(&buf[..n]);
+    let get = |name: &str| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| String::from_utf8(v.clone()).unwrap())
+    };
+    assert_eq!(get("MESSAGE").as_deref(), Some("first line\nsecond line"));
+    assert_eq!(get("PRIORITY").as_deref(), Some("3"));
+    assert_eq!(get("SYSLOG_IDENTIFIER").as_deref(), Some("warden-test"));
+    assert_eq!(get("TARGET").as_deref(), Some("log_outputs"));
+    assert_eq!(get("CMD_ID").as_deref(), Some("c1"));
+    assert_eq!(get("ATTEMPTS").as_deref(), Some("3"));
+    assert!(get("CODE_LINE").is_some());
+}
+
+#[test]
+fn journald_field_names_are_sanitized() {
+    assert_eq!(warden::journald::field_name("cmd_id"), "CMD_ID");
+    assert_eq!(warden::journald::field_name("http.status"), "HTTP_STATUS");
+    assert_eq!(warden::journald::field_name("_private"), "F__PRIVATE");
+    assert_eq!(warden::journald::field_name("2xx"), "F_2XX");
+}
@@ -1 +1,283 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+use std::fs;
+use warden::{bundle, updater};
 
+fn signing_key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+fn public_key(key: &SigningKey) -> String {
+    BASE64.encode(key.verifying_key().as_bytes())
+}
+
+/// 返回 (sha256 hex, base64 签名)
+fn sign(key: &SigningKey, data: &[u8]) -> (String, String) {
+    let digest = Sha256::digest(data);
+    let signature = key.sign(&digest);
+    (hex::encode(digest), BASE64.encode(signature.to_bytes()))
+}
+
+/// 按 agent.update 的格式签名清单，返回 (manifest, signature)
+fn signed_manifest(
+    key: &SigningKey,
+    version: &str,
+    channel: &str,
+    binary: &[u8],
+) -> (String, String) {
+    let manifest = serde_json::json!({
+        "version": version,
+        "channel": channel,
+        "sha256": hex::encode(Sha256::digest(binary)),
+    })
+    .to_string();
+    let (_, signature) = sign(key, manifest.as_bytes());
+    (manifest, signature)
+}
+
+#[test]
+fn accepts_manifest_signed_by_trusted_key() {
+    let key = signing_key(1);
+    let (manifest, signature) = signed_manifest(&key, "9.9.9", "beta", b"new agent binary");
+    let trusted = vec![public_key(&signing_key(9)), public_key(&key)];
+    let manifest = updater::verify_manifest(&manifest, &signature, &trusted).unwrap();
+    assert_eq!(manifest.version, "9.9.9");
+    assert_eq!(manifest.channel, "beta");
+    updater::check_sha256(&Sha256::digest(b"new agent binary"), &manifest.sha256).unwrap();
+}
+
+#[test]
+fn rejects_tampered_binary() {
+    let key = signing_key(1);
+    let (manifest, signature) = signed_manifest(&key, "9.9.9", "stable", b"new agent binary");
+    let manifest = updater::verify_manifest(&manifest, &signature, &[public_key(&key)]).unwrap();
+    let err =
+        updater::check_sha256(&Sha256::digest(b"evil agent binary"), &manifest.sha256).unwrap_err();
+    assert!(format!("{err:#}").contains("sha256 mismatch"));
+}
+
+#[test]
+fn rejects_tampered_version() {
+    let key = signing_key(1);
+    let (manifest, signature) = signed_manifest(&key, "1.0.0", "stable", b"old agent binary");
+    // 把旧版本的构建重新标成新版本以绕过降级检查
+    let relabelled = manifest.replace("1.0.0", "9.9.9");
+    let err = updater::verify_manifest(&relabelled, &signature, &[public_key(&key)]).unwrap_err();
+    assert!(format!("{err:#}").contains("signature rejected"));
+}
+
+#[test]
+fn rejects_tampered_channel() {
+    let key = signing_key(1);
+    let (manifest, signature) = signed_manifest(&key, "9.9.9", "beta", b"beta agent binary");
+    let relabelled = manifest.replace("beta", "stable");
+    let err = updater::verify_manifest(&relabelled, &signature, &[public_key(&key)]).unwrap_err();
+    assert!(format!("{err:#}").contains("signature rejected"));
+}
+
+#[test]
+fn rejects_signature_from_untrusted_key() {
+    let (manifest, signature) = signed_manifest(&signing_key(2), "9.9.9", "stable", b"binary");
+    let err = updater::verify_manifest(&manifest, &signature, &[public_key(&signing_key(1))])
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("does not match any trusted key"));
+}
+
+#[test]
+fn rejects_updates_without_trusted_keys() {
+    let (manifest, signature) = signed_manifest(&signing_key(1), "9.9.9", "stable", b"binary");
+    assert!(updater::verify_manifest(&manifest, &signature, &[]).is_err());
+}
+
+#[test]
+fn install_keeps_previous_binary_and_rollback_restores_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("warden");
+    fs::write(&target, b"v1").unwrap();
+
+    let staged = updater::stage(b"v2", &target).unwrap();
+    let prev = updater::install(&staged, &target).unwrap();
+    assert_eq!(prev, updater::previous_path(&target));
+    assert_eq!(fs::read(&target).unwrap(), b"v2");
+    assert_eq!(fs::read(&prev).unwrap(), b"v1");
+    assert!(!staged.exists());
+
+    updater::rollback(&target).unwrap();
+    assert_eq!(fs::read(&target).unwrap(), b"v1");
+    assert!(!prev.exists());
+}
+
+#[test]
+fn repeated_install_replaces_stale_previous_binary() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("warden");
+    fs::write(&target, b"v1").unwrap();
+
+    let staged = updater::stage(b"v2", &target).unwrap();
+    updater::install(&staged, &target).unwrap();
+    let staged = updater::stage(b"v3", &target).unwrap();
+    let prev = updater::install(&staged, &target).unwrap();
+    assert_eq!(fs::read(&target).unwrap(), b"v3");
+    assert_eq!(fs::read(&prev).unwrap(), b"v2");
+}
+
+#[test]
+fn rollback_without_previous_binary_leaves_target_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("warden");
+    fs::write(&target, b"v1").unwrap();
+
+    assert!(updater::rollback(&target).is_err());
+    assert_eq!(fs::read(&target).unwrap(), b"v1");
+}
+
+/// 构造 bundle：manifest 由 key 签名，files 为 bundle 内的 (路径, 内容)
+fn build_bundle(
+    dir: &std::path::Path,
+    key: &SigningKey,
+    manifest: &serde_json::Value,
+    files: &[(&str, &[u8])],
+) -> std::path::PathBuf {
+    let path = dir.join("bundle.tar");
+    let manifest = serde_json::to_vec(manifest).unwrap();
+    let (_, signature) = sign(key, &manifest);
+    let mut builder = tar::Builder::new(fs::File::create(&path).unwrap());
+    let entries = [
+        ("manifest.json", manifest.as_slice()),
+        ("manifest.sig", signature.as_bytes()),
+    ];
+    for (name, data) in entries.iter().chain(files) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data).unwrap();
+    }
+    builder.finish().unwrap();
+    path
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+#[test]
+fn opens_bundle_signed_by_trusted_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = signing_key(1);
+    let manifest = serde_json::json!({
+        "agent": { "version": "9.9.9", "file": "warden", "sha256": sha256_hex(b"v9") },
+        "plugins": [{ "name": "p", "version": "1", "entry": "p.wasm", "sha256": sha256_hex(b"plugin") }],
+    });
+    let path = build_bundle(
+        dir.path(),
+        &key,
+        &manifest,
+        &[("warden", b"v9"), ("plugins/p.wasm", b"plugin")],
+    );
+    let bundle = bundle::open(&path, &[public_key(&key)]).unwrap();
+    assert_eq!(bundle.manifest.agent.unwrap().version, "9.9.9");
+    assert_eq!(bundle.manifest.plugins.len(), 1);
+}
+
+#[test]
+fn rejects_bundle_with_tampered_plugin() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = signing_key(1);
+    let manifest = serde_json::json!({
+        "plugins": [{ "name": "p", "version": "1", "entry": "p.wasm", "sha256": sha256_hex(b"plugin") }],
+    });
+    let path = build_bundle(dir.path(), &key, &manifest, &[("plugins/p.wasm", b"evil")]);
+    let err = bundle::open(&path, &[public_key(&key)]).err().unwrap();
+    assert!(format!("{err:#}").contains("sha256 mismatch for plugins/p.wasm"));
+}
+
+#[test]
+fn rejects_oversized_bundle_entry_before_reading_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bundle.tar");
+    // 只写入声明了 1 GB 大小的头部，不附带数据
+    let mut header = tar::Header::new_gnu();
+    header.set_path("warden").unwrap();
+    header.set_size(1 << 30);
+    header.set_mode(0o644);
+    header.set_cksum();
+    fs::write(&path, header.as_bytes()).unwrap();
+    let err = bundle::open(&path, &[public_key(&signing_key(1))])
+        .err()
+        .unwrap();
+    assert!(format!("{err:#}").contains("warden in bundle exceeds"));
+}
+
+#[test]
+fn rejects_bundle_signed_by_untrusted_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let manifest = serde_json::json!({
+        "plugins": [{ "name": "p", "version": "1", "entry": "p.wasm", "sha256": sha256_hex(b"plugin") }],
+    });
+    let path = build_bundle(
+        dir.path(),
+        &signing_key(2),
+        &manifest,
+        &[("plugins/p.wasm", b"plugin")],
+    );
+    let err = bundle::open(&path, &[public_key(&signing_key(1))])
+        .err()
+        .unwrap();
+    assert!(format!("{err:#}").contains("does not match any trusted key"));
+}
+
+/// 不重复的伪随机内容，补丁中的匹配只能指向基准中的确定位置
+fn pseudo_binary(len: usize) -> Vec<u8> {
+    let mut x: u32 = 0x1234_5678;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+fn make_delta(base: &[u8], new: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 19, base).unwrap();
+    encoder.write_all(new).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn delta_patch_reconstructs_new_binary() {
+    let base: Vec<u8> = (0..200_000u32)
+        .flat_map(|i| (i % 251).to_le_bytes())
+        .collect();
+    let mut new = base.clone();
+    new[1000..1010].copy_from_slice(b"new agent!");
+    new.extend_from_slice(b"appended section");
+    let patch = make_delta(&base, &new);
+    assert!(patch.len() < new.len() / 10);
+    let window_log = updater::delta_window_log(base.len() as u64, 32).unwrap();
+    let mut data = Vec::new();
+    updater::apply_delta(&base, &patch[..], &mut data, window_log).unwrap();
+    assert_eq!(data, new);
+}
+
+#[test]
+fn delta_patch_against_wrong_base_fails_verification() {
+    let base = pseudo_binary(200_000);
+    let mut new = base.clone();
+    new[10..20].copy_from_slice(b"new agent!");
+    let sha256 = hex::encode(Sha256::digest(&new));
+    let patch = make_delta(&base, &new);
+
+    let mut other = base.clone();
+    other[30_000..30_010].copy_from_slice(b"other base");
+    // 基准不同时还原结果要么出错，要么无法通过清单中的摘要校验
+    let mut data = Vec::new();
+    if updater::apply_delta(&other, &patch[..], &mut data, 31).is_ok() {
+        assert!(updater::check_sha256(&Sha256::digest(&data), &sha256).is_err());
+    }
+}